@@ -0,0 +1,391 @@
+//! Headless client for Cups rooms, for bots, servers and scripting.
+//!
+//! Creates or joins a room, prints its events to stdout and sends every line
+//! read from stdin as a chat message.
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::{bail, Context as _, Result};
+use cups_core::chat::{
+    channel::{Channel, TicketOpts},
+    ChatNode, ChatTicket, Event, MessageId, NetworkConfig, NetworkMode, RoomEvent,
+};
+use iroh::SecretKey;
+use n0_future::StreamExt as _;
+use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader};
+
+const USAGE: &str = "\
+Usage: cups-cli [OPTIONS] <COMMAND>
+
+Commands:
+  create <NAME>     Create a new room and print its ticket
+  join <TICKET>     Join a room from a ticket
+
+Options:
+  --nickname <NAME>  Name shown to other peers [default: cups-cli]
+  --key <FILE>       Secret key file, created if missing. A fresh identity is used if omitted
+  --blobs <DIR>      Where attachments are kept [default: <tmp>/cups-cli]
+  --plaintext        Create a room without end-to-end encryption
+  --lan              Only reach peers on the local network, found over mDNS
+  --relay <URL>      Relay to use in place of the default ones, may be repeated
+  --pkarr <URL>      Pkarr relay to publish and resolve addresses with
+  --dns <ORIGIN>     DNS origin to resolve addresses under
+  --port <PORT>      Port to bind on [default: random]
+  --json             Print events as JSON, one per line
+  -h, --help         Print this help
+
+Once joined, every line on stdin is sent as a message, except:
+  /nick <NAME>  Change nickname
+  /ticket       Print a ticket others can join with
+  /quit         Leave the room";
+
+enum Command {
+    Create(String),
+    Join(Box<ChatTicket>),
+}
+
+struct Args {
+    command: Command,
+    nickname: String,
+    key: Option<PathBuf>,
+    blobs: PathBuf,
+    plaintext: bool,
+    network: NetworkConfig,
+    json: bool,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>> {
+        let mut command = None;
+        let mut nickname = "cups-cli".to_string();
+        let mut key = None;
+        let mut blobs = std::env::temp_dir().join("cups-cli");
+        let mut plaintext = false;
+        let mut network = NetworkConfig::default();
+        let mut json = false;
+        while let Some(arg) = args.next() {
+            let mut value =
+                |name: &str| args.next().with_context(|| format!("{name} needs a value"));
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--nickname" => nickname = value("--nickname")?,
+                "--key" => key = Some(value("--key")?.into()),
+                "--blobs" => blobs = value("--blobs")?.into(),
+                "--plaintext" => plaintext = true,
+                "--lan" => network.mode = NetworkMode::LanOnly,
+                "--relay" => {
+                    let url = value("--relay")?;
+                    network.relays.push(
+                        url.parse()
+                            .with_context(|| format!("invalid relay {url:?}"))?,
+                    );
+                }
+                "--pkarr" => {
+                    let url = value("--pkarr")?;
+                    network.pkarr_relay = Some(
+                        url.parse()
+                            .with_context(|| format!("invalid pkarr relay {url:?}"))?,
+                    );
+                }
+                "--dns" => network.dns_origin = Some(value("--dns")?),
+                "--port" => {
+                    let port = value("--port")?;
+                    network.bind_port = Some(
+                        port.parse()
+                            .with_context(|| format!("invalid port {port:?}"))?,
+                    );
+                }
+                "--json" => json = true,
+                "create" if command.is_none() => {
+                    command = Some(Command::Create(value("create")?));
+                }
+                "join" if command.is_none() => {
+                    let ticket =
+                        ChatTicket::deserialize(&value("join")?).context("invalid ticket")?;
+                    command = Some(Command::Join(Box::new(ticket)));
+                }
+                other => bail!("unexpected argument {other:?}"),
+            }
+        }
+        let Some(command) = command else {
+            bail!("missing command");
+        };
+        Ok(Some(Self {
+            command,
+            nickname,
+            key,
+            blobs,
+            plaintext,
+            network,
+            json,
+        }))
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Logs go to stderr so stdout only carries events.
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::WARN)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<()> {
+    let key = match &args.key {
+        Some(path) => Some(load_or_create_key(path).await?),
+        None => None,
+    };
+    let node = ChatNode::spawn(key, args.blobs, args.network).await?;
+    eprintln!("node id: {}", node.node_id());
+
+    let ticket = match args.command {
+        Command::Create(name) => {
+            // We moderate the rooms we create.
+            let ticket = ChatTicket::new_named(&name).with_creator(node.node_id());
+            if args.plaintext {
+                ticket
+            } else {
+                ticket.with_secret()
+            }
+        }
+        Command::Join(ticket) => *ticket,
+    };
+    let mut channel = node.generate_channel(ticket, args.nickname)?;
+    print_ticket(&channel)?;
+    let topic = channel.topic_id();
+    let sender = channel.sender();
+    let mut events = channel.take_receiver().expect("receiver was just created");
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(Ok(event)) => {
+                    if args.json {
+                        println!("{}", serde_json::to_string(&RoomEvent::new(topic, &event))?);
+                    } else {
+                        print_event(&event);
+                    }
+                    // Leave as asked when an admin puts us out of the room.
+                    if let Event::Moderation { target, action, .. } = &event {
+                        if *target == node.node_id() && action.removes() {
+                            eprintln!("removed from the room");
+                            break;
+                        }
+                    }
+                }
+                Some(Err(err)) => bail!("room stream failed: {err}"),
+                None => bail!("room stream ended"),
+            },
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    break; // stdin closed
+                };
+                let line = line.trim();
+                match line.split_once(' ').unwrap_or((line, "")) {
+                    ("", _) => {}
+                    ("/quit", _) => break,
+                    ("/ticket", _) => print_ticket(&channel)?,
+                    ("/nick", name) if !name.trim().is_empty() => {
+                        sender.set_nickname(name.trim().to_string());
+                    }
+                    _ => {
+                        sender.send(line.to_string()).await?;
+                    }
+                }
+            }
+        }
+    }
+    if let Err(err) = sender.leave().await {
+        eprintln!("failed to say goodbye: {err}");
+    }
+    node.shutdown().await;
+    Ok(())
+}
+
+/// Read a hex encoded secret key, or generate one and save it there.
+async fn load_or_create_key(path: &Path) -> Result<SecretKey> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid key in {}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let key = SecretKey::generate(rand::rngs::OsRng);
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            let mut options = tokio::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            // Only ever readable by us, not even briefly after it's created.
+            #[cfg(unix)]
+            options.mode(0o600);
+            let mut file = options
+                .open(path)
+                .await
+                .with_context(|| format!("failed to create {}", path.display()))?;
+            file.write_all(key.to_string().as_bytes())
+                .await
+                .with_context(|| format!("failed to write key to {}", path.display()))?;
+            Ok(key)
+        }
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+fn print_ticket(channel: &Channel) -> Result<()> {
+    eprintln!("ticket: {}", channel.ticket(TicketOpts::all())?.serialize());
+    Ok(())
+}
+
+/// Print an event as a single human readable line.
+fn print_event(event: &Event) {
+    match event {
+        Event::MessageReceived {
+            from,
+            text,
+            nickname,
+            sent_timestamp,
+            reply_to,
+            ..
+        } => println!(
+            "[{}] <{nickname}@{}> {}{text}",
+            format_time(*sent_timestamp),
+            from.fmt_short(),
+            reply_to
+                .map(|parent| format!("(re {}) ", short_id(&parent)))
+                .unwrap_or_default()
+        ),
+        Event::MessageEdited {
+            from,
+            target_id,
+            new_text,
+            sent_timestamp,
+            ..
+        } => println!(
+            "[{}] {} edited {}: {new_text}",
+            format_time(*sent_timestamp),
+            from.fmt_short(),
+            short_id(target_id)
+        ),
+        Event::MessageDeleted {
+            from,
+            target_id,
+            sent_timestamp,
+            ..
+        } => println!(
+            "[{}] {} deleted {}",
+            format_time(*sent_timestamp),
+            from.fmt_short(),
+            short_id(target_id)
+        ),
+        Event::Reaction {
+            from,
+            target_id,
+            emoji,
+            add,
+            sent_timestamp,
+            ..
+        } => println!(
+            "[{}] {} {} {emoji} {} {}",
+            format_time(*sent_timestamp),
+            from.fmt_short(),
+            if *add { "reacted" } else { "took back" },
+            if *add { "to" } else { "on" },
+            short_id(target_id)
+        ),
+        Event::AttachmentReceived {
+            from,
+            nickname,
+            attachment,
+            sent_timestamp,
+            ..
+        } => println!(
+            "[{}] <{nickname}@{}> shared {} ({} bytes, {})",
+            format_time(*sent_timestamp),
+            from.fmt_short(),
+            attachment.name,
+            attachment.size,
+            attachment.hash
+        ),
+        Event::Backfill { from, messages } => {
+            println!(
+                "* {} messages from {}'s history",
+                messages.len(),
+                from.fmt_short()
+            );
+            messages.iter().for_each(print_event);
+        }
+        Event::Joined { neighbors } => println!("* joined with {} neighbors", neighbors.len()),
+        Event::NeighborUp { node_id } => println!("* {} connected", node_id.fmt_short()),
+        Event::NeighborDown { node_id } => println!("* {} disconnected", node_id.fmt_short()),
+        Event::Left { from, .. } => println!("* {} left", from.fmt_short()),
+        Event::Moderation {
+            from,
+            target,
+            action,
+            ..
+        } => println!("* {}: {action} {}", from.fmt_short(), target.fmt_short()),
+        Event::InviteRedeemed { from, invite, .. } => println!(
+            "* {} joined with an invite from {}",
+            from.fmt_short(),
+            invite.terms().issuer.fmt_short()
+        ),
+        Event::InviteRevoked {
+            from, invite_id, ..
+        } => println!("* {} revoked invite {invite_id}", from.fmt_short()),
+        Event::UnsupportedMessage { from, .. } => println!(
+            "* {} sent a message this build can't read, update required",
+            from.fmt_short()
+        ),
+        Event::Errorred { message } => println!("* error: {message}"),
+        Event::Lagged => println!("* missed some messages"),
+        Event::Presence { .. }
+        | Event::Typing { .. }
+        | Event::Receipt { .. }
+        | Event::ReceiptsUpdated { .. }
+        | Event::Disconnected
+        | Event::Reconnecting { .. }
+        | Event::Reconnected
+        | Event::ReactionSummary { .. } => {}
+    }
+}
+
+/// Enough of a message id to tell messages apart on screen.
+fn short_id(id: &MessageId) -> String {
+    id.to_string()[..8].to_string()
+}
+
+/// `HH:MM:SS` in UTC of a timestamp in micros.
+fn format_time(timestamp: u64) -> String {
+    let seconds = timestamp / 1_000_000 % 86_400;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,521 @@
+//! An opt-in local API for bots and scripts, e.g. to post CI notifications to a room.
+//!
+//! Clients connect over TCP to localhost and speak JSON-RPC 2.0, one JSON object
+//! per line. The first call must be `authenticate` with the configured token,
+//! after which the methods mirror the app's IPC commands (`create_room`,
+//! `join_room`, `send_message`, `leave_room`, `get_latest_ticket`) and every
+//! event the app emits (`chat-event`, `peers-event`, ...) is pushed to the
+//! client as a notification named after it.
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+use anyhow::{Context as _, Result};
+use n0_future::{
+    task::{self, AbortOnDropHandle},
+    StreamExt as _,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io::AsyncWriteExt as _,
+    net::{tcp::OwnedWriteHalf, TcpListener, TcpStream},
+    select,
+    sync::{
+        broadcast::{self, error::RecvError},
+        Mutex as TokioMutex,
+    },
+    task::JoinSet,
+};
+use tokio_util::codec::{FramedRead, LinesCodec};
+use tracing::{debug, info, warn};
+
+use crate::{
+    state::{AppContext, AppStore},
+    EventSink,
+};
+
+/// Port the local API listens on, on localhost, unless configured otherwise.
+pub const DEFAULT_API_PORT: u16 = 7878;
+
+/// A request is one JSON-RPC call, so anything larger is bogus.
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+/// Events buffered for each client before slow ones start missing some.
+const EVENTS_CAPACITY: usize = 256;
+
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+/// A command failed, as it would have over IPC.
+const COMMAND_FAILED: i32 = -32000;
+const UNAUTHORIZED: i32 = -32001;
+
+/// Where the local API listens and the token clients must present.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiConfig {
+    pub port: u16,
+    pub token: String,
+}
+
+impl ApiConfig {
+    /// A config for `port` with a fresh random token.
+    pub fn generate(port: u16) -> Self {
+        let token: [u8; 32] = rand::random();
+        Self {
+            port,
+            token: token.iter().map(|byte| format!("{byte:02x}")).collect(),
+        }
+    }
+}
+
+/// Relays the app's events to connected API clients. Clients only hear of
+/// events emitted after they authenticate.
+#[derive(Debug, Clone)]
+pub struct ApiEvents(broadcast::Sender<Notification>);
+
+impl Default for ApiEvents {
+    fn default() -> Self {
+        Self(broadcast::channel(EVENTS_CAPACITY).0)
+    }
+}
+
+impl EventSink for ApiEvents {
+    fn emit(&self, event: &str, payload: Value) -> Result<()> {
+        // Nobody listening is the usual case, not an error.
+        let _ = self.0.send(Notification {
+            jsonrpc: "2.0",
+            method: event.to_string(),
+            params: payload,
+        });
+        Ok(())
+    }
+}
+
+/// The local API server, which can be started and stopped while the app runs.
+#[derive(Default)]
+pub struct LocalApi {
+    events: ApiEvents,
+    server: TokioMutex<Option<ApiServer>>,
+}
+
+impl LocalApi {
+    /// The sink to give the [`AppContext`], alongside the UI's, so clients hear of its events.
+    pub fn events(&self) -> ApiEvents {
+        self.events.clone()
+    }
+
+    /// Start serving with `config`, replacing the running server if there is one.
+    pub async fn start(
+        &self,
+        config: ApiConfig,
+        context: AppContext,
+        store: AppStore,
+    ) -> Result<()> {
+        let mut server = self.server.lock().await;
+        // Release the port first, the new config may well use the same one.
+        if let Some(running) = server.take() {
+            running.shutdown().await;
+        }
+        *server = Some(ApiServer::spawn(config, context, store, self.events.clone()).await?);
+        Ok(())
+    }
+
+    /// Stop serving and disconnect every client. Returns false if it wasn't running.
+    pub async fn stop(&self) -> bool {
+        match self.server.lock().await.take() {
+            Some(server) => {
+                server.shutdown().await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The config the server is running with, if it is.
+    pub async fn config(&self) -> Option<ApiConfig> {
+        let server = self.server.lock().await;
+        server.as_ref().map(|server| server.config.clone())
+    }
+}
+
+/// Accepts clients on localhost until shut down or dropped.
+struct ApiServer {
+    config: ApiConfig,
+    task: AbortOnDropHandle<()>,
+}
+
+impl ApiServer {
+    async fn spawn(
+        config: ApiConfig,
+        context: AppContext,
+        store: AppStore,
+        events: ApiEvents,
+    ) -> Result<Self> {
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, config.port));
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("failed to bind local API to {addr}"))?;
+        info!("local API listening on {addr}");
+        let api = Arc::new(Api {
+            context,
+            store,
+            token: config.token.clone(),
+            events,
+        });
+        let task = task::spawn(async move {
+            // Dropped along with the listener, disconnecting every client.
+            let mut clients = JoinSet::new();
+            loop {
+                match listener.accept().await {
+                    Ok((stream, client)) => {
+                        debug!("local API client connected from {client}");
+                        let api = api.clone();
+                        clients.spawn(async move {
+                            if let Err(e) = api.serve(stream).await {
+                                warn!("local API client {client} failed: {e}");
+                            }
+                        });
+                    }
+                    Err(e) => warn!("failed to accept local API client: {e}"),
+                }
+                while clients.try_join_next().is_some() {}
+            }
+        });
+        Ok(Self {
+            config,
+            task: AbortOnDropHandle::new(task),
+        })
+    }
+
+    /// Stop accepting clients and wait until the port is released.
+    async fn shutdown(mut self) {
+        self.task.abort();
+        let _ = (&mut self.task).await;
+    }
+}
+
+/// What a client connection needs to answer calls.
+struct Api {
+    context: AppContext,
+    store: AppStore,
+    token: String,
+    events: ApiEvents,
+}
+
+impl Api {
+    async fn serve(&self, stream: TcpStream) -> Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut requests = FramedRead::new(read, LinesCodec::new_with_max_length(MAX_REQUEST_SIZE));
+        // Only authenticated clients are sent events.
+        let mut events = None;
+        loop {
+            select! {
+                line = requests.next() => {
+                    let Some(line) = line else {
+                        return Ok(()); // client hung up
+                    };
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    if let Some(response) = self.handle(&line, &mut events).await {
+                        send(&mut write, &response).await?;
+                    }
+                }
+                event = next_event(&mut events) => match event {
+                    Ok(notification) => send(&mut write, &notification).await?,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("local API client is too slow, it missed {missed} events");
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            }
+        }
+    }
+
+    /// Answer a single line, or return `None` if it was a notification.
+    async fn handle(
+        &self,
+        line: &str,
+        events: &mut Option<broadcast::Receiver<Notification>>,
+    ) -> Option<Response> {
+        let request: Request = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = RpcError::new(PARSE_ERROR, e.to_string());
+                return Some(Response::new(Value::Null, Err(error)));
+            }
+        };
+        let outcome = if request.method == "authenticate" {
+            self.authenticate(request.params).map(|()| {
+                events.get_or_insert_with(|| self.events.0.subscribe());
+                Value::Null
+            })
+        } else if events.is_none() {
+            Err(RpcError::new(UNAUTHORIZED, "call authenticate first"))
+        } else {
+            self.call(&request.method, request.params).await
+        };
+        if let Err(e) = &outcome {
+            debug!("local API call {} failed: {}", request.method, e.message);
+        }
+        Some(Response::new(request.id?, outcome))
+    }
+
+    fn authenticate(&self, params: Value) -> Result<(), RpcError> {
+        let params: AuthenticateParams = parse_params(params)?;
+        // Compare in constant time so the token can't be guessed a byte at a time.
+        let matches = params.token.len() == self.token.len()
+            && params
+                .token
+                .bytes()
+                .zip(self.token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0;
+        if matches {
+            Ok(())
+        } else {
+            Err(RpcError::new(UNAUTHORIZED, "invalid token"))
+        }
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "create_room" => {
+                let params: CreateRoomParams = parse_params(params)?;
+                let ticket = self
+                    .context
+                    .create_room(
+                        &params.name,
+                        &params.nickname,
+                        params.encrypted.unwrap_or(true),
+                        params.invite_only.unwrap_or(false),
+                        &self.store,
+                    )
+                    .await?;
+                to_result(ticket)
+            }
+            "join_room" => {
+                let params: JoinRoomParams = parse_params(params)?;
+                self.context
+                    .join_room(&params.ticket, &params.nickname, &self.store)
+                    .await?;
+                to_result(())
+            }
+            "send_message" => {
+                let params: SendMessageParams = parse_params(params)?;
+                let topic = parse_topic(&params.topic)?;
+                let reply_to = params
+                    .reply_to
+                    .as_deref()
+                    .map(str::parse)
+                    .transpose()
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid replyTo: {e}")))?;
+                let id = self
+                    .context
+                    .send_message(&topic, params.message, reply_to)
+                    .await?;
+                to_result(id)
+            }
+            "edit_message" => {
+                let params: EditMessageParams = parse_params(params)?;
+                let topic = parse_topic(&params.topic)?;
+                let id = self
+                    .context
+                    .edit_message(&topic, &params.message_id, params.text)
+                    .await?;
+                to_result(id)
+            }
+            "delete_message" => {
+                let params: DeleteMessageParams = parse_params(params)?;
+                let topic = parse_topic(&params.topic)?;
+                let id = self
+                    .context
+                    .delete_message(&topic, &params.message_id)
+                    .await?;
+                to_result(id)
+            }
+            "react" => {
+                let params: ReactParams = parse_params(params)?;
+                let topic = parse_topic(&params.topic)?;
+                let id = self
+                    .context
+                    .react(&topic, &params.message_id, params.emoji, params.add)
+                    .await?;
+                to_result(id)
+            }
+            "leave_room" => {
+                let params: LeaveRoomParams = parse_params(params)?;
+                let topic = parse_topic(&params.topic)?;
+                self.context.leave_room(&topic, &self.store).await?;
+                to_result(())
+            }
+            "get_latest_ticket" => to_result(self.context.latest_room().await?),
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method {method}"),
+            )),
+        }
+    }
+}
+
+/// The next event for an authenticated client, never resolving for others.
+async fn next_event(
+    events: &mut Option<broadcast::Receiver<Notification>>,
+) -> Result<Notification, RecvError> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn send(write: &mut OwnedWriteHalf, message: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    write.write_all(&line).await?;
+    Ok(())
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn parse_topic(topic: &str) -> Result<crate::chat::channel::TopicId, RpcError> {
+    topic
+        .parse()
+        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid topic ID {topic}: {e}")))
+}
+
+fn to_result(value: impl Serialize) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(COMMAND_FAILED, e.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    /// Absent for notifications, which get no response.
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+impl Response {
+    fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            outcome: match outcome {
+                Ok(result) => Outcome::Result(result),
+                Err(error) => Outcome::Error(error),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Outcome {
+    Result(Value),
+    Error(RpcError),
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(COMMAND_FAILED, e.to_string())
+    }
+}
+
+/// An event pushed to authenticated clients.
+#[derive(Debug, Clone, Serialize)]
+struct Notification {
+    jsonrpc: &'static str,
+    method: String,
+    params: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthenticateParams {
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateRoomParams {
+    name: String,
+    nickname: String,
+    #[serde(default)]
+    encrypted: Option<bool>,
+    #[serde(default)]
+    invite_only: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JoinRoomParams {
+    ticket: String,
+    nickname: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SendMessageParams {
+    topic: String,
+    message: String,
+    #[serde(default)]
+    reply_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EditMessageParams {
+    topic: String,
+    message_id: String,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeleteMessageParams {
+    topic: String,
+    message_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReactParams {
+    topic: String,
+    message_id: String,
+    emoji: String,
+    add: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct LeaveRoomParams {
+    topic: String,
+}
@@ -0,0 +1,527 @@
+mod blobs;
+pub mod channel;
+mod crypto;
+mod direct;
+mod event;
+mod history;
+mod invite;
+mod message;
+mod moderation;
+mod network;
+pub mod peers;
+mod replay;
+mod sender;
+#[cfg(test)]
+pub(crate) mod testing;
+mod ticket;
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Result};
+pub use blobs::{guess_mime, Attachment, BlobHash, BlobStore};
+use blobs::{BlobsProtocol, BLOBS_ALPN};
+use channel::{ChatReceiver, TopicId};
+use crypto::RoomSecret;
+pub use direct::DirectEvent;
+use direct::{DirectProtocol, DIRECT_ALPN};
+pub use event::{Event, MessageReceipts, ReactionCount, RoomEvent};
+use history::{HistoryProtocol, HistoryStore, Since, FETCH_TIMEOUT, HISTORY_ALPN};
+pub use invite::{Invite, InviteId, InviteTerms};
+pub use iroh::NodeId;
+use iroh::{endpoint::RemoteInfo, protocol::Router, Endpoint, SecretKey};
+use iroh_gossip::net::{Gossip, GossipEvent, GOSSIP_ALPN};
+use message::{Message, SignedMessage};
+pub use message::{MessageId, ReceiptKind, MAX_REACTION_LEN, MAX_RECEIPT_BATCH};
+use moderation::ModerationStore;
+pub use moderation::{Moderation, ModerationAction, RoomModeration};
+use n0_future::{
+    task::{self, AbortOnDropHandle},
+    time::Duration,
+    StreamExt,
+};
+pub use network::{NetworkConfig, NetworkMode};
+pub use replay::AcceptanceWindow;
+use replay::ReplayGuard;
+use sender::TypingState;
+pub use sender::{ChatSender, TYPING_THROTTLE};
+pub use ticket::{ChatTicket, RoomCreator, VisitedRoom};
+use tokio::sync::{mpsc, Notify};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::utils::get_timestamp;
+
+pub const PRESENCE_INTERVAL: Duration = Duration::from_secs(5);
+/// Number of neighbors asked for history when we join a room.
+const BACKFILL_PEERS: usize = 3;
+/// Direct messages received but not yet taken by the app.
+const DIRECT_EVENTS_CAPACITY: usize = 64;
+
+pub struct ChatNode {
+    secret_key: SecretKey,
+    router: Router,
+    gossip: Gossip,
+    history: HistoryStore,
+    moderation: ModerationStore,
+    window: Mutex<AcceptanceWindow>,
+    /// The replay guard of each room we are in, kept across rejoins.
+    guards: Mutex<HashMap<TopicId, Arc<Mutex<ReplayGuard>>>>,
+    blobs: BlobStore,
+    direct_events: Mutex<Option<mpsc::Receiver<DirectEvent>>>,
+}
+
+impl ChatNode {
+    /// Spawns a gossip node that reaches peers as `network` says, keeping
+    /// attachments in `blobs_dir`.
+    pub async fn spawn(
+        secret_key: Option<SecretKey>,
+        blobs_dir: PathBuf,
+        network: NetworkConfig,
+    ) -> Result<Self> {
+        let secret_key = secret_key.unwrap_or_else(|| SecretKey::generate(rand::rngs::OsRng));
+        let endpoint = network
+            .configure(iroh::Endpoint::builder().secret_key(secret_key.clone()))
+            .alpns(vec![GOSSIP_ALPN.to_vec()])
+            .bind()
+            .await?;
+
+        let node_id = endpoint.node_id();
+        info!("endpoint bound");
+        info!("node id: {node_id:#?}");
+
+        let gossip = Gossip::builder().spawn(endpoint.clone()).await?;
+        info!("gossip spawned");
+        let history = HistoryStore::default();
+        let moderation = ModerationStore::default();
+        let blobs = BlobStore::new(blobs_dir);
+        let (direct_tx, direct_rx) = mpsc::channel(DIRECT_EVENTS_CAPACITY);
+        let router = Router::builder(endpoint)
+            .accept(GOSSIP_ALPN, gossip.clone())
+            .accept(
+                HISTORY_ALPN,
+                HistoryProtocol::new(history.clone(), moderation.clone()),
+            )
+            .accept(DIRECT_ALPN, DirectProtocol::new(direct_tx))
+            .accept(
+                BLOBS_ALPN,
+                BlobsProtocol::new(blobs.clone(), moderation.clone()),
+            )
+            .spawn();
+        info!("router spawned");
+        Ok(Self {
+            gossip,
+            router,
+            history,
+            moderation,
+            secret_key,
+            window: Mutex::new(AcceptanceWindow::default()),
+            guards: Mutex::default(),
+            blobs,
+            direct_events: Mutex::new(Some(direct_rx)),
+        })
+    }
+
+    /// Set how far message timestamps may drift from local time in rooms joined from now on.
+    pub fn with_acceptance_window(self, window: AcceptanceWindow) -> Self {
+        self.set_acceptance_window(window);
+        self
+    }
+
+    /// Change how far message timestamps may drift from local time, for rooms
+    /// joined from now on.
+    pub fn set_acceptance_window(&self, window: AcceptanceWindow) {
+        *self.window.lock().expect("poisened") = window;
+    }
+
+    /// The replay guard of a room, which outlives the room's event stream so that
+    /// what we saw before a rejoin can't be replayed to us after it.
+    fn replay_guard(&self, topic: TopicId) -> Arc<Mutex<ReplayGuard>> {
+        let window = *self.window.lock().expect("poisened");
+        let mut guards = self.guards.lock().expect("poisened");
+        let guard = guards
+            .entry(topic)
+            .or_insert_with(|| Arc::new(Mutex::new(ReplayGuard::new(window))));
+        guard.lock().expect("poisened").set_window(window);
+        guard.clone()
+    }
+
+    /// Forget which messages we saw in a room we left.
+    pub fn forget_room(&self, topic: &TopicId) {
+        self.guards.lock().expect("poisened").remove(topic);
+    }
+
+    /// Returns the node id of this node.
+    pub fn node_id(&self) -> NodeId {
+        self.router.endpoint().node_id()
+    }
+
+    /// The endpoint our protocols are served on.
+    pub fn endpoint(&self) -> &Endpoint {
+        self.router.endpoint()
+    }
+
+    /// Where attachments we shared or fetched are kept and served from.
+    pub fn blobs(&self) -> &BlobStore {
+        &self.blobs
+    }
+
+    /// The moderation state of a ticket's room, taking in the creator and members it lists.
+    pub fn moderation(&self, ticket: &ChatTicket) -> Moderation {
+        self.moderation.room(ticket)
+    }
+
+    /// Take the stream of direct messages sent to us. Can only be taken once.
+    pub fn take_direct_receiver(&self) -> Option<mpsc::Receiver<DirectEvent>> {
+        self.direct_events.lock().expect("poisened").take()
+    }
+
+    /// Send a chat message straight to a single peer, returning its [`MessageId`]
+    /// once the peer has accepted it.
+    pub async fn send_direct(
+        &self,
+        peer: NodeId,
+        nickname: String,
+        text: String,
+    ) -> Result<MessageId> {
+        if peer == self.node_id() {
+            bail!("Cannot send a direct message to ourselves");
+        }
+        let message = Message::Message { text, nickname };
+        let (signed_message, _) = SignedMessage::sign_and_encode(&self.secret_key, message)?;
+        let id = MessageId::from_signed(&signed_message);
+        n0_future::time::timeout(
+            direct::SEND_TIMEOUT,
+            direct::send(self.router.endpoint(), peer, signed_message),
+        )
+        .await
+        .map_err(|_| anyhow!("Timed out sending to {}", peer.fmt_short()))??;
+        Ok(id)
+    }
+
+    #[allow(unused)]
+    /// Returns information about all the remote nodes this [`Endpoint`] knows about.
+    pub fn remote_info(&self) -> Vec<RemoteInfo> {
+        self.router
+            .endpoint()
+            .remote_info_iter()
+            .collect::<Vec<_>>()
+    }
+
+    /// Joins a chat channel from a ticket.
+    ///
+    /// Returns a [`ChatSender`] to send messages or change our nickname
+    /// and a stream of [`Event`] items for incoming messages and other event.s
+    pub fn join(
+        &self,
+        ticket: &ChatTicket,
+        nickname: String,
+    ) -> Result<(ChatSender, ChatReceiver)> {
+        let topic_id = ticket.topic_id;
+        let secret = ticket.secret.clone();
+        let moderation = self.moderation(ticket);
+        moderation.joined(get_timestamp());
+        // Members check the invite once we announce it, but there's no use joining
+        // with one we can tell doesn't hold.
+        if let Some(invite) = &ticket.invite {
+            invite.validate(topic_id, get_timestamp())?;
+            moderation.restore_redemption(invite.id(), self.node_id());
+            moderation.restore(self.node_id(), ModerationAction::AddMember, get_timestamp());
+        }
+        let bootstrap: Vec<NodeId> = ticket
+            .bootstrap
+            .iter()
+            .filter(|node| moderation.is_member(node) && !moderation.is_banned(node))
+            .cloned()
+            .collect();
+        // Dial what the ticket tells us right away, rather than wait on discovery.
+        for addr in &ticket.addrs {
+            if addr.node_id == self.node_id() || !bootstrap.contains(&addr.node_id) {
+                continue;
+            }
+            if let Err(err) = self.endpoint().add_node_addr(addr.clone()) {
+                warn!(
+                    "failed to add address of {}: {err}",
+                    addr.node_id.fmt_short()
+                );
+            }
+        }
+        info!(?bootstrap, "joining {topic_id}");
+        let gossip_topic = self.gossip.subscribe(topic_id, bootstrap)?;
+        let (sender, receiver) = gossip_topic.split();
+
+        let nickname = Arc::new(Mutex::new(nickname));
+        let trigger_presence = Arc::new(Notify::new());
+        let typing = Arc::new(Mutex::new(TypingState::default()));
+        // The invite we joined with, announced whenever we (re)join the swarm.
+        let redeem = Arc::new(Mutex::new(None::<Invite>));
+        // Cancelled if we can no longer broadcast to the room, ending the event stream.
+        let broadcast_failed = CancellationToken::new();
+
+        // We spawn a task that occasionally sens a Presence message with our nickname.
+        // This allows to track which peers are online currently. Typing updates are
+        // sent in its place when it's woken for one.
+        let presence_task = AbortOnDropHandle::new(task::spawn({
+            let secret_key = self.secret_key.clone();
+            let sender = sender.clone();
+            let trigger_presence = trigger_presence.clone();
+            let nickname = nickname.clone();
+            let typing = typing.clone();
+            let redeem = redeem.clone();
+            let secret = secret.clone();
+            let broadcast_failed = broadcast_failed.clone();
+
+            async move {
+                'presence: loop {
+                    let pending_typing = typing.lock().expect("poisened").take();
+                    let message = match pending_typing {
+                        Some(active) => Message::Typing { active },
+                        None => Message::Presence {
+                            nickname: nickname.lock().expect("poisened").clone(),
+                        },
+                    };
+                    // Announce our invite first, so members let the rest through.
+                    let pending_redeem = redeem.lock().expect("poisened").take();
+                    let messages = pending_redeem
+                        .map(|invite| Message::Redeem { invite })
+                        .into_iter()
+                        .chain([message]);
+                    for message in messages {
+                        debug!("send presence {message:?}");
+                        let signed_message = SignedMessage::sign_and_encode(&secret_key, message)
+                            .and_then(|(message, _)| crypto::seal(secret.as_ref(), message))
+                            .expect("failed to encode message");
+                        if let Err(err) = sender.broadcast(signed_message.into()).await {
+                            tracing::warn!("presence task failed to broadcast: {err}");
+                            broadcast_failed.cancel();
+                            break 'presence;
+                        }
+                    }
+                    n0_future::future::race(
+                        n0_future::time::sleep(PRESENCE_INTERVAL),
+                        trigger_presence.notified(),
+                    )
+                    .await;
+                }
+            }
+        }));
+
+        // Payloads served by neighbors from their history once we've joined.
+        let (backfill_tx, backfill_rx) = mpsc::channel::<(NodeId, Vec<Vec<u8>>)>(BACKFILL_PEERS);
+        let since = Since::Timestamp(self.history.latest(&topic_id).unwrap_or_default());
+
+        // We create a stream of events, coming from the gossip topic event receiver.
+        // We'll want to map the events to our own event type, which includes parsing
+        // the messages and verifying the signatures, and trigger presence
+        // once the swarm is joined initially.
+        let guard = self.replay_guard(topic_id);
+        let receiver = n0_future::stream::try_unfold((receiver, guard, backfill_rx), {
+            let trigger_presence = trigger_presence.clone();
+            let secret = secret.clone();
+            let history = self.history.clone();
+            let moderation = moderation.clone();
+            let endpoint = self.router.endpoint().clone();
+            let invite = ticket.invite.clone();
+            let redeem = redeem.clone();
+            move |(mut receiver, guard, mut backfill_rx)| {
+                let trigger_presence = trigger_presence.clone();
+                let secret = secret.clone();
+                let history = history.clone();
+                let moderation = moderation.clone();
+                let endpoint = endpoint.clone();
+                let invite = invite.clone();
+                let redeem = redeem.clone();
+                let backfill_tx = backfill_tx.clone();
+                let since = since.clone();
+                let broadcast_failed = broadcast_failed.clone();
+                async move {
+                    loop {
+                        // Store if we were joined before the next event comes in.
+                        let was_joined = receiver.is_joined();
+
+                        // Fetch the next event, or the next batch of history.
+                        let event = tokio::select! {
+                            Some((from, payloads)) = backfill_rx.recv() => {
+                                let mut event = Event::from_backfill(from, &payloads, secret.as_ref(), &mut guard.lock().expect("poisened"));
+                                if let Event::Backfill { messages, .. } = &mut event {
+                                    messages.retain(|event| moderation.admit(event));
+                                    // Serve on only what got past moderation.
+                                    let admitted: HashSet<_> = messages.iter().filter_map(Event::history_entry).map(|(id, _, _)| id).collect();
+                                    for payload in &payloads {
+                                        remember(&history, &admitted, topic_id, payload, secret.as_ref());
+                                    }
+                                    messages.retain(|event| authorized(&history, topic_id, event));
+                                }
+                                break Ok(Some((event, (receiver, guard, backfill_rx))));
+                            }
+                            event = receiver.try_next() => event?,
+                            _ = broadcast_failed.cancelled() => {
+                                bail!("lost the ability to broadcast to the room");
+                            }
+                        };
+                        let Some(event) = event else {
+                            return Ok(None);
+                        };
+                        let payload = match &event {
+                            iroh_gossip::net::Event::Gossip(GossipEvent::Received(message)) => {
+                                Some(message.content.to_vec())
+                            }
+                            _ => None,
+                        };
+                        // Convert into our event type. this fails if we receive a message
+                        // that cannot be decoced into our event type. If that is the case,
+                        // we just keep and log the error.
+                        let event = Event::from_gossip(
+                            event,
+                            secret.as_ref(),
+                            &mut guard.lock().expect("poisened"),
+                        );
+                        let event = match event {
+                            Ok(event) => event,
+                            Err(err) => {
+                                warn!("received invalid message: {err}");
+                                continue;
+                            }
+                        };
+                        if !authorized(&history, topic_id, &event) || !moderation.admit(&event) {
+                            continue;
+                        }
+                        let deletes = match &event {
+                            Event::MessageDeleted { target_id, .. } => Some(*target_id),
+                            _ => None,
+                        };
+                        if let Some(target_id) = &deletes {
+                            history.remove(&topic_id, target_id);
+                        }
+                        // Keep chat messages around to serve to late joiners.
+                        if let (Some((id, from, sent_timestamp)), Some(payload)) =
+                            (event.history_entry(), payload)
+                        {
+                            history.insert(topic_id, id, from, sent_timestamp, payload, deletes);
+                        }
+                        // If we just joined, trigger sending our presence message,
+                        // announce the invite we joined with and ask some neighbors for what we missed.
+                        if !was_joined && receiver.is_joined() {
+                            *redeem.lock().expect("poisened") = invite.clone();
+                            trigger_presence.notify_waiters();
+                            for peer in receiver.neighbors().take(BACKFILL_PEERS) {
+                                task::spawn(fetch_backfill(
+                                    endpoint.clone(),
+                                    peer,
+                                    topic_id,
+                                    since.clone(),
+                                    backfill_tx.clone(),
+                                ));
+                            }
+                        };
+
+                        break Ok(Some((event, (receiver, guard, backfill_rx))));
+                    }
+                }
+            }
+        });
+
+        let sender = ChatSender::new(
+            nickname,
+            self.secret_key.clone(),
+            secret,
+            sender,
+            trigger_presence,
+            typing,
+            presence_task,
+            topic_id,
+            self.history.clone(),
+            moderation,
+        );
+        Ok((sender, Box::pin(receiver)))
+    }
+
+    /// Stop serving our protocols and close the endpoint.
+    pub async fn shutdown(&self) {
+        if let Err(err) = self.router.shutdown().await {
+            warn!("failed to shutdown router cleanly: {err}");
+        }
+        self.router.endpoint().close().await;
+    }
+}
+
+/// Fetch history from a neighbor and hand it to the room's event stream.
+async fn fetch_backfill(
+    endpoint: iroh::Endpoint,
+    peer: NodeId,
+    topic: TopicId,
+    since: Since,
+    backfill_tx: mpsc::Sender<(NodeId, Vec<Vec<u8>>)>,
+) {
+    match n0_future::time::timeout(FETCH_TIMEOUT, history::fetch(&endpoint, peer, topic, since))
+        .await
+    {
+        Ok(Ok(payloads)) => {
+            debug!(
+                "received {} history messages from {}",
+                payloads.len(),
+                peer.fmt_short()
+            );
+            backfill_tx.send((peer, payloads)).await.ok();
+        }
+        Ok(Err(err)) => warn!("failed to fetch history from {}: {err}", peer.fmt_short()),
+        Err(_) => warn!("timed out fetching history from {}", peer.fmt_short()),
+    }
+}
+
+/// Store a backfilled chat message we admitted so we can serve it on in turn,
+/// applying deletions by the target's author.
+fn remember(
+    history: &HistoryStore,
+    admitted: &HashSet<MessageId>,
+    topic: TopicId,
+    payload: &[u8],
+    secret: Option<&RoomSecret>,
+) {
+    let Ok(content) = crypto::open(secret, payload) else {
+        return;
+    };
+    let Ok(message) = SignedMessage::verify_and_decode(&content) else {
+        return;
+    };
+    if !admitted.contains(&message.id) {
+        return;
+    }
+    let Some(inner) = message.message.as_ref().filter(|inner| inner.is_stored()) else {
+        return;
+    };
+    if let Some(target) = inner.target() {
+        if !history.may_amend(&topic, &target, &message.from) {
+            return;
+        }
+        if matches!(inner, Message::Delete { .. }) {
+            history.remove(&topic, &target);
+        }
+    }
+    history.insert(
+        topic,
+        message.id,
+        message.from,
+        message.timestamp,
+        payload.to_vec(),
+        inner.deleted(),
+    );
+}
+
+/// Drop edits and deletions of messages we know were sent by someone else.
+fn authorized(history: &HistoryStore, topic: TopicId, event: &Event) -> bool {
+    let Some((from, target)) = event.amendment() else {
+        return true;
+    };
+    let authorized = history.may_amend(&topic, &target, &from);
+    if !authorized {
+        warn!(
+            "ignoring amendment of {target} by {}, who did not send it",
+            from.fmt_short()
+        );
+    }
+    authorized
+}
@@ -0,0 +1,306 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context as _, Result};
+use iroh::{endpoint::Connection, protocol::ProtocolHandler, Endpoint, NodeId};
+use iroh_gossip::proto::TopicId;
+use n0_future::{
+    boxed::BoxFuture,
+    time::{timeout, Duration},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    sync::watch,
+};
+use tracing::{debug, warn};
+
+use super::moderation::ModerationStore;
+use crate::utils::hex_id;
+
+/// ALPN of the protocol used to fetch attachments from peers that have them.
+pub const BLOBS_ALPN: &[u8] = b"cups/blobs/0";
+
+/// Largest file that can be shared as an attachment.
+pub const MAX_BLOB_SIZE: u64 = 100 * 1024 * 1024;
+/// A request is a hash and a topic, so anything larger is bogus.
+const MAX_REQUEST_SIZE: usize = 128;
+const CHUNK_SIZE: usize = 64 * 1024;
+/// How long a requester gets to read what we sent and hang up before we do.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Content address of an attachment: the blake3 hash of its bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlobHash([u8; 32]);
+
+impl BlobHash {
+    fn from_hasher(hasher: &blake3::Hasher) -> Self {
+        Self(*hasher.finalize().as_bytes())
+    }
+}
+
+hex_id!(BlobHash);
+
+/// A file shared in a room, as announced by a [`super::message::Message::Attachment`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub hash: BlobHash,
+    pub name: String,
+    pub size: u64,
+    pub mime: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BlobRequest {
+    hash: BlobHash,
+    /// The room the attachment was shared in, whose members may fetch it.
+    topic: TopicId,
+}
+
+/// Complete, verified blobs kept on disk, one file per hash.
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, hash: &BlobHash) -> PathBuf {
+        self.dir.join(hash.to_string())
+    }
+
+    fn partial_path(&self, hash: &BlobHash) -> PathBuf {
+        self.dir.join(format!("{hash}.partial"))
+    }
+
+    /// Whether we hold the complete blob, and so can serve it.
+    pub fn has(&self, hash: &BlobHash) -> bool {
+        self.path(hash).is_file()
+    }
+
+    /// Copy a file into the store, returning its hash and size.
+    pub async fn import(&self, source: &Path) -> Result<(BlobHash, u64)> {
+        let mut file = fs::File::open(source)
+            .await
+            .with_context(|| format!("failed to open {}", source.display()))?;
+        let size = file.metadata().await?.len();
+        ensure!(
+            size <= MAX_BLOB_SIZE,
+            "{} is larger than the {} MiB attachment limit",
+            source.display(),
+            MAX_BLOB_SIZE / 1024 / 1024
+        );
+        fs::create_dir_all(&self.dir).await?;
+        let temp = self
+            .dir
+            .join(format!("import-{}.partial", rand::random::<u64>()));
+        let mut out = fs::File::create(&temp).await?;
+        let mut hasher = blake3::Hasher::new();
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+            out.write_all(&buf[..read]).await?;
+        }
+        out.flush().await?;
+        let hash = BlobHash::from_hasher(&hasher);
+        fs::rename(&temp, self.path(&hash)).await?;
+        Ok((hash, size))
+    }
+
+    /// Copy a complete blob out of the store.
+    pub async fn export(&self, hash: &BlobHash, destination: &Path) -> Result<()> {
+        if !self.has(hash) {
+            bail!("attachment {hash} has not been downloaded");
+        }
+        fs::copy(self.path(hash), destination)
+            .await
+            .with_context(|| format!("failed to save to {}", destination.display()))?;
+        Ok(())
+    }
+
+    /// Fetch a blob from the first provider that has it, verifying its hash
+    /// before it is kept. Bytes received so far are published on `progress`.
+    pub async fn download(
+        &self,
+        endpoint: &Endpoint,
+        topic: TopicId,
+        attachment: &Attachment,
+        providers: &[NodeId],
+        progress: &watch::Sender<u64>,
+    ) -> Result<()> {
+        if self.has(&attachment.hash) {
+            progress.send_replace(attachment.size);
+            return Ok(());
+        }
+        ensure!(
+            attachment.size <= MAX_BLOB_SIZE,
+            "attachment is larger than the {} MiB limit",
+            MAX_BLOB_SIZE / 1024 / 1024
+        );
+        fs::create_dir_all(&self.dir).await?;
+        for provider in providers {
+            progress.send_replace(0);
+            match self
+                .download_from(endpoint, *provider, topic, attachment, progress)
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(err) => warn!(
+                    "failed to fetch {} from {}: {err}",
+                    attachment.hash,
+                    provider.fmt_short()
+                ),
+            }
+        }
+        bail!("no peer could provide attachment {}", attachment.name)
+    }
+
+    async fn download_from(
+        &self,
+        endpoint: &Endpoint,
+        provider: NodeId,
+        topic: TopicId,
+        attachment: &Attachment,
+        progress: &watch::Sender<u64>,
+    ) -> Result<()> {
+        let connection = endpoint.connect(provider, BLOBS_ALPN).await?;
+        let (mut send, mut recv) = connection.open_bi().await?;
+        let request = BlobRequest {
+            hash: attachment.hash,
+            topic,
+        };
+        send.write_all(&postcard::to_stdvec(&request)?).await?;
+        send.finish()?;
+
+        // The provider answers with the size, or hangs up if it lacks the blob.
+        let mut size = [0u8; 8];
+        recv.read_exact(&mut size)
+            .await
+            .context("peer does not have the attachment")?;
+        let size = u64::from_be_bytes(size);
+        ensure!(
+            size == attachment.size,
+            "peer offered {size} bytes, expected {}",
+            attachment.size
+        );
+
+        let partial = self.partial_path(&attachment.hash);
+        let mut out = fs::File::create(&partial).await?;
+        let mut hasher = blake3::Hasher::new();
+        let mut received = 0u64;
+        while let Some(chunk) = recv.read_chunk(CHUNK_SIZE, true).await? {
+            received += chunk.bytes.len() as u64;
+            ensure!(received <= size, "peer sent more than {size} bytes");
+            hasher.update(&chunk.bytes);
+            out.write_all(&chunk.bytes).await?;
+            progress.send_replace(received);
+        }
+        out.flush().await?;
+        connection.close(0u32.into(), b"done");
+        ensure!(received == size, "peer sent {received} of {size} bytes");
+        ensure!(
+            BlobHash::from_hasher(&hasher) == attachment.hash,
+            "content does not match hash {}",
+            attachment.hash
+        );
+        fs::rename(&partial, self.path(&attachment.hash)).await?;
+        debug!(
+            "fetched {} ({} bytes) from {}",
+            attachment.hash,
+            size,
+            provider.fmt_short()
+        );
+        Ok(())
+    }
+}
+
+/// Serves complete blobs from the [`BlobStore`] to peers that ask for them.
+///
+/// Peers name the room they want a hash from, and only get it if they are let
+/// into that room. Hashes are only ever sent in rooms, and encrypted in encrypted
+/// ones, so knowing one means having been in a room it was shared in.
+#[derive(Debug, Clone)]
+pub struct BlobsProtocol {
+    store: BlobStore,
+    moderation: ModerationStore,
+}
+
+impl BlobsProtocol {
+    pub fn new(store: BlobStore, moderation: ModerationStore) -> Self {
+        Self { store, moderation }
+    }
+}
+
+impl ProtocolHandler for BlobsProtocol {
+    fn accept(&self, connection: Connection) -> BoxFuture<Result<()>> {
+        let store = self.store.clone();
+        let moderation = self.moderation.clone();
+        Box::pin(async move {
+            let remote = connection.remote_node_id()?;
+            let (mut send, mut recv) = connection.accept_bi().await?;
+            let request: BlobRequest =
+                postcard::from_bytes(&recv.read_to_end(MAX_REQUEST_SIZE).await?)?;
+            let file = match moderation.serves(&request.topic, &remote) {
+                true => fs::File::open(store.path(&request.hash)).await.ok(),
+                false => None,
+            };
+            let Some(mut file) = file else {
+                // We don't have it or won't serve it: hang up and let the requester
+                // try someone else.
+                send.finish()?;
+                timeout(CLOSE_TIMEOUT, connection.closed()).await.ok();
+                return Ok(());
+            };
+            let size = file.metadata().await?.len();
+            debug!(
+                "serving {} ({} bytes) to {}",
+                request.hash,
+                size,
+                remote.fmt_short()
+            );
+            send.write_all(&size.to_be_bytes()).await?;
+            let mut buf = vec![0u8; CHUNK_SIZE];
+            loop {
+                let read = file.read(&mut buf).await?;
+                if read == 0 {
+                    break;
+                }
+                send.write_all(&buf[..read]).await?;
+            }
+            send.finish()?;
+            // Wait for the requester to read everything and hang up, unless it stalls.
+            timeout(CLOSE_TIMEOUT, connection.closed()).await.ok();
+            Ok(())
+        })
+    }
+}
+
+/// Best guess at a file's MIME type from its extension, for previews.
+pub fn guess_mime(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("txt" | "log") => "text/plain",
+        Some("patch" | "diff") => "text/x-diff",
+        Some("md") => "text/markdown",
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
@@ -3,8 +3,12 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use super::{event::Event, sender::ChatSender, ChatNode, ChatTicket};
+use super::{
+    crypto::RoomSecret, event::Event, moderation::Moderation, sender::ChatSender,
+    ticket::RoomCreator, ChatNode, ChatTicket,
+};
 pub use iroh::NodeId;
+use iroh::{Endpoint, NodeAddr};
 pub use iroh_gossip::proto::TopicId;
 use n0_future::{boxed::BoxStream, StreamExt as _};
 use serde::{Deserialize, Serialize};
@@ -17,6 +21,8 @@ pub struct TicketOpts {
     pub include_myself: bool,
     pub include_bootstrap: bool,
     pub include_neighbors: bool,
+    /// Add how to dial the bootstrap nodes, as far as we know.
+    pub include_addrs: bool,
 }
 
 impl TicketOpts {
@@ -26,6 +32,7 @@ impl TicketOpts {
             include_myself: true,
             include_bootstrap: true,
             include_neighbors: true,
+            include_addrs: true,
         }
     }
 }
@@ -36,6 +43,10 @@ pub struct Channel {
     me: NodeId,
     bootstrap: BTreeSet<NodeId>,
     neighbors: Arc<Mutex<BTreeSet<NodeId>>>,
+    secret: Option<RoomSecret>,
+    creator: Option<RoomCreator>,
+    moderation: Moderation,
+    endpoint: Endpoint,
     sender: ChatSender,
     receiver: Option<ChatReceiver>,
 }
@@ -49,8 +60,18 @@ impl Channel {
         self.receiver.take()
     }
 
+    /// Who moderates the room and who they banned or muted.
+    pub fn moderation(&self) -> &Moderation {
+        &self.moderation
+    }
+
+    /// A ticket to join the room with, never bootstrapping from banned peers or
+    /// non-members of invite-only rooms.
     pub fn ticket(&self, opts: TicketOpts) -> anyhow::Result<ChatTicket> {
         let mut ticket = ChatTicket::new(self.topic_id, &self.name);
+        ticket.secret = self.secret.clone();
+        ticket.creator = self.creator;
+        ticket.members = self.moderation.members();
         if opts.include_myself {
             ticket.bootstrap.insert(self.me);
         }
@@ -61,14 +82,54 @@ impl Channel {
             let neighbors = self.neighbors.lock().unwrap();
             ticket.bootstrap.extend(neighbors.iter().copied())
         }
+        ticket
+            .bootstrap
+            .retain(|node| self.moderation.is_member(node) && !self.moderation.is_banned(node));
+        if opts.include_addrs {
+            ticket.addrs = ticket
+                .bootstrap
+                .iter()
+                .filter_map(|node| self.node_addr(node))
+                .collect();
+        }
         tracing::info!("opts {:?} ticket {:?}", opts, ticket);
         Ok(ticket)
     }
 
+    /// How to dial a node, as far as our endpoint knows right now.
+    fn node_addr(&self, node: &NodeId) -> Option<NodeAddr> {
+        let addr = if *node == self.me {
+            let relay_url = self.endpoint.home_relay().get().ok().flatten();
+            let direct_addresses = self
+                .endpoint
+                .direct_addresses()
+                .get()
+                .ok()
+                .flatten()
+                .unwrap_or_default();
+            NodeAddr::from_parts(
+                self.me,
+                relay_url,
+                direct_addresses.into_iter().map(|addr| addr.addr),
+            )
+        } else {
+            self.endpoint.remote_info(*node)?.into()
+        };
+        (!addr.is_empty()).then_some(addr)
+    }
+
     pub fn id(&self) -> String {
         self.topic_id.to_string()
     }
 
+    pub fn topic_id(&self) -> TopicId {
+        self.topic_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     #[allow(unused)]
     pub fn neighbors(&self) -> Vec<String> {
         self.neighbors
@@ -88,6 +149,7 @@ impl ChatNode {
         nickname: String, // user name
     ) -> anyhow::Result<Channel> {
         let (sender, receiver) = self.join(&ticket, nickname)?;
+        let moderation = self.moderation(&ticket);
         let neighbors = Arc::new(Mutex::new(BTreeSet::new()));
         let receiver_stream = build_receiver_stream(receiver, neighbors.clone());
 
@@ -100,6 +162,10 @@ impl ChatNode {
             topic_id: ticket.topic_id,
             bootstrap: ticket.bootstrap,
             neighbors,
+            secret: ticket.secret,
+            creator: moderation.room_creator(),
+            moderation,
+            endpoint: self.endpoint().clone(),
             me: self.node_id(),
             sender,
             receiver: Some(receiver_stream),
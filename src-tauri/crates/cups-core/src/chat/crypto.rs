@@ -0,0 +1,119 @@
+use std::{borrow::Cow, fmt};
+
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use serde::{Deserialize, Serialize};
+
+const NONCE_LEN: usize = 24;
+
+/// Symmetric key for an end-to-end encrypted room, carried in its [`super::ChatTicket`].
+///
+/// Anyone who can reach the gossip topic can still see that traffic is flowing,
+/// but only ticket holders can read the signed messages inside.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RoomSecret([u8; 32]);
+
+impl RoomSecret {
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// Encrypt the payload, returning the random nonce followed by the ciphertext.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher()
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow!("failed to encrypt message"))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypt and authenticate a payload produced by [`RoomSecret::encrypt`].
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            bail!("encrypted message is too short");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher()
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("failed to decrypt message"))
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0.into())
+    }
+}
+
+impl fmt::Debug for RoomSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RoomSecret(..)")
+    }
+}
+
+/// Encrypt an encoded message for broadcast if the room has a secret.
+pub fn seal(secret: Option<&RoomSecret>, data: Vec<u8>) -> Result<Vec<u8>> {
+    match secret {
+        Some(secret) => secret.encrypt(&data),
+        None => Ok(data),
+    }
+}
+
+/// Reverse of [`seal`] for a payload received from the room.
+pub fn open<'a>(secret: Option<&RoomSecret>, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+    match secret {
+        Some(secret) => secret.decrypt(data).map(Cow::Owned),
+        None => Ok(Cow::Borrowed(data)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_payloads_open_with_the_room_secret() {
+        let secret = RoomSecret::generate();
+        let sealed = seal(Some(&secret), b"hello".to_vec()).unwrap();
+        assert_ne!(sealed, b"hello");
+        assert_eq!(sealed.len(), NONCE_LEN + b"hello".len() + 16);
+        assert_eq!(open(Some(&secret), &sealed).unwrap().as_ref(), b"hello");
+    }
+
+    #[test]
+    fn sealing_twice_uses_fresh_nonces() {
+        let secret = RoomSecret::generate();
+        let first = seal(Some(&secret), b"hello".to_vec()).unwrap();
+        let second = seal(Some(&secret), b"hello".to_vec()).unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn other_secrets_cannot_open() {
+        let sealed = seal(Some(&RoomSecret::generate()), b"hello".to_vec()).unwrap();
+        assert!(open(Some(&RoomSecret::generate()), &sealed).is_err());
+    }
+
+    #[test]
+    fn tampered_payloads_are_rejected() {
+        let secret = RoomSecret::generate();
+        let mut sealed = seal(Some(&secret), b"hello".to_vec()).unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(open(Some(&secret), &sealed).is_err());
+        assert!(open(Some(&secret), &sealed[..NONCE_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn plaintext_rooms_pass_payloads_through() {
+        let sealed = seal(None, b"hello".to_vec()).unwrap();
+        assert_eq!(sealed, b"hello");
+        assert!(matches!(
+            open(None, &sealed).unwrap(),
+            Cow::Borrowed(b"hello")
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{ensure, Result};
+use iroh::{endpoint::Connection, protocol::ProtocolHandler, Endpoint, NodeId};
+use n0_future::{
+    boxed::BoxFuture,
+    time::{timeout, Duration},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use super::message::{Message, MessageId, SignedMessage};
+
+/// ALPN of the protocol used to send a message straight to a single peer.
+pub const DIRECT_ALPN: &[u8] = b"cups/direct/0";
+
+/// A direct message is one signed chat message, so anything larger is bogus.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+/// How long to wait for a peer to accept a direct message before giving up.
+pub const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events from direct conversations, emitted to the frontend as `direct-event`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum DirectEvent {
+    #[serde(rename_all = "camelCase")]
+    MessageReceived {
+        id: MessageId,
+        from: NodeId,
+        text: String,
+        nickname: String,
+        sent_timestamp: u64,
+    },
+    /// A peer on a newer build sent a direct message this one can't decode.
+    #[serde(rename_all = "camelCase")]
+    UnsupportedMessage {
+        from: NodeId,
+        version: u16,
+        sent_timestamp: u64,
+    },
+}
+
+/// Accepts direct messages from peers and passes them on as [`DirectEvent`]s.
+///
+/// The QUIC connection is encrypted and authenticated with the peers' node keys,
+/// so only messages signed by the node on the other end are accepted.
+#[derive(Debug, Clone)]
+pub struct DirectProtocol {
+    events: mpsc::Sender<DirectEvent>,
+}
+
+impl DirectProtocol {
+    pub fn new(events: mpsc::Sender<DirectEvent>) -> Self {
+        Self { events }
+    }
+}
+
+impl ProtocolHandler for DirectProtocol {
+    fn accept(&self, connection: Connection) -> BoxFuture<Result<()>> {
+        let events = self.events.clone();
+        Box::pin(async move {
+            let remote = connection.remote_node_id()?;
+            let (mut send, mut recv) = connection.accept_bi().await?;
+            let message =
+                SignedMessage::verify_and_decode(&recv.read_to_end(MAX_MESSAGE_SIZE).await?)?;
+            ensure!(
+                message.from == remote,
+                "direct message from {} was signed by {}",
+                remote.fmt_short(),
+                message.from.fmt_short()
+            );
+            let event = match message.message {
+                Some(Message::Message { text, nickname }) => DirectEvent::MessageReceived {
+                    id: message.id,
+                    from: message.from,
+                    text,
+                    nickname,
+                    sent_timestamp: message.timestamp,
+                },
+                None => DirectEvent::UnsupportedMessage {
+                    from: message.from,
+                    version: message.version,
+                    sent_timestamp: message.timestamp,
+                },
+                Some(other) => {
+                    warn!(
+                        "ignoring direct {other:?} from {}, only chat messages are expected",
+                        remote.fmt_short()
+                    );
+                    return Ok(());
+                }
+            };
+            debug!("direct message {} from {}", message.id, remote.fmt_short());
+            events.send(event).await?;
+            // An empty reply tells the sender we have the message.
+            send.finish()?;
+            timeout(SEND_TIMEOUT, connection.closed()).await.ok();
+            Ok(())
+        })
+    }
+}
+
+/// Deliver a signed message to a peer, returning once they have accepted it.
+pub async fn send(endpoint: &Endpoint, peer: NodeId, signed_message: Vec<u8>) -> Result<()> {
+    let connection = endpoint.connect(peer, DIRECT_ALPN).await?;
+    let (mut send, mut recv) = connection.open_bi().await?;
+    send.write_all(&signed_message).await?;
+    send.finish()?;
+    recv.read_to_end(0).await?;
+    connection.close(0u32.into(), b"done");
+    Ok(())
+}
@@ -0,0 +1,488 @@
+use anyhow::Context as _;
+pub use iroh::NodeId;
+use iroh_gossip::{net::GossipEvent, proto::TopicId};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    blobs::Attachment,
+    crypto::{self, RoomSecret},
+    invite::{Invite, InviteId},
+    message::{Message, MessageId, ReceiptKind, ReceivedMessage, SignedMessage},
+    moderation::ModerationAction,
+    replay::ReplayGuard,
+};
+
+/// A payload emitted to the frontend, tagged with the room it belongs to.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomEvent<T> {
+    pub topic: String,
+    #[serde(flatten)]
+    pub payload: T,
+}
+
+impl<T> RoomEvent<T> {
+    pub fn new(topic: TopicId, payload: T) -> Self {
+        Self {
+            topic: topic.to_string(),
+            payload,
+        }
+    }
+}
+
+/// Everyone currently reacting to a message with one emoji.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub senders: Vec<NodeId>,
+}
+
+/// The peers that acknowledged one message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReceipts {
+    pub message_id: MessageId,
+    pub delivered: Vec<NodeId>,
+    pub read: Vec<NodeId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    #[serde(rename_all = "camelCase")]
+    Joined {
+        neighbors: Vec<NodeId>,
+    },
+    #[serde(rename_all = "camelCase")]
+    MessageReceived {
+        id: MessageId,
+        from: NodeId,
+        text: String,
+        nickname: String,
+        sent_timestamp: u64,
+        /// The sender's clock is outside the skew tolerance, so `sent_timestamp` is suspect.
+        clock_skewed: bool,
+        /// The message this one answers, making it part of that message's thread.
+        reply_to: Option<MessageId>,
+    },
+    /// A file shared in the room, fetched on demand from the sender or a neighbor.
+    #[serde(rename_all = "camelCase")]
+    AttachmentReceived {
+        id: MessageId,
+        from: NodeId,
+        nickname: String,
+        attachment: Attachment,
+        sent_timestamp: u64,
+        clock_skewed: bool,
+    },
+    /// The author of `target_id` replaced its text.
+    #[serde(rename_all = "camelCase")]
+    MessageEdited {
+        id: MessageId,
+        from: NodeId,
+        target_id: MessageId,
+        new_text: String,
+        sent_timestamp: u64,
+        clock_skewed: bool,
+    },
+    /// The author of `target_id` deleted it.
+    #[serde(rename_all = "camelCase")]
+    MessageDeleted {
+        id: MessageId,
+        from: NodeId,
+        target_id: MessageId,
+        sent_timestamp: u64,
+        clock_skewed: bool,
+    },
+    /// A peer added or took back an emoji reaction to `target_id`.
+    #[serde(rename_all = "camelCase")]
+    Reaction {
+        id: MessageId,
+        from: NodeId,
+        target_id: MessageId,
+        emoji: String,
+        add: bool,
+        sent_timestamp: u64,
+    },
+    /// The reactions to `target_id` changed. Not received from peers, but sent in
+    /// place of [`Event::Reaction`]s once they have been aggregated.
+    #[serde(rename_all = "camelCase")]
+    ReactionSummary {
+        target_id: MessageId,
+        reactions: Vec<ReactionCount>,
+    },
+    /// A peer started or stopped typing.
+    #[serde(rename_all = "camelCase")]
+    Typing {
+        from: NodeId,
+        active: bool,
+        sent_timestamp: u64,
+    },
+    /// A peer acknowledged a batch of messages.
+    #[serde(rename_all = "camelCase")]
+    Receipt {
+        from: NodeId,
+        message_ids: Vec<MessageId>,
+        kind: ReceiptKind,
+        sent_timestamp: u64,
+    },
+    /// Who has acknowledged some messages now. Not received from peers, but sent in
+    /// place of [`Event::Receipt`]s once they have been recorded.
+    #[serde(rename_all = "camelCase")]
+    ReceiptsUpdated {
+        receipts: Vec<MessageReceipts>,
+    },
+    /// A peer announced it is leaving the room.
+    #[serde(rename_all = "camelCase")]
+    Left {
+        from: NodeId,
+        sent_timestamp: u64,
+    },
+    /// A room admin acted on `target`.
+    #[serde(rename_all = "camelCase")]
+    Moderation {
+        id: MessageId,
+        from: NodeId,
+        target: NodeId,
+        action: ModerationAction,
+        sent_timestamp: u64,
+    },
+    /// A newcomer joined with an invite that holds.
+    #[serde(rename_all = "camelCase")]
+    InviteRedeemed {
+        id: MessageId,
+        from: NodeId,
+        invite_id: InviteId,
+        invite: Invite,
+        sent_timestamp: u64,
+    },
+    /// A peer revoked an invite, which counts if it issued the invite or is an admin.
+    #[serde(rename_all = "camelCase")]
+    InviteRevoked {
+        id: MessageId,
+        from: NodeId,
+        invite_id: InviteId,
+        sent_timestamp: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    Presence {
+        from: NodeId,
+        nickname: String,
+        sent_timestamp: u64,
+        version: u16,
+    },
+    /// A peer on a newer build sent a message this one can't decode.
+    #[serde(rename_all = "camelCase")]
+    UnsupportedMessage {
+        from: NodeId,
+        version: u16,
+        sent_timestamp: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    NeighborUp {
+        node_id: NodeId,
+    },
+    #[serde(rename_all = "camelCase")]
+    NeighborDown {
+        node_id: NodeId,
+    },
+    Lagged,
+    #[serde(rename_all = "camelCase")]
+    Errorred {
+        message: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Disconnected,
+    /// The room's stream ended and we will try to rejoin after `delay_ms`.
+    #[serde(rename_all = "camelCase")]
+    Reconnecting {
+        attempt: u32,
+        delay_ms: u64,
+    },
+    /// We rejoined the room after it was disconnected.
+    #[serde(rename_all = "camelCase")]
+    Reconnected,
+    /// Past messages served by a neighbor after we joined, oldest first.
+    #[serde(rename_all = "camelCase")]
+    Backfill {
+        from: NodeId,
+        messages: Vec<Event>,
+    },
+}
+
+impl Event {
+    /// Convert a gossip event, decrypting its payload first if the room has a secret
+    /// and dropping replayed or stale messages.
+    pub fn from_gossip(
+        event: iroh_gossip::net::Event,
+        secret: Option<&RoomSecret>,
+        guard: &mut ReplayGuard,
+    ) -> anyhow::Result<Self> {
+        let converted = match event {
+            iroh_gossip::net::Event::Gossip(event) => match event {
+                GossipEvent::Joined(neighbors) => Self::Joined { neighbors },
+                GossipEvent::NeighborUp(node_id) => Self::NeighborUp { node_id },
+                GossipEvent::NeighborDown(node_id) => Self::NeighborDown { node_id },
+                GossipEvent::Received(message) => {
+                    let message = decode_payload(&message.content, secret)?;
+                    let clock_skewed = guard.check(&message)?;
+                    Self::from_message(message, clock_skewed)
+                }
+            },
+            iroh_gossip::net::Event::Lagged => Self::Lagged,
+        };
+        Ok(converted)
+    }
+
+    /// Convert the payloads a neighbor served us from its history, keeping only
+    /// valid chat messages we haven't already seen.
+    pub fn from_backfill(
+        from: NodeId,
+        payloads: &[Vec<u8>],
+        secret: Option<&RoomSecret>,
+        guard: &mut ReplayGuard,
+    ) -> Self {
+        let messages = payloads
+            .iter()
+            .filter_map(|payload| match decode_payload(payload, secret) {
+                Ok(message) => Some(message),
+                Err(err) => {
+                    tracing::warn!("received invalid backfill message: {err}");
+                    None
+                }
+            })
+            .filter(|message| message.message.as_ref().is_some_and(Message::is_stored))
+            .filter(|message| guard.check_backfill(message))
+            .map(|message| Self::from_message(message, false))
+            .collect();
+        Self::Backfill { from, messages }
+    }
+
+    /// Id, sender and send time of events that belong in the room's history.
+    pub fn history_entry(&self) -> Option<(MessageId, NodeId, u64)> {
+        match self {
+            Self::MessageReceived {
+                id,
+                from,
+                sent_timestamp,
+                ..
+            }
+            | Self::AttachmentReceived {
+                id,
+                from,
+                sent_timestamp,
+                ..
+            }
+            | Self::MessageEdited {
+                id,
+                from,
+                sent_timestamp,
+                ..
+            }
+            | Self::MessageDeleted {
+                id,
+                from,
+                sent_timestamp,
+                ..
+            }
+            | Self::Reaction {
+                id,
+                from,
+                sent_timestamp,
+                ..
+            }
+            | Self::InviteRedeemed {
+                id,
+                from,
+                sent_timestamp,
+                ..
+            }
+            | Self::InviteRevoked {
+                id,
+                from,
+                sent_timestamp,
+                ..
+            } => Some((*id, *from, *sent_timestamp)),
+            Self::Moderation {
+                id,
+                from,
+                action,
+                sent_timestamp,
+                ..
+            } if *action != ModerationAction::Kick => Some((*id, *from, *sent_timestamp)),
+            _ => None,
+        }
+    }
+
+    /// The peer an event comes from, if it comes from a single peer.
+    pub fn sender(&self) -> Option<NodeId> {
+        match self {
+            Self::MessageReceived { from, .. }
+            | Self::AttachmentReceived { from, .. }
+            | Self::MessageEdited { from, .. }
+            | Self::MessageDeleted { from, .. }
+            | Self::Reaction { from, .. }
+            | Self::Typing { from, .. }
+            | Self::Receipt { from, .. }
+            | Self::Left { from, .. }
+            | Self::Moderation { from, .. }
+            | Self::InviteRedeemed { from, .. }
+            | Self::InviteRevoked { from, .. }
+            | Self::Presence { from, .. }
+            | Self::UnsupportedMessage { from, .. } => Some(*from),
+            Self::NeighborUp { node_id } => Some(*node_id),
+            _ => None,
+        }
+    }
+
+    /// Sender and target of edits and deletions, which only the target's author may send.
+    pub fn amendment(&self) -> Option<(NodeId, MessageId)> {
+        match self {
+            Self::MessageEdited {
+                from, target_id, ..
+            }
+            | Self::MessageDeleted {
+                from, target_id, ..
+            } => Some((*from, *target_id)),
+            _ => None,
+        }
+    }
+
+    fn from_message(message: ReceivedMessage, clock_skewed: bool) -> Self {
+        match message.message {
+            Some(Message::Presence { nickname }) => Self::Presence {
+                from: message.from,
+                nickname,
+                sent_timestamp: message.timestamp,
+                version: message.version,
+            },
+            Some(Message::Message { text, nickname }) => Self::MessageReceived {
+                id: message.id,
+                from: message.from,
+                text,
+                nickname,
+                sent_timestamp: message.timestamp,
+                clock_skewed,
+                reply_to: None,
+            },
+            Some(Message::Reply {
+                text,
+                nickname,
+                reply_to,
+            }) => Self::MessageReceived {
+                id: message.id,
+                from: message.from,
+                text,
+                nickname,
+                sent_timestamp: message.timestamp,
+                clock_skewed,
+                reply_to: Some(reply_to),
+            },
+            Some(Message::Attachment {
+                hash,
+                name,
+                size,
+                mime,
+                nickname,
+            }) => Self::AttachmentReceived {
+                id: message.id,
+                from: message.from,
+                nickname,
+                attachment: Attachment {
+                    hash,
+                    name,
+                    size,
+                    mime,
+                },
+                sent_timestamp: message.timestamp,
+                clock_skewed,
+            },
+            Some(Message::Edit {
+                target_id,
+                new_text,
+            }) => Self::MessageEdited {
+                id: message.id,
+                from: message.from,
+                target_id,
+                new_text,
+                sent_timestamp: message.timestamp,
+                clock_skewed,
+            },
+            Some(Message::Delete { target_id }) => Self::MessageDeleted {
+                id: message.id,
+                from: message.from,
+                target_id,
+                sent_timestamp: message.timestamp,
+                clock_skewed,
+            },
+            Some(Message::Reaction {
+                target_id,
+                emoji,
+                add,
+            }) => Self::Reaction {
+                id: message.id,
+                from: message.from,
+                target_id,
+                emoji,
+                add,
+                sent_timestamp: message.timestamp,
+            },
+            Some(Message::Typing { active }) => Self::Typing {
+                from: message.from,
+                active,
+                sent_timestamp: message.timestamp,
+            },
+            Some(Message::Receipt { up_to_ids, kind }) => Self::Receipt {
+                from: message.from,
+                message_ids: up_to_ids,
+                kind,
+                sent_timestamp: message.timestamp,
+            },
+            Some(Message::Leave) => Self::Left {
+                from: message.from,
+                sent_timestamp: message.timestamp,
+            },
+            Some(
+                control @ (Message::Grant { .. }
+                | Message::Kick { .. }
+                | Message::Ban { .. }
+                | Message::Mute { .. }
+                | Message::Member { .. }),
+            ) => {
+                let (target, action) =
+                    ModerationAction::from_message(&control).expect("a moderation message");
+                Self::Moderation {
+                    id: message.id,
+                    from: message.from,
+                    target,
+                    action,
+                    sent_timestamp: message.timestamp,
+                }
+            }
+            Some(Message::Redeem { invite }) => Self::InviteRedeemed {
+                id: message.id,
+                from: message.from,
+                invite_id: invite.id(),
+                invite,
+                sent_timestamp: message.timestamp,
+            },
+            Some(Message::RevokeInvite { invite_id }) => Self::InviteRevoked {
+                id: message.id,
+                from: message.from,
+                invite_id,
+                sent_timestamp: message.timestamp,
+            },
+            None => Self::UnsupportedMessage {
+                from: message.from,
+                version: message.version,
+                sent_timestamp: message.timestamp,
+            },
+        }
+    }
+}
+
+/// Decrypt a payload broadcast on the room if it has a secret, then verify its signature.
+fn decode_payload(payload: &[u8], secret: Option<&RoomSecret>) -> anyhow::Result<ReceivedMessage> {
+    let content = crypto::open(secret, payload).context("failed to decrypt message")?;
+    SignedMessage::verify_and_decode(&content).context("failed to parse and verify signed message")
+}
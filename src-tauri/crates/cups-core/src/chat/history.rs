@@ -0,0 +1,223 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use iroh::{endpoint::Connection, protocol::ProtocolHandler, Endpoint, NodeId};
+use iroh_gossip::proto::TopicId;
+use n0_future::{
+    boxed::BoxFuture,
+    time::{timeout, Duration},
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use super::{message::MessageId, moderation::ModerationStore};
+
+/// ALPN of the protocol late joiners use to ask neighbors for past messages.
+pub const HISTORY_ALPN: &[u8] = b"cups/history/0";
+
+/// Most messages kept per room for serving to late joiners.
+const MAX_STORED: usize = 1_000;
+/// Most messages sent back in a single response.
+const MAX_SERVED: usize = 200;
+/// Most bytes of (possibly encrypted) payloads sent back in a single response.
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+/// A request is a topic, a cursor and a limit, so anything larger is bogus.
+const MAX_REQUEST_SIZE: usize = 1024;
+/// How long to wait for a neighbor to answer before giving up on it.
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a late joiner wants history to start from. Other cursors can be added
+/// as variants without breaking the request's encoding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Since {
+    /// Messages sent after this Unix timestamp in micros.
+    Timestamp(u64),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HistoryRequest {
+    topic: TopicId,
+    since: Since,
+    limit: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HistoryResponse {
+    /// Message payloads exactly as they were broadcast, oldest first.
+    payloads: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredMessage {
+    id: MessageId,
+    from: NodeId,
+    timestamp: u64,
+    payload: Vec<u8>,
+    /// The message this one deletes, if it's a deletion.
+    deletes: Option<MessageId>,
+}
+
+/// Recent chat messages of each room we are in, kept as broadcast so they can be
+/// re-verified (and decrypted, for encrypted rooms) by whoever we serve them to.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryStore(Arc<Mutex<HashMap<TopicId, VecDeque<StoredMessage>>>>);
+
+impl HistoryStore {
+    /// Remember a verified chat message, evicting the oldest if the room is full.
+    /// A message its author deleted before it got here is left out, only the
+    /// deletion is served.
+    pub fn insert(
+        &self,
+        topic: TopicId,
+        id: MessageId,
+        from: NodeId,
+        timestamp: u64,
+        payload: Vec<u8>,
+        deletes: Option<MessageId>,
+    ) {
+        let mut rooms = self.0.lock().expect("poisened");
+        let messages = rooms.entry(topic).or_default();
+        let deleted = |message: &StoredMessage| message.deletes == Some(id) && message.from == from;
+        if messages
+            .iter()
+            .any(|message| message.id == id || deleted(message))
+        {
+            return;
+        }
+        messages.push_back(StoredMessage {
+            id,
+            from,
+            timestamp,
+            payload,
+            deletes,
+        });
+        if messages.len() > MAX_STORED {
+            messages.pop_front();
+        }
+    }
+
+    /// Whether `from` may edit or delete `target`, which is only refused if we hold
+    /// the target and someone else sent it. Amendments of messages we don't hold are
+    /// left to whoever gets the message to check, as it may be older than we keep or
+    /// yet to arrive.
+    pub fn may_amend(&self, topic: &TopicId, target: &MessageId, from: &NodeId) -> bool {
+        let rooms = self.0.lock().expect("poisened");
+        rooms
+            .get(topic)
+            .and_then(|messages| messages.iter().find(|message| message.id == *target))
+            .is_none_or(|message| message.from == *from)
+    }
+
+    /// Forget a deleted message so only its tombstone is served from now on.
+    pub fn remove(&self, topic: &TopicId, target: &MessageId) {
+        let mut rooms = self.0.lock().expect("poisened");
+        if let Some(messages) = rooms.get_mut(topic) {
+            messages.retain(|message| message.id != *target);
+        }
+    }
+
+    /// Timestamp of the newest message we hold for a room.
+    pub fn latest(&self, topic: &TopicId) -> Option<u64> {
+        let rooms = self.0.lock().expect("poisened");
+        rooms
+            .get(topic)
+            .and_then(|messages| messages.iter().map(|message| message.timestamp).max())
+    }
+
+    /// The newest payloads after `since`, oldest first, within the serving limits.
+    fn since(&self, topic: &TopicId, since: &Since, limit: usize) -> Vec<Vec<u8>> {
+        let rooms = self.0.lock().expect("poisened");
+        let Some(messages) = rooms.get(topic) else {
+            return Vec::new();
+        };
+        let matching: Vec<_> = match since {
+            Since::Timestamp(timestamp) => messages
+                .iter()
+                .filter(|message| message.timestamp > *timestamp)
+                .collect(),
+        };
+        let mut size = 0;
+        let mut payloads: Vec<_> = matching
+            .into_iter()
+            .rev()
+            .take(limit.min(MAX_SERVED))
+            .take_while(|message| {
+                size += message.payload.len();
+                size <= MAX_RESPONSE_SIZE
+            })
+            .map(|message| message.payload.clone())
+            .collect();
+        payloads.reverse();
+        payloads
+    }
+}
+
+/// Serves room history from the [`HistoryStore`] to neighbors that ask for it.
+#[derive(Debug, Clone)]
+pub struct HistoryProtocol {
+    store: HistoryStore,
+    moderation: ModerationStore,
+}
+
+impl HistoryProtocol {
+    pub fn new(store: HistoryStore, moderation: ModerationStore) -> Self {
+        Self { store, moderation }
+    }
+}
+
+impl ProtocolHandler for HistoryProtocol {
+    fn accept(&self, connection: Connection) -> BoxFuture<Result<()>> {
+        let store = self.store.clone();
+        let moderation = self.moderation.clone();
+        Box::pin(async move {
+            let remote = connection.remote_node_id()?;
+            let (mut send, mut recv) = connection.accept_bi().await?;
+            let request: HistoryRequest =
+                postcard::from_bytes(&recv.read_to_end(MAX_REQUEST_SIZE).await?)?;
+            // Only members of rooms we are in get their history, and nothing is
+            // served to peers the room's moderation keeps out.
+            let payloads = match moderation.serves(&request.topic, &remote) {
+                true => store.since(&request.topic, &request.since, request.limit as usize),
+                false => Vec::new(),
+            };
+            debug!(
+                "serving {} messages of {} to {}",
+                payloads.len(),
+                request.topic,
+                remote.fmt_short()
+            );
+            let response = postcard::to_stdvec(&HistoryResponse { payloads })?;
+            send.write_all(&response).await?;
+            send.finish()?;
+            // Wait for the requester to read everything and hang up, unless it stalls.
+            timeout(FETCH_TIMEOUT, connection.closed()).await.ok();
+            Ok(())
+        })
+    }
+}
+
+/// Ask a neighbor for the message payloads it holds for a room.
+pub async fn fetch(
+    endpoint: &Endpoint,
+    peer: NodeId,
+    topic: TopicId,
+    since: Since,
+) -> Result<Vec<Vec<u8>>> {
+    let connection = endpoint.connect(peer, HISTORY_ALPN).await?;
+    let (mut send, mut recv) = connection.open_bi().await?;
+    let request = HistoryRequest {
+        topic,
+        since,
+        limit: MAX_SERVED as u32,
+    };
+    send.write_all(&postcard::to_stdvec(&request)?).await?;
+    send.finish()?;
+    // Allow for postcard's length prefixes on top of the payload limit.
+    let response: HistoryResponse =
+        postcard::from_bytes(&recv.read_to_end(MAX_RESPONSE_SIZE * 2).await?)?;
+    connection.close(0u32.into(), b"done");
+    Ok(response.payloads)
+}
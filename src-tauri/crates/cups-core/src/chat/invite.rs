@@ -0,0 +1,91 @@
+use anyhow::{ensure, Result};
+use iroh::{NodeId, SecretKey};
+use iroh_base::Signature;
+use iroh_gossip::proto::TopicId;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::hex_id;
+
+/// What an invite lets its holders do, as signed by the member who issued it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteTerms {
+    pub topic_id: TopicId,
+    pub issuer: NodeId,
+    /// Tells apart invites issued with the same terms.
+    nonce: [u8; 16],
+    /// When the invite stops letting newcomers in, in micros.
+    pub expires_at: Option<u64>,
+    /// How many newcomers the invite lets in.
+    pub max_uses: Option<u32>,
+}
+
+/// Limits on who may join a room with a [`super::ChatTicket`], checked by its
+/// members when a newcomer announces the invite it joined with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invite {
+    terms: InviteTerms,
+    signature: Signature,
+}
+
+impl Invite {
+    /// Issue an invite to a room, signed with our key.
+    pub fn issue(
+        secret_key: &SecretKey,
+        topic_id: TopicId,
+        expires_at: Option<u64>,
+        max_uses: Option<u32>,
+    ) -> Result<Self> {
+        let terms = InviteTerms {
+            topic_id,
+            issuer: secret_key.public(),
+            nonce: rand::random(),
+            expires_at,
+            max_uses,
+        };
+        let signature = secret_key.sign(&postcard::to_stdvec(&terms)?);
+        Ok(Self { terms, signature })
+    }
+
+    pub fn terms(&self) -> &InviteTerms {
+        &self.terms
+    }
+
+    pub fn id(&self) -> InviteId {
+        InviteId::from_terms(&self.terms)
+    }
+
+    pub fn is_expired(&self, at: u64) -> bool {
+        self.terms
+            .expires_at
+            .is_some_and(|expires_at| at > expires_at)
+    }
+
+    /// Check the invite was signed by its issuer for the room and hasn't expired
+    /// by `at`. Whether the issuer may invite and the invite is still unused and
+    /// unrevoked is up to the room's [`super::Moderation`].
+    pub fn validate(&self, topic_id: TopicId, at: u64) -> Result<()> {
+        ensure!(
+            self.terms.topic_id == topic_id,
+            "invite is for another room"
+        );
+        self.terms
+            .issuer
+            .verify(&postcard::to_stdvec(&self.terms)?, &self.signature)?;
+        ensure!(!self.is_expired(at), "invite has expired");
+        Ok(())
+    }
+}
+
+/// Unique identifier of an invite, derived from the hash of its terms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InviteId([u8; 32]);
+
+impl InviteId {
+    fn from_terms(terms: &InviteTerms) -> Self {
+        let bytes = postcard::to_stdvec(terms).expect("invite terms always encode");
+        Self(*blake3::hash(&bytes).as_bytes())
+    }
+}
+
+hex_id!(InviteId);
@@ -0,0 +1,472 @@
+use anyhow::{ensure, Result};
+pub use iroh::NodeId;
+use iroh::{PublicKey, SecretKey};
+use iroh_base::Signature;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    blobs::BlobHash,
+    invite::{Invite, InviteId},
+};
+use crate::utils::{get_timestamp, hex_id};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedMessage {
+    from: PublicKey,
+    data: Vec<u8>,
+    signature: Signature,
+}
+
+impl SignedMessage {
+    pub fn verify_and_decode(bytes: &[u8]) -> Result<ReceivedMessage> {
+        // Postcard ignores whatever follows a value, which would let anyone relaying
+        // the message pad it into one with a different id.
+        let (signed_message, rest): (Self, _) = postcard::take_from_bytes(bytes)?;
+        ensure!(
+            rest.is_empty(),
+            "{} trailing bytes after message",
+            rest.len()
+        );
+        let key: PublicKey = signed_message.from;
+        key.verify(&signed_message.data, &signed_message.signature)?;
+        let (wire_message, trailer) = postcard::take_from_bytes(&signed_message.data)?;
+        let (version, timestamp, message) = match wire_message {
+            // Versioned builds trail the frame with their version, legacy ones don't.
+            WireMessage::VO { timestamp, message } => {
+                let version = postcard::from_bytes(trailer).unwrap_or(0);
+                (version, timestamp, Some(message.into()))
+            }
+            WireMessage::V1 {
+                version,
+                timestamp,
+                message,
+            } => match postcard::from_bytes(&message) {
+                Ok(message) => (version, timestamp, Some(message)),
+                // A newer peer may send messages we don't know about yet.
+                Err(_) if is_unknown(&message) => (version, timestamp, None),
+                Err(err) => return Err(err.into()),
+            },
+        };
+        Ok(ReceivedMessage {
+            id: MessageId::from_signed(&postcard::to_stdvec(&signed_message)?),
+            from: signed_message.from,
+            version,
+            timestamp,
+            message,
+        })
+    }
+
+    /// Returns the encoded message and the timestamp it was signed with.
+    pub fn sign_and_encode(secret_key: &SecretKey, message: Message) -> Result<(Vec<u8>, u64)> {
+        let timestamp = get_timestamp();
+        let data = match LegacyMessage::try_from(message) {
+            // Legacy peers read what they understand from the frame and ignore the
+            // version trailing it, so they keep seeing us chat and come online.
+            Ok(message) => {
+                let mut data = postcard::to_stdvec(&WireMessage::VO { timestamp, message })?;
+                data.extend(postcard::to_stdvec(&PROTOCOL_VERSION)?);
+                data
+            }
+            Err(message) => postcard::to_stdvec(&WireMessage::V1 {
+                version: PROTOCOL_VERSION,
+                timestamp,
+                message: postcard::to_stdvec(&message)?,
+            })?,
+        };
+        let signature = secret_key.sign(&data);
+        let from: PublicKey = secret_key.public();
+        let signed_message = Self {
+            from,
+            data,
+            signature,
+        };
+        let encoded = postcard::to_stdvec(&signed_message)?;
+        Ok((encoded, timestamp))
+    }
+}
+
+/// Version of the wire encoding spoken by this build.
+///
+/// Only bump this when the envelope or the encoding of existing messages changes.
+/// Messages added since are told apart from corrupt ones by [`MESSAGE_VARIANTS`].
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Number of [`Message`] variants this build knows. Postcard tags a variant with
+/// its index, so one tagged past these was added by a newer build.
+const MESSAGE_VARIANTS: u32 = 17;
+
+/// Longest emoji sequence accepted in a [`Message::Reaction`], in bytes.
+pub const MAX_REACTION_LEN: usize = 64;
+
+/// Most message ids acknowledged by a single [`Message::Receipt`].
+pub const MAX_RECEIPT_BATCH: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum WireMessage {
+    /// Unversioned frame sent by builds predating [`PROTOCOL_VERSION`], and by
+    /// newer ones for the messages those builds understand.
+    VO {
+        timestamp: u64,
+        message: LegacyMessage,
+    },
+    /// The message is encoded separately from the envelope so that the sender,
+    /// version and timestamp can always be read, even if the message can't.
+    V1 {
+        version: u16,
+        timestamp: u64,
+        message: Vec<u8>,
+    },
+}
+
+/// New variants must only ever be appended, and [`MESSAGE_VARIANTS`] bumped.
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(clippy::enum_variant_names)] // `Message::Message` is part of the wire format
+pub enum Message {
+    Presence {
+        nickname: String,
+    },
+    Message {
+        text: String,
+        nickname: String,
+    },
+    /// A file fetched separately over [`super::blobs::BLOBS_ALPN`].
+    Attachment {
+        hash: BlobHash,
+        name: String,
+        size: u64,
+        mime: String,
+        nickname: String,
+    },
+    /// Replaces the text of one of the sender's own messages.
+    Edit {
+        target_id: MessageId,
+        new_text: String,
+    },
+    /// Retracts one of the sender's own messages, kept as a tombstone.
+    Delete {
+        target_id: MessageId,
+    },
+    /// A chat message answering `reply_to`, starting or continuing its thread.
+    Reply {
+        text: String,
+        nickname: String,
+        reply_to: MessageId,
+    },
+    /// Adds or takes back an emoji reaction to `target_id`.
+    Reaction {
+        target_id: MessageId,
+        emoji: String,
+        add: bool,
+    },
+    /// Whether the sender is typing, sent in place of a presence and never stored.
+    Typing {
+        active: bool,
+    },
+    /// Acknowledges a batch of messages, never stored.
+    Receipt {
+        up_to_ids: Vec<MessageId>,
+        kind: ReceiptKind,
+    },
+    /// The sender is leaving the room, sent before unsubscribing and never stored.
+    Leave,
+    /// Makes `node_id` a room admin or takes it back, only honoured from the room's creator.
+    Grant {
+        node_id: NodeId,
+        admin: bool,
+    },
+    /// Asks `node_id` to leave the room, only honoured from admins.
+    Kick {
+        node_id: NodeId,
+    },
+    /// Bans or unbans `node_id`, whose messages everyone then drops.
+    Ban {
+        node_id: NodeId,
+        banned: bool,
+    },
+    /// Mutes or unmutes `node_id`, whose chat messages everyone then drops.
+    Mute {
+        node_id: NodeId,
+        muted: bool,
+    },
+    /// Lets `node_id` into an invite-only room or removes it, only honoured from admins.
+    Member {
+        node_id: NodeId,
+        member: bool,
+    },
+    /// Announces the invite the sender joined with, for members to check.
+    Redeem {
+        invite: Invite,
+    },
+    /// Stops an invite from letting anyone else in, only honoured from its issuer or admins.
+    RevokeInvite {
+        invite_id: InviteId,
+    },
+}
+
+/// What a [`Message::Receipt`] acknowledges. Reading a message implies it was delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+impl ReceiptKind {
+    /// Name of the kind as stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Delivered => "delivered",
+            Self::Read => "read",
+        }
+    }
+}
+
+impl Message {
+    /// Whether the message is part of the room's history, kept and served to late joiners.
+    pub fn is_stored(&self) -> bool {
+        matches!(
+            self,
+            Self::Message { .. }
+                | Self::Reply { .. }
+                | Self::Attachment { .. }
+                | Self::Edit { .. }
+                | Self::Delete { .. }
+                | Self::Reaction { .. }
+                | Self::Grant { .. }
+                | Self::Ban { .. }
+                | Self::Mute { .. }
+                | Self::Member { .. }
+                | Self::Redeem { .. }
+                | Self::RevokeInvite { .. }
+        )
+    }
+
+    /// The message a deletion retracts.
+    pub fn deleted(&self) -> Option<MessageId> {
+        match self {
+            Self::Delete { target_id } => Some(*target_id),
+            _ => None,
+        }
+    }
+
+    /// The message an edit or deletion applies to, which only its author may amend.
+    pub fn target(&self) -> Option<MessageId> {
+        match self {
+            Self::Edit { target_id, .. } | Self::Delete { target_id } => Some(*target_id),
+            _ => None,
+        }
+    }
+}
+
+/// Whether an encoded [`Message`] is of a variant added after this build.
+fn is_unknown(message: &[u8]) -> bool {
+    postcard::take_from_bytes::<u32>(message).is_ok_and(|(tag, _)| tag >= MESSAGE_VARIANTS)
+}
+
+/// The message set understood by [`WireMessage::VO`] peers, frozen in place.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum LegacyMessage {
+    Presence { nickname: String },
+    Message { text: String, nickname: String },
+}
+
+impl From<LegacyMessage> for Message {
+    fn from(message: LegacyMessage) -> Self {
+        match message {
+            LegacyMessage::Presence { nickname } => Self::Presence { nickname },
+            LegacyMessage::Message { text, nickname } => Self::Message { text, nickname },
+        }
+    }
+}
+
+/// Hands the message back if it doesn't fit the legacy layout.
+impl TryFrom<Message> for LegacyMessage {
+    type Error = Message;
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
+        match message {
+            Message::Presence { nickname } => Ok(Self::Presence { nickname }),
+            Message::Message { text, nickname } => Ok(Self::Message { text, nickname }),
+            message => Err(message),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReceivedMessage {
+    pub id: MessageId,
+    /// Protocol version the sender was speaking, `0` for legacy peers.
+    pub version: u16,
+    pub timestamp: u64,
+    pub from: NodeId,
+    /// `None` if the sender runs a newer protocol with a message we can't decode.
+    pub message: Option<Message>,
+}
+
+/// Unique identifier of a chat message, derived from the hash of its signed bytes
+/// as this build encodes them, so only the signer can mint a new id for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MessageId([u8; 32]);
+
+impl MessageId {
+    /// Derive the id of an encoded [`SignedMessage`].
+    pub fn from_signed(bytes: &[u8]) -> Self {
+        Self(*blake3::hash(bytes).as_bytes())
+    }
+}
+
+hex_id!(MessageId);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::testing::secret_key;
+
+    /// Sign a frame put together by hand, as other builds would.
+    fn sign(secret_key: &SecretKey, data: Vec<u8>) -> Vec<u8> {
+        let signature = secret_key.sign(&data);
+        postcard::to_stdvec(&SignedMessage {
+            from: secret_key.public(),
+            data,
+            signature,
+        })
+        .unwrap()
+    }
+
+    fn data(bytes: &[u8]) -> Vec<u8> {
+        postcard::from_bytes::<SignedMessage>(bytes).unwrap().data
+    }
+
+    #[test]
+    fn legacy_messages_are_sent_in_frames_legacy_peers_read() {
+        let key = secret_key();
+        let message = Message::Message {
+            text: "hi".to_string(),
+            nickname: "alice".to_string(),
+        };
+        let (bytes, _) = SignedMessage::sign_and_encode(&key, message).unwrap();
+        let wire: WireMessage = postcard::from_bytes(&data(&bytes)).unwrap();
+        assert!(matches!(
+            wire,
+            WireMessage::VO {
+                message: LegacyMessage::Message { .. },
+                ..
+            }
+        ));
+        let received = SignedMessage::verify_and_decode(&bytes).unwrap();
+        assert_eq!(received.from, key.public());
+        assert_eq!(received.version, PROTOCOL_VERSION);
+        assert!(matches!(
+            received.message,
+            Some(Message::Message { text, .. }) if text == "hi"
+        ));
+    }
+
+    #[test]
+    fn other_messages_are_sent_versioned() {
+        let (bytes, _) =
+            SignedMessage::sign_and_encode(&secret_key(), Message::Typing { active: true })
+                .unwrap();
+        let wire: WireMessage = postcard::from_bytes(&data(&bytes)).unwrap();
+        assert!(matches!(
+            wire,
+            WireMessage::V1 {
+                version: PROTOCOL_VERSION,
+                ..
+            }
+        ));
+        let received = SignedMessage::verify_and_decode(&bytes).unwrap();
+        assert_eq!(received.version, PROTOCOL_VERSION);
+        assert!(matches!(
+            received.message,
+            Some(Message::Typing { active: true })
+        ));
+    }
+
+    #[test]
+    fn legacy_frames_without_a_version_are_version_zero() {
+        let frame = WireMessage::VO {
+            timestamp: 1,
+            message: LegacyMessage::Presence {
+                nickname: "bob".to_string(),
+            },
+        };
+        let bytes = sign(&secret_key(), postcard::to_stdvec(&frame).unwrap());
+        let received = SignedMessage::verify_and_decode(&bytes).unwrap();
+        assert_eq!(received.version, 0);
+        assert_eq!(received.timestamp, 1);
+        assert!(matches!(
+            received.message,
+            Some(Message::Presence { nickname }) if nickname == "bob"
+        ));
+    }
+
+    #[test]
+    fn unknown_messages_from_newer_peers_are_kept_undecoded() {
+        let frame = WireMessage::V1 {
+            version: PROTOCOL_VERSION,
+            timestamp: 1,
+            message: postcard::to_stdvec(&(MESSAGE_VARIANTS, "new")).unwrap(),
+        };
+        let bytes = sign(&secret_key(), postcard::to_stdvec(&frame).unwrap());
+        let received = SignedMessage::verify_and_decode(&bytes).unwrap();
+        assert_eq!(received.version, PROTOCOL_VERSION);
+        assert!(received.message.is_none());
+    }
+
+    #[test]
+    fn known_messages_that_fail_to_decode_are_rejected() {
+        // A chat message missing its text and nickname.
+        let frame = WireMessage::V1 {
+            version: PROTOCOL_VERSION,
+            timestamp: 1,
+            message: postcard::to_stdvec(&1u32).unwrap(),
+        };
+        let bytes = sign(&secret_key(), postcard::to_stdvec(&frame).unwrap());
+        assert!(SignedMessage::verify_and_decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn ids_are_hex_for_the_frontend_and_raw_bytes_on_the_wire() {
+        let id = MessageId::from_signed(b"hi");
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, format!("\"{id}\""));
+        assert_eq!(serde_json::from_str::<MessageId>(&json).unwrap(), id);
+        let bytes = postcard::to_stdvec(&id).unwrap();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(postcard::from_bytes::<MessageId>(&bytes).unwrap(), id);
+    }
+
+    #[test]
+    fn every_variant_is_counted() {
+        let last = Message::RevokeInvite {
+            invite_id: "00".repeat(32).parse().unwrap(),
+        };
+        let (tag, _) =
+            postcard::take_from_bytes::<u32>(&postcard::to_stdvec(&last).unwrap()).unwrap();
+        assert_eq!(tag, MESSAGE_VARIANTS - 1);
+    }
+
+    #[test]
+    fn padded_messages_are_rejected() {
+        let message = Message::Presence {
+            nickname: "alice".to_string(),
+        };
+        let (mut bytes, _) = SignedMessage::sign_and_encode(&secret_key(), message).unwrap();
+        let id = SignedMessage::verify_and_decode(&bytes).unwrap().id;
+        assert_eq!(id, MessageId::from_signed(&bytes));
+        bytes.push(0);
+        assert!(SignedMessage::verify_and_decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn forged_messages_are_rejected() {
+        let message = Message::Presence {
+            nickname: "alice".to_string(),
+        };
+        let (bytes, _) = SignedMessage::sign_and_encode(&secret_key(), message).unwrap();
+        let mut signed: SignedMessage = postcard::from_bytes(&bytes).unwrap();
+        signed.from = secret_key().public();
+        let forged = postcard::to_stdvec(&signed).unwrap();
+        assert!(SignedMessage::verify_and_decode(&forged).is_err());
+    }
+}
@@ -0,0 +1,839 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Result};
+use iroh::NodeId;
+use iroh_gossip::proto::TopicId;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    event::Event,
+    invite::{Invite, InviteId},
+    message::Message,
+    ticket::RoomCreator,
+    ChatTicket,
+};
+
+/// What a moderator did to a member of a room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ModerationAction {
+    /// Made an admin, which only the room's creator may do.
+    Grant,
+    /// No longer an admin, which only the room's creator may do.
+    Revoke,
+    /// Asked to leave the room, free to rejoin once it has. Until then, everything
+    /// it sends but its goodbye is dropped.
+    Kick,
+    /// Everything they send is dropped, and they are left out of tickets.
+    Ban,
+    Unban,
+    /// Their chat messages and typing updates are dropped.
+    Mute,
+    Unmute,
+    /// Let into an invite-only room.
+    AddMember,
+    /// No longer let into an invite-only room.
+    RemoveMember,
+}
+
+impl ModerationAction {
+    /// The control message carrying this action against `node_id`.
+    pub fn to_message(self, node_id: NodeId) -> Message {
+        match self {
+            Self::Grant => Message::Grant {
+                node_id,
+                admin: true,
+            },
+            Self::Revoke => Message::Grant {
+                node_id,
+                admin: false,
+            },
+            Self::Kick => Message::Kick { node_id },
+            Self::Ban => Message::Ban {
+                node_id,
+                banned: true,
+            },
+            Self::Unban => Message::Ban {
+                node_id,
+                banned: false,
+            },
+            Self::Mute => Message::Mute {
+                node_id,
+                muted: true,
+            },
+            Self::Unmute => Message::Mute {
+                node_id,
+                muted: false,
+            },
+            Self::AddMember => Message::Member {
+                node_id,
+                member: true,
+            },
+            Self::RemoveMember => Message::Member {
+                node_id,
+                member: false,
+            },
+        }
+    }
+
+    /// The target and action of a control message, if it is one.
+    pub fn from_message(message: &Message) -> Option<(NodeId, Self)> {
+        let (node_id, action) = match *message {
+            Message::Grant { node_id, admin } => {
+                (node_id, if admin { Self::Grant } else { Self::Revoke })
+            }
+            Message::Kick { node_id } => (node_id, Self::Kick),
+            Message::Ban { node_id, banned } => {
+                (node_id, if banned { Self::Ban } else { Self::Unban })
+            }
+            Message::Mute { node_id, muted } => {
+                (node_id, if muted { Self::Mute } else { Self::Unmute })
+            }
+            Message::Member { node_id, member } => (
+                node_id,
+                if member {
+                    Self::AddMember
+                } else {
+                    Self::RemoveMember
+                },
+            ),
+            _ => return None,
+        };
+        Some((node_id, action))
+    }
+
+    /// How the action is stored in the database, as a kind and whether it is in
+    /// effect, unless it is a one-off.
+    pub fn as_record(&self) -> Option<(&'static str, bool)> {
+        match self {
+            Self::Kick => None,
+            Self::Grant | Self::Ban | Self::Mute | Self::AddMember => Some((self.kind(), true)),
+            Self::Revoke | Self::Unban | Self::Unmute | Self::RemoveMember => {
+                Some((self.kind(), false))
+            }
+        }
+    }
+
+    /// The kind of moderation the action changes.
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Grant | Self::Revoke => "admin",
+            Self::Kick => "kick",
+            Self::Ban | Self::Unban => "ban",
+            Self::Mute | Self::Unmute => "mute",
+            Self::AddMember | Self::RemoveMember => "member",
+        }
+    }
+
+    /// Whether the action puts its target out of the room.
+    pub fn removes(&self) -> bool {
+        matches!(self, Self::Kick | Self::Ban | Self::RemoveMember)
+    }
+
+    /// The action that put a stored kind of moderation in effect, or lifted it.
+    pub fn from_record(kind: &str, active: bool) -> Option<Self> {
+        match (kind, active) {
+            ("admin", true) => Some(Self::Grant),
+            ("admin", false) => Some(Self::Revoke),
+            ("ban", true) => Some(Self::Ban),
+            ("ban", false) => Some(Self::Unban),
+            ("mute", true) => Some(Self::Mute),
+            ("mute", false) => Some(Self::Unmute),
+            ("member", true) => Some(Self::AddMember),
+            ("member", false) => Some(Self::RemoveMember),
+            _ => None,
+        }
+    }
+
+    /// Whether only the room's creator may take this action.
+    fn is_grant(&self) -> bool {
+        matches!(self, Self::Grant | Self::Revoke)
+    }
+
+    /// Whether the action only applies to invite-only rooms.
+    fn is_membership(&self) -> bool {
+        matches!(self, Self::AddMember | Self::RemoveMember)
+    }
+}
+
+impl fmt::Display for ModerationAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Grant => "grant admin to",
+            Self::Revoke => "revoke admin from",
+            Self::Kick => "kick",
+            Self::Ban => "ban",
+            Self::Unban => "unban",
+            Self::Mute => "mute",
+            Self::Unmute => "unmute",
+            Self::AddMember => "add as member",
+            Self::RemoveMember => "remove from members",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Who moderates a room and who they banned or muted, for the frontend.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomModeration {
+    /// Unset for rooms created before moderation, which nobody can moderate.
+    pub creator: Option<NodeId>,
+    pub admins: Vec<NodeId>,
+    pub banned: Vec<NodeId>,
+    pub muted: Vec<NodeId>,
+    /// Who besides the creator is let in, unset for rooms anyone with a ticket can join.
+    pub members: Option<Vec<NodeId>>,
+}
+
+#[derive(Debug, Default)]
+struct Roles {
+    topic: Option<TopicId>,
+    creator: Option<RoomCreator>,
+    admins: HashSet<NodeId>,
+    banned: HashSet<NodeId>,
+    muted: HashSet<NodeId>,
+    /// Peers asked to leave that haven't yet, whose gossip is dropped until they do.
+    kicked: HashSet<NodeId>,
+    members: Option<HashSet<NodeId>>,
+    /// Peers that joined with an invite that didn't hold, kept out like non-members.
+    refused: HashSet<NodeId>,
+    invites: HashMap<InviteId, InviteUses>,
+    /// When each kind of moderation of each node last changed, so that an action
+    /// arriving late doesn't undo a later one.
+    updated: HashMap<(NodeId, &'static str), u64>,
+    /// When we last joined the room. We can't tell whether the targets of kicks
+    /// from before then left since, so those are ignored.
+    joined_at: u64,
+}
+
+impl Roles {
+    fn creator(&self) -> Option<NodeId> {
+        self.creator.map(|creator| creator.node_id)
+    }
+
+    fn is_admin(&self, node: &NodeId) -> bool {
+        self.creator().as_ref() == Some(node) || self.admins.contains(node)
+    }
+
+    fn is_member(&self, node: &NodeId) -> bool {
+        self.creator().as_ref() == Some(node)
+            || (!self.refused.contains(node)
+                && self
+                    .members
+                    .as_ref()
+                    .is_none_or(|members| members.contains(node)))
+    }
+
+    /// Why an invite announced by `node` at `at` doesn't hold, if it doesn't.
+    fn check_invite(&self, node: &NodeId, invite: &Invite, at: u64) -> Result<()> {
+        let uses = self.invites.get(&invite.id());
+        if uses.is_some_and(|uses| uses.redeemed_by.contains(node)) {
+            if self.members.is_some() && !self.is_member(node) {
+                bail!("invite was already used before being removed");
+            }
+            return Ok(());
+        }
+        if let Some(topic) = self.topic {
+            invite.validate(topic, at)?;
+        }
+        let terms = invite.terms();
+        if !self.may_invite(&terms.issuer) {
+            bail!("{} may not invite to the room", terms.issuer.fmt_short());
+        }
+        let Some(uses) = uses else {
+            return Ok(());
+        };
+        let revoked = uses.revocations.iter().any(|(revoker, revoked_at)| {
+            *revoked_at <= at && (*revoker == terms.issuer || self.is_admin(revoker))
+        });
+        if revoked {
+            bail!("invite has been revoked");
+        }
+        if terms
+            .max_uses
+            .is_some_and(|max| uses.redeemed_by.len() >= max as usize)
+        {
+            bail!("invite has been used up");
+        }
+        Ok(())
+    }
+
+    /// Record that a kind of moderation of `node` changed at `at`, unless it
+    /// changed later already. Returns whether it was recorded.
+    fn supersede(&mut self, node: NodeId, kind: &'static str, at: u64) -> bool {
+        if self
+            .updated
+            .get(&(node, kind))
+            .is_some_and(|last| at <= *last)
+        {
+            return false;
+        }
+        self.updated.insert((node, kind), at);
+        true
+    }
+
+    /// Admins can invite to any room, and members to rooms that aren't invite-only.
+    fn may_invite(&self, node: &NodeId) -> bool {
+        !self.banned.contains(node)
+            && (self.is_admin(node) || (self.members.is_none() && self.is_member(node)))
+    }
+}
+
+/// Who joined with an invite and who revoked it, as far as we know.
+#[derive(Debug, Default)]
+struct InviteUses {
+    redeemed_by: HashSet<NodeId>,
+    /// Each revoker with when it revoked the invite, which only counts from the
+    /// invite's issuer or admins once we know who issued it.
+    revocations: Vec<(NodeId, u64)>,
+}
+
+/// The moderation state of a single room, built from the creator recorded in its
+/// ticket and the signed control messages seen so far.
+#[derive(Debug, Clone, Default)]
+pub struct Moderation(Arc<Mutex<Roles>>);
+
+impl Moderation {
+    /// The room's creator, who is its first admin and the only one who can appoint others.
+    pub fn creator(&self) -> Option<NodeId> {
+        self.0.lock().expect("poisened").creator()
+    }
+
+    /// The room's creator with the proof its topic derives from it, for tickets.
+    pub(super) fn room_creator(&self) -> Option<RoomCreator> {
+        self.0.lock().expect("poisened").creator
+    }
+
+    pub fn is_creator(&self, node: &NodeId) -> bool {
+        self.creator().as_ref() == Some(node)
+    }
+
+    pub fn is_admin(&self, node: &NodeId) -> bool {
+        self.0.lock().expect("poisened").is_admin(node)
+    }
+
+    pub fn is_banned(&self, node: &NodeId) -> bool {
+        self.0.lock().expect("poisened").banned.contains(node)
+    }
+
+    pub fn is_muted(&self, node: &NodeId) -> bool {
+        self.0.lock().expect("poisened").muted.contains(node)
+    }
+
+    /// Whether the node is let into the room, which everyone is unless it's invite-only
+    /// or the node joined with an invite that didn't hold.
+    pub fn is_member(&self, node: &NodeId) -> bool {
+        self.0.lock().expect("poisened").is_member(node)
+    }
+
+    /// Whether we serve the room's history and attachments to the node, which
+    /// we do to members that aren't banned or kicked.
+    pub fn serves(&self, node: &NodeId) -> bool {
+        let roles = self.0.lock().expect("poisened");
+        roles.is_member(node) && !roles.banned.contains(node) && !roles.kicked.contains(node)
+    }
+
+    /// Whether the node may issue invites to the room.
+    pub fn may_invite(&self, node: &NodeId) -> bool {
+        self.0.lock().expect("poisened").may_invite(node)
+    }
+
+    /// Who besides the creator is let into an invite-only room.
+    pub fn members(&self) -> Option<BTreeSet<NodeId>> {
+        let roles = self.0.lock().expect("poisened");
+        roles
+            .members
+            .as_ref()
+            .map(|members| members.iter().copied().collect())
+    }
+
+    /// Whether `from` may take `action` against `target`. Nobody can act on the
+    /// creator or on themselves, and only the creator can act on other admins.
+    pub fn permits(&self, from: &NodeId, target: &NodeId, action: ModerationAction) -> bool {
+        let roles = self.0.lock().expect("poisened");
+        let Some(creator) = roles.creator() else {
+            return false;
+        };
+        if from == target || *target == creator {
+            return false;
+        }
+        if action.is_membership() && roles.members.is_none() {
+            return false;
+        }
+        if *from == creator {
+            return true;
+        }
+        !action.is_grant() && roles.admins.contains(from) && !roles.admins.contains(target)
+    }
+
+    /// Take an action `from` took at `at`, if it was allowed to and nothing
+    /// later superseded it. Returns whether it was taken.
+    pub fn apply(&self, from: &NodeId, target: &NodeId, action: ModerationAction, at: u64) -> bool {
+        self.permits(from, target, action) && self.restore(*target, action, at)
+    }
+
+    /// Take an action recorded earlier, without checking who took it, unless a
+    /// later action of the same kind was taken already. Returns whether it was.
+    pub fn restore(&self, target: NodeId, action: ModerationAction, at: u64) -> bool {
+        let mut roles = self.0.lock().expect("poisened");
+        if action == ModerationAction::Kick && at < roles.joined_at {
+            return false;
+        }
+        if !roles.supersede(target, action.kind(), at) {
+            return false;
+        }
+        match action {
+            ModerationAction::Grant => roles.admins.insert(target),
+            ModerationAction::Revoke => roles.admins.remove(&target),
+            ModerationAction::Kick => roles.kicked.insert(target),
+            ModerationAction::Ban => roles.banned.insert(target),
+            ModerationAction::Unban => roles.banned.remove(&target),
+            ModerationAction::Mute => roles.muted.insert(target),
+            ModerationAction::Unmute => roles.muted.remove(&target),
+            ModerationAction::AddMember => {
+                roles.refused.remove(&target);
+                roles
+                    .members
+                    .as_mut()
+                    .is_some_and(|members| members.insert(target))
+            }
+            ModerationAction::RemoveMember => roles
+                .members
+                .as_mut()
+                .is_some_and(|members| members.remove(&target)),
+        };
+        true
+    }
+
+    /// Whether the node was kicked and hasn't left yet.
+    pub fn is_kicked(&self, node: &NodeId) -> bool {
+        self.0.lock().expect("poisened").kicked.contains(node)
+    }
+
+    /// Record that `node` left the room at `at`, which lifts a kick from before.
+    pub fn left(&self, node: NodeId, at: u64) {
+        let mut roles = self.0.lock().expect("poisened");
+        if roles.supersede(node, ModerationAction::Kick.kind(), at) {
+            roles.kicked.remove(&node);
+        }
+    }
+
+    /// Record that we joined the room at `at`.
+    pub fn joined(&self, at: u64) {
+        self.0.lock().expect("poisened").joined_at = at;
+    }
+
+    /// Let `node` in with an invite it announced at `at`, if the invite holds.
+    ///
+    /// The invite must have been issued by someone who may invite, and be neither
+    /// expired, revoked, nor used up by others then. Announcing it again is fine
+    /// as long as the node wasn't removed since. A node whose invite doesn't hold
+    /// is kept out of the room.
+    pub fn redeem(&self, node: NodeId, invite: &Invite, at: u64) -> Result<()> {
+        let mut roles = self.0.lock().expect("poisened");
+        let result = roles.check_invite(&node, invite, at);
+        match result {
+            Ok(()) => {
+                let id = invite.id();
+                roles
+                    .invites
+                    .entry(id)
+                    .or_default()
+                    .redeemed_by
+                    .insert(node);
+                roles.refused.remove(&node);
+                if roles.supersede(node, "member", at) {
+                    if let Some(members) = roles.members.as_mut() {
+                        members.insert(node);
+                    }
+                }
+            }
+            // Non-members are kept out of invite-only rooms already.
+            Err(_) if roles.members.is_none() && !roles.is_admin(&node) => {
+                roles.refused.insert(node);
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    /// Record that `from` revoked an invite at `at`. Since only its id is known
+    /// until someone joins with it, whether `from` could is checked then.
+    pub fn revoke(&self, from: NodeId, id: InviteId, at: u64) {
+        let mut roles = self.0.lock().expect("poisened");
+        roles
+            .invites
+            .entry(id)
+            .or_default()
+            .revocations
+            .push((from, at));
+    }
+
+    /// Record a use of an invite seen earlier, without checking it.
+    pub fn restore_redemption(&self, id: InviteId, node: NodeId) {
+        let mut roles = self.0.lock().expect("poisened");
+        roles
+            .invites
+            .entry(id)
+            .or_default()
+            .redeemed_by
+            .insert(node);
+    }
+
+    /// Whether an event gets past the room's moderation, taking the action it
+    /// carries if it is a moderation or invite event.
+    ///
+    /// Everything from banned peers and non-members of invite-only rooms is dropped,
+    /// as is everything but their goodbye from kicked peers, chat messages and
+    /// typing updates from muted peers and moderation by peers without the right
+    /// to. Newcomers are let in or refused by the invite they announce.
+    pub fn admit(&self, event: &Event) -> bool {
+        let Some(from) = event.sender() else {
+            return true;
+        };
+        if self.is_banned(&from) {
+            return false;
+        }
+        if let Event::Left { sent_timestamp, .. } = event {
+            self.left(from, *sent_timestamp);
+        }
+        if self.is_kicked(&from) {
+            return false;
+        }
+        if let Event::InviteRedeemed {
+            invite,
+            sent_timestamp,
+            ..
+        } = event
+        {
+            return match self.redeem(from, invite, *sent_timestamp) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!(
+                        "refusing {}, who joined with an invite: {err}",
+                        from.fmt_short()
+                    );
+                    false
+                }
+            };
+        }
+        if !self.is_member(&from) {
+            return false;
+        }
+        match event {
+            Event::InviteRevoked {
+                invite_id,
+                sent_timestamp,
+                ..
+            } => {
+                self.revoke(from, *invite_id, *sent_timestamp);
+                true
+            }
+            Event::Moderation {
+                target,
+                action,
+                sent_timestamp,
+                ..
+            } => {
+                if !self.permits(&from, target, *action) {
+                    warn!(
+                        "ignoring attempt by {} to {action} {}",
+                        from.fmt_short(),
+                        target.fmt_short()
+                    );
+                    return false;
+                }
+                // Superseded by a later action, so replaying it changes nothing.
+                self.restore(*target, *action, *sent_timestamp)
+            }
+            Event::Typing { .. } => !self.is_muted(&from),
+            event => event.history_entry().is_none() || !self.is_muted(&from),
+        }
+    }
+
+    /// A copy of the current state for the frontend.
+    pub fn snapshot(&self) -> RoomModeration {
+        let roles = self.0.lock().expect("poisened");
+        RoomModeration {
+            creator: roles.creator(),
+            admins: roles.admins.iter().copied().collect(),
+            banned: roles.banned.iter().copied().collect(),
+            muted: roles.muted.iter().copied().collect(),
+            members: roles
+                .members
+                .as_ref()
+                .map(|members| members.iter().copied().collect()),
+        }
+    }
+}
+
+/// The moderation state of each room we are in, kept across reconnects.
+#[derive(Debug, Clone, Default)]
+pub struct ModerationStore(Arc<Mutex<HashMap<TopicId, Moderation>>>);
+
+impl ModerationStore {
+    /// The moderation of a ticket's room, taking in the creator and members it lists.
+    pub fn room(&self, ticket: &ChatTicket) -> Moderation {
+        let mut rooms = self.0.lock().expect("poisened");
+        let moderation = rooms.entry(ticket.topic_id).or_default().clone();
+        let mut roles = moderation.0.lock().expect("poisened");
+        roles.topic = Some(ticket.topic_id);
+        if roles.creator.is_none() && ticket.creator().is_some() {
+            roles.creator = ticket.creator;
+        }
+        if let Some(members) = &ticket.members {
+            // Members removed since we heard about them stay removed.
+            let members = members
+                .iter()
+                .filter(|node| !roles.updated.contains_key(&(**node, "member")))
+                .copied()
+                .collect::<Vec<_>>();
+            roles.members.get_or_insert_default().extend(members);
+        }
+        drop(roles);
+        moderation
+    }
+
+    /// Whether we serve a room's history and attachments to `node`, which needs
+    /// us to have been in the room.
+    pub fn serves(&self, topic: &TopicId, node: &NodeId) -> bool {
+        let rooms = self.0.lock().expect("poisened");
+        rooms
+            .get(topic)
+            .is_some_and(|moderation| moderation.serves(node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+    use crate::chat::{
+        testing::{node_id, secret_key},
+        MessageId,
+    };
+
+    /// A room created by `creator`, as its members see it.
+    fn room(creator: &SecretKey, invite_only: bool) -> (Moderation, TopicId) {
+        let mut ticket = ChatTicket::new_named("room").with_creator(creator.public());
+        if invite_only {
+            ticket = ticket.invite_only();
+        }
+        (ModerationStore::default().room(&ticket), ticket.topic_id)
+    }
+
+    fn id() -> MessageId {
+        MessageId::from_signed(&rand::random::<[u8; 32]>())
+    }
+
+    fn chat(from: NodeId) -> Event {
+        Event::MessageReceived {
+            id: id(),
+            from,
+            text: "hi".to_string(),
+            nickname: "someone".to_string(),
+            sent_timestamp: 1,
+            clock_skewed: false,
+            reply_to: None,
+        }
+    }
+
+    fn moderate(from: NodeId, target: NodeId, action: ModerationAction, at: u64) -> Event {
+        Event::Moderation {
+            id: id(),
+            from,
+            target,
+            action,
+            sent_timestamp: at,
+        }
+    }
+
+    fn redeem(from: NodeId, invite: &Invite, at: u64) -> Event {
+        Event::InviteRedeemed {
+            id: id(),
+            from,
+            invite_id: invite.id(),
+            invite: invite.clone(),
+            sent_timestamp: at,
+        }
+    }
+
+    #[test]
+    fn only_admins_moderate() {
+        let creator = secret_key();
+        let (moderation, _) = room(&creator, false);
+        let (admin, peer, other) = (node_id(), node_id(), node_id());
+        let creator = creator.public();
+
+        assert!(!moderation.admit(&moderate(peer, other, ModerationAction::Ban, 1)));
+        assert!(moderation.admit(&moderate(creator, admin, ModerationAction::Grant, 2)));
+        assert!(moderation.admit(&moderate(admin, peer, ModerationAction::Ban, 3)));
+        assert!(moderation.is_banned(&peer));
+        // Only the creator appoints admins, and nobody acts on the creator.
+        assert!(!moderation.admit(&moderate(admin, other, ModerationAction::Grant, 4)));
+        assert!(!moderation.admit(&moderate(admin, creator, ModerationAction::Ban, 5)));
+    }
+
+    #[test]
+    fn banned_and_muted_peers_are_dropped() {
+        let creator = secret_key();
+        let (moderation, _) = room(&creator, false);
+        let (banned, muted) = (node_id(), node_id());
+        let creator = creator.public();
+
+        assert!(moderation.admit(&moderate(creator, banned, ModerationAction::Ban, 1)));
+        assert!(moderation.admit(&moderate(creator, muted, ModerationAction::Mute, 2)));
+        assert!(!moderation.admit(&chat(banned)));
+        assert!(!moderation.admit(&Event::Left {
+            from: banned,
+            sent_timestamp: 3,
+        }));
+        assert!(!moderation.admit(&chat(muted)));
+        assert!(moderation.admit(&Event::Left {
+            from: muted,
+            sent_timestamp: 3,
+        }));
+    }
+
+    #[test]
+    fn later_actions_win() {
+        let creator = secret_key();
+        let (moderation, _) = room(&creator, false);
+        let peer = node_id();
+        let creator = creator.public();
+
+        assert!(moderation.admit(&moderate(creator, peer, ModerationAction::Unban, 2)));
+        // A ban from before the unban, arriving late or replayed.
+        assert!(!moderation.admit(&moderate(creator, peer, ModerationAction::Ban, 1)));
+        assert!(!moderation.is_banned(&peer));
+        assert!(!moderation.restore(peer, ModerationAction::Ban, 2));
+        assert!(moderation.admit(&moderate(creator, peer, ModerationAction::Ban, 3)));
+        assert!(moderation.is_banned(&peer));
+        // Other kinds of moderation are ordered separately.
+        assert!(moderation.admit(&moderate(creator, peer, ModerationAction::Mute, 1)));
+    }
+
+    #[test]
+    fn kicked_peers_are_dropped_until_they_leave() {
+        let creator = secret_key();
+        let (moderation, _) = room(&creator, false);
+        let peer = node_id();
+        let creator = creator.public();
+        moderation.joined(10);
+
+        // We can't tell whether peers kicked before we joined left since.
+        assert!(!moderation.admit(&moderate(creator, peer, ModerationAction::Kick, 5)));
+        assert!(moderation.admit(&chat(peer)));
+
+        assert!(moderation.admit(&moderate(creator, peer, ModerationAction::Kick, 20)));
+        assert!(!moderation.admit(&chat(peer)));
+        assert!(!moderation.serves(&peer));
+        assert!(moderation.admit(&Event::Left {
+            from: peer,
+            sent_timestamp: 21,
+        }));
+        assert!(moderation.admit(&chat(peer)));
+        // Hearing of the kick again doesn't undo the leaving.
+        assert!(!moderation.admit(&moderate(creator, peer, ModerationAction::Kick, 20)));
+    }
+
+    #[test]
+    fn invite_only_rooms_drop_non_members() {
+        let creator = secret_key();
+        let (moderation, _) = room(&creator, true);
+        let peer = node_id();
+        let creator = creator.public();
+
+        assert!(moderation.admit(&chat(creator)));
+        assert!(!moderation.admit(&chat(peer)));
+        assert!(!moderation.serves(&peer));
+        assert!(moderation.admit(&moderate(creator, peer, ModerationAction::AddMember, 1)));
+        assert!(moderation.admit(&chat(peer)));
+        assert!(moderation.serves(&peer));
+        assert!(moderation.admit(&moderate(creator, peer, ModerationAction::RemoveMember, 2)));
+        assert!(!moderation.admit(&chat(peer)));
+    }
+
+    #[test]
+    fn invites_let_newcomers_in() {
+        let creator = secret_key();
+        let (moderation, topic) = room(&creator, true);
+        let newcomer = node_id();
+        let invite = Invite::issue(&creator, topic, None, None).unwrap();
+
+        assert!(moderation.admit(&redeem(newcomer, &invite, 1)));
+        assert!(moderation.is_member(&newcomer));
+        assert!(moderation.admit(&chat(newcomer)));
+        // Announcing it again is fine.
+        assert!(moderation.admit(&redeem(newcomer, &invite, 2)));
+    }
+
+    #[test]
+    fn invites_hold_to_their_terms() {
+        let creator = secret_key();
+        let (moderation, topic) = room(&creator, true);
+        let (first, second) = (node_id(), node_id());
+
+        let once = Invite::issue(&creator, topic, None, Some(1)).unwrap();
+        assert!(moderation.admit(&redeem(first, &once, 1)));
+        assert!(!moderation.admit(&redeem(second, &once, 2)));
+
+        let expiring = Invite::issue(&creator, topic, Some(10), None).unwrap();
+        assert!(!moderation.admit(&redeem(second, &expiring, 11)));
+
+        let elsewhere = Invite::issue(&creator, TopicId::from_bytes([0; 32]), None, None).unwrap();
+        assert!(!moderation.admit(&redeem(second, &elsewhere, 1)));
+
+        // Members of invite-only rooms can't invite, only admins.
+        let member = secret_key();
+        let by_member = Invite::issue(&member, topic, None, None).unwrap();
+        moderation.restore(member.public(), ModerationAction::AddMember, 1);
+        assert!(!moderation.admit(&redeem(second, &by_member, 2)));
+        assert!(!moderation.is_member(&second));
+    }
+
+    #[test]
+    fn revoked_invites_stop_letting_newcomers_in() {
+        let creator = secret_key();
+        let (moderation, topic) = room(&creator, true);
+        let (early, late) = (node_id(), node_id());
+        let invite = Invite::issue(&creator, topic, None, None).unwrap();
+
+        assert!(moderation.admit(&redeem(early, &invite, 1)));
+        assert!(moderation.admit(&Event::InviteRevoked {
+            id: id(),
+            from: creator.public(),
+            invite_id: invite.id(),
+            sent_timestamp: 2,
+        }));
+        assert!(!moderation.admit(&redeem(late, &invite, 3)));
+        assert!(moderation.is_member(&early));
+    }
+
+    #[test]
+    fn failed_invites_keep_newcomers_out_of_open_rooms() {
+        let creator = secret_key();
+        let (moderation, topic) = room(&creator, false);
+        let newcomer = node_id();
+        let expired = Invite::issue(&creator, topic, Some(10), None).unwrap();
+
+        assert!(moderation.admit(&chat(newcomer)));
+        assert!(!moderation.admit(&redeem(newcomer, &expired, 11)));
+        assert!(!moderation.admit(&chat(newcomer)));
+    }
+
+    #[test]
+    fn rooms_only_take_creators_bound_to_them() {
+        let mut ticket = ChatTicket::new_named("room").with_creator(node_id());
+        ticket.creator.as_mut().unwrap().node_id = node_id();
+        let moderation = ModerationStore::default().room(&ticket);
+        assert!(moderation.creator().is_none());
+    }
+}
@@ -0,0 +1,109 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6, UdpSocket};
+
+use iroh::{
+    discovery::{
+        dns::DnsDiscovery,
+        pkarr::{PkarrPublisher, PkarrResolver},
+    },
+    endpoint::Builder,
+    RelayMode, RelayUrl,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use url::Url;
+
+/// Where the mDNS group lives, which is routed over the local network.
+const MDNS_GROUP: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(224, 0, 0, 251), 5353);
+
+/// How the node finds and reaches peers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NetworkMode {
+    /// Through relays and a discovery service, from anywhere.
+    #[default]
+    Public,
+    /// Only on the local network, found over mDNS, without relays or anything
+    /// else outside of it.
+    LanOnly,
+}
+
+/// Which infrastructure the node uses, n0's unless told otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NetworkConfig {
+    pub mode: NetworkMode,
+    /// Relays to use in place of n0's, ignored on the local network.
+    pub relays: Vec<RelayUrl>,
+    /// Pkarr relay to publish our address to and resolve peers' from, in place
+    /// of n0's, ignored on the local network.
+    pub pkarr_relay: Option<Url>,
+    /// DNS origin to resolve peers' addresses under, in place of n0's, ignored
+    /// on the local network.
+    pub dns_origin: Option<String>,
+    /// Port to bind on, a random one if unset or taken.
+    pub bind_port: Option<u16>,
+}
+
+impl NetworkConfig {
+    /// Whether discovery goes through a service of our choosing rather than n0's.
+    fn custom_discovery(&self) -> bool {
+        self.pkarr_relay.is_some() || self.dns_origin.is_some()
+    }
+
+    /// Set up an endpoint to find and reach peers this way.
+    pub(super) fn configure(&self, builder: Builder) -> Builder {
+        let port = self.bind_port.unwrap_or_default();
+        match self.mode {
+            NetworkMode::Public => {
+                let mut builder = match self.relays.is_empty() {
+                    true => builder,
+                    false => {
+                        builder.relay_mode(RelayMode::Custom(self.relays.iter().cloned().collect()))
+                    }
+                };
+                if !self.custom_discovery() {
+                    builder = builder.discovery_n0();
+                }
+                if let Some(pkarr_relay) = self.pkarr_relay.clone() {
+                    let resolver = PkarrResolver::new(pkarr_relay.clone());
+                    builder = builder
+                        .add_discovery(move |secret_key| {
+                            Some(PkarrPublisher::new(secret_key.clone(), pkarr_relay))
+                        })
+                        .add_discovery(move |_| Some(resolver));
+                }
+                if let Some(origin) = self.dns_origin.clone() {
+                    builder = builder.add_discovery(move |_| Some(DnsDiscovery::new(origin)));
+                }
+                if self.bind_port.is_some() {
+                    builder = builder
+                        .bind_addr_v4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port))
+                        .bind_addr_v6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, port, 0, 0));
+                }
+                builder
+            }
+            NetworkMode::LanOnly => {
+                let builder = builder
+                    .relay_mode(RelayMode::Disabled)
+                    .discovery_local_network();
+                let ip = lan_ipv4().unwrap_or_else(|| {
+                    warn!("no local network interface found, binding to all of them");
+                    Ipv4Addr::UNSPECIFIED
+                });
+                info!("binding to the local network on {ip}");
+                builder.bind_addr_v4(SocketAddrV4::new(ip, port))
+            }
+        }
+    }
+}
+
+/// The address of the interface the local network is reached on, found by
+/// routing towards the mDNS group. Nothing is sent.
+fn lan_ipv4() -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect(MDNS_GROUP).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() && !ip.is_loopback() => Some(ip),
+        _ => None,
+    }
+}
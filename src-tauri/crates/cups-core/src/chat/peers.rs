@@ -0,0 +1,443 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{utils::get_timestamp, EventSink};
+use iroh::NodeId;
+use iroh_gossip::proto::TopicId;
+use serde::{Deserialize, Serialize};
+
+use super::{Event, Moderation, ModerationAction, RoomEvent};
+
+/// How long a peer counts as typing after its last typing update, in micros.
+/// Ongoing typing is repeated every [`super::TYPING_THROTTLE`].
+const TYPING_TIMEOUT: u64 = 6_000_000;
+
+/// The known members of a single room's Gossip Swarm.
+pub struct PeerMap {
+    topic: TopicId,
+    peers: HashMap<NodeId, PeerInfo>,
+    /// When each typing peer last told us so, by our clock.
+    typing_since: HashMap<NodeId, u64>,
+    moderation: Moderation,
+}
+
+impl PeerMap {
+    pub fn new(topic: TopicId, moderation: Moderation) -> Self {
+        Self {
+            topic,
+            peers: HashMap::new(),
+            typing_since: HashMap::new(),
+            moderation,
+        }
+    }
+    pub fn to_vec(&self) -> Vec<PeerInfo> {
+        self.peers.values().cloned().collect()
+    }
+    /// Update the activity of the peers list. Returns a list of updated peers if updated.
+    pub fn update(
+        &mut self,
+        event: Option<&Event>,
+        new_starters: &mut HashSet<NodeId>,
+        sink: &dyn EventSink,
+    ) {
+        let before = self.to_vec();
+        let map = &mut self.peers;
+        match event {
+            Some(Event::Joined { neighbors }) => {
+                for &id in neighbors {
+                    new_starters.insert(id);
+                    map.entry(id)
+                        .and_modify(|peer| {
+                            peer.status = PeerStatus::Online;
+                            peer.offline_reason = None;
+                            peer.last_seen = get_timestamp();
+                        })
+                        .or_insert(PeerInfo::new(id, None));
+                }
+            }
+            Some(Event::Presence {
+                from: id,
+                nickname,
+                version,
+                ..
+            }) => {
+                if new_starters.remove(id) {
+                    sink.send(
+                        "peers-new",
+                        RoomEvent::new(self.topic, NewPeer { nickname }),
+                    );
+                }
+                map.entry(*id)
+                    .and_modify(|peer| {
+                        peer.nickname = nickname.clone();
+                        // Use our own clock, senders can claim any timestamp.
+                        peer.last_seen = get_timestamp();
+                        peer.status = PeerStatus::Online;
+                        peer.offline_reason = None;
+                        peer.version = Some(*version);
+                    })
+                    .or_insert(PeerInfo {
+                        version: Some(*version),
+                        ..PeerInfo::new(*id, Some(nickname.clone()))
+                    });
+            }
+            Some(Event::Typing {
+                from: id, active, ..
+            }) => {
+                let peer = map.entry(*id).or_insert(PeerInfo::new(*id, None));
+                peer.typing = *active;
+                peer.last_seen = get_timestamp();
+                peer.status = PeerStatus::Online;
+                peer.offline_reason = None;
+                if *active {
+                    self.typing_since.insert(*id, get_timestamp());
+                }
+            }
+            Some(Event::Receipt { from: id, .. }) => {
+                // A signed receipt is proof the peer is around, even if we lost track of it.
+                let peer = map.entry(*id).or_insert(PeerInfo::new(*id, None));
+                peer.last_seen = get_timestamp();
+                peer.status = PeerStatus::Online;
+                peer.offline_reason = None;
+            }
+            Some(Event::MessageReceived { from: id, .. }) => {
+                // Whatever they were typing has been sent.
+                if let Some(peer) = map.get_mut(id) {
+                    peer.typing = false;
+                }
+            }
+            Some(Event::Left { from: id, .. }) => {
+                // The peer said goodbye, no need to wait for the swarm to notice.
+                let peer = map.entry(*id).or_insert(PeerInfo::new(*id, None));
+                peer.status = PeerStatus::Offline;
+                peer.offline_reason = Some(OfflineReason::Left);
+                peer.typing = false;
+                peer.last_seen = get_timestamp();
+            }
+            Some(Event::Moderation { target, action, .. }) => {
+                // Roles and mutes are refreshed below.
+                let reason = match action {
+                    ModerationAction::Kick => Some(OfflineReason::Kicked),
+                    ModerationAction::Ban => Some(OfflineReason::Banned),
+                    ModerationAction::RemoveMember => Some(OfflineReason::Removed),
+                    _ => None,
+                };
+                if let Some(reason) = reason {
+                    let peer = map.entry(*target).or_insert(PeerInfo::new(*target, None));
+                    peer.status = PeerStatus::Offline;
+                    peer.offline_reason = Some(reason);
+                    peer.typing = false;
+                }
+            }
+            Some(Event::NeighborDown { node_id: id }) => {
+                // node reported to have left the room.
+                map.entry(*id)
+                    .and_modify(|peer| {
+                        peer.status = PeerStatus::Offline;
+                        // Keep the reason if the peer told us it was leaving.
+                        peer.offline_reason
+                            .get_or_insert(OfflineReason::Disconnected);
+                        peer.typing = false;
+                        peer.last_seen = get_timestamp();
+                    })
+                    .or_insert(PeerInfo::new(*id, None));
+            }
+            Some(Event::NeighborUp { node_id: id }) => {
+                // node reported to have rejoined the room
+                map.entry(*id)
+                    .and_modify(|peer| {
+                        peer.status = PeerStatus::Online;
+                        peer.offline_reason = None;
+                        peer.last_seen = get_timestamp();
+                    })
+                    .or_insert(PeerInfo::new(*id, None));
+            }
+            None => {
+                // tick at regular intervals to update the peerStatus
+                for peer in map.values_mut() {
+                    let millis_since_last_seen =
+                        (get_timestamp().saturating_sub(peer.last_seen)) / 1000;
+                    if millis_since_last_seen > 10_000 && peer.status != PeerStatus::Offline {
+                        peer.status = PeerStatus::Away;
+                    };
+                    let typing_since = self.typing_since.get(&peer.id).copied().unwrap_or(0);
+                    if get_timestamp().saturating_sub(typing_since) > TYPING_TIMEOUT {
+                        peer.typing = false;
+                    }
+                }
+            }
+            _ => return, // ignore other events for now,
+        }
+        for peer in map.values_mut() {
+            peer.role = if self.moderation.is_creator(&peer.id) {
+                PeerRole::Creator
+            } else if self.moderation.is_admin(&peer.id) {
+                PeerRole::Admin
+            } else {
+                PeerRole::RemoteNode
+            };
+            peer.muted = self.moderation.is_muted(&peer.id);
+        }
+        let after = self.to_vec();
+        if before != after {
+            sink.send(
+                "peers-event",
+                RoomEvent::new(self.topic, PeerList { peers: after }),
+            );
+        }
+    }
+}
+
+/// Payload of `peers-event`, the full peer list of a room whenever it changes.
+#[derive(Debug, Clone, Serialize)]
+struct PeerList {
+    peers: Vec<PeerInfo>,
+}
+
+/// Payload of `peers-new`, sent when a newly joined peer first identifies itself.
+#[derive(Debug, Clone, Serialize)]
+struct NewPeer<'a> {
+    nickname: &'a str,
+}
+
+/// Information for the frontend to display about known peers
+/// in the Gossip Swarm.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerInfo {
+    pub id: NodeId,
+    pub nickname: String,
+    pub last_seen: u64,
+    pub role: PeerRole,
+    pub status: PeerStatus,
+    /// Protocol version from the peer's last presence message, if any.
+    pub version: Option<u16>,
+    /// The peer told us it's typing, within the last few seconds.
+    pub typing: bool,
+    /// Why the peer went [`PeerStatus::Offline`], if it did.
+    pub offline_reason: Option<OfflineReason>,
+    /// A room admin muted the peer, so its chat messages are dropped.
+    pub muted: bool,
+}
+
+impl PeerInfo {
+    fn new(id: NodeId, nickname: Option<String>) -> Self {
+        Self {
+            id,
+            nickname: nickname.unwrap_or("identifying...".to_string()),
+            last_seen: get_timestamp(),
+            role: PeerRole::RemoteNode,
+            status: PeerStatus::Online,
+            version: None,
+            typing: false,
+            offline_reason: None,
+            muted: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum PeerRole {
+    Myself,
+    RemoteNode,
+    /// A remote node appointed by the room's creator to moderate it.
+    Admin,
+    /// The remote node that created the room.
+    Creator,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum PeerStatus {
+    Online,
+    Away,
+    Offline,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum OfflineReason {
+    /// The peer announced it was leaving the room.
+    Left,
+    /// The swarm lost its connection to the peer.
+    Disconnected,
+    /// A room admin asked the peer to leave.
+    Kicked,
+    /// A room admin banned the peer.
+    Banned,
+    /// A room admin removed the peer from an invite-only room.
+    Removed,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::chat::{moderation::ModerationStore, testing::node_id, ChatTicket, MessageId};
+
+    /// Keeps the names of the events sent to the frontend.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl EventSink for Recorder {
+        fn emit(&self, event: &str, _payload: serde_json::Value) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(event.to_string());
+            Ok(())
+        }
+    }
+
+    impl Recorder {
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    fn presence(from: NodeId, nickname: &str) -> Event {
+        Event::Presence {
+            from,
+            nickname: nickname.to_string(),
+            sent_timestamp: get_timestamp(),
+            version: 2,
+        }
+    }
+
+    fn peer(peers: &PeerMap, id: NodeId) -> PeerInfo {
+        peers
+            .to_vec()
+            .into_iter()
+            .find(|peer| peer.id == id)
+            .unwrap()
+    }
+
+    #[test]
+    fn newcomers_are_announced_once_they_identify() {
+        let ticket = ChatTicket::new_named("room");
+        let mut peers = PeerMap::new(ticket.topic_id, Moderation::default());
+        let sink = Recorder::default();
+        let mut new_starters = HashSet::new();
+        let id = node_id();
+
+        peers.update(
+            Some(&Event::Joined {
+                neighbors: vec![id],
+            }),
+            &mut new_starters,
+            &sink,
+        );
+        assert_eq!(sink.take(), ["peers-event"]);
+        assert_eq!(peer(&peers, id).nickname, "identifying...");
+
+        peers.update(Some(&presence(id, "alice")), &mut new_starters, &sink);
+        assert_eq!(sink.take(), ["peers-new", "peers-event"]);
+        let info = peer(&peers, id);
+        assert_eq!(info.nickname, "alice");
+        assert_eq!(info.version, Some(2));
+
+        // Only announced the first time.
+        peers.update(Some(&presence(id, "alice")), &mut new_starters, &sink);
+        assert!(!sink.take().contains(&"peers-new".to_string()));
+    }
+
+    #[test]
+    fn peers_keep_why_they_went_offline() {
+        let ticket = ChatTicket::new_named("room");
+        let mut peers = PeerMap::new(ticket.topic_id, Moderation::default());
+        let sink = Recorder::default();
+        let mut new_starters = HashSet::new();
+        let id = node_id();
+
+        peers.update(Some(&presence(id, "alice")), &mut new_starters, &sink);
+        peers.update(
+            Some(&Event::Left {
+                from: id,
+                sent_timestamp: get_timestamp(),
+            }),
+            &mut new_starters,
+            &sink,
+        );
+        peers.update(
+            Some(&Event::NeighborDown { node_id: id }),
+            &mut new_starters,
+            &sink,
+        );
+        let info = peer(&peers, id);
+        assert_eq!(info.status, PeerStatus::Offline);
+        assert_eq!(info.offline_reason, Some(OfflineReason::Left));
+
+        peers.update(
+            Some(&Event::NeighborUp { node_id: id }),
+            &mut new_starters,
+            &sink,
+        );
+        let info = peer(&peers, id);
+        assert_eq!(info.status, PeerStatus::Online);
+        assert_eq!(info.offline_reason, None);
+    }
+
+    #[test]
+    fn typing_stops_with_the_message() {
+        let ticket = ChatTicket::new_named("room");
+        let mut peers = PeerMap::new(ticket.topic_id, Moderation::default());
+        let sink = Recorder::default();
+        let mut new_starters = HashSet::new();
+        let id = node_id();
+
+        peers.update(
+            Some(&Event::Typing {
+                from: id,
+                active: true,
+                sent_timestamp: get_timestamp(),
+            }),
+            &mut new_starters,
+            &sink,
+        );
+        assert!(peer(&peers, id).typing);
+        peers.update(
+            Some(&Event::MessageReceived {
+                id: MessageId::from_signed(b"hi"),
+                from: id,
+                text: "hi".to_string(),
+                nickname: "alice".to_string(),
+                sent_timestamp: get_timestamp(),
+                clock_skewed: false,
+                reply_to: None,
+            }),
+            &mut new_starters,
+            &sink,
+        );
+        assert!(!peer(&peers, id).typing);
+    }
+
+    #[test]
+    fn roles_follow_the_room_moderation() {
+        let (creator, admin, target) = (node_id(), node_id(), node_id());
+        let ticket = ChatTicket::new_named("room").with_creator(creator);
+        let moderation = ModerationStore::default().room(&ticket);
+        let mut peers = PeerMap::new(ticket.topic_id, moderation.clone());
+        let sink = Recorder::default();
+        let mut new_starters = HashSet::new();
+
+        moderation.apply(&creator, &admin, ModerationAction::Grant, 1);
+        for id in [creator, admin, target] {
+            peers.update(Some(&presence(id, "someone")), &mut new_starters, &sink);
+        }
+        assert_eq!(peer(&peers, creator).role, PeerRole::Creator);
+        assert_eq!(peer(&peers, admin).role, PeerRole::Admin);
+        assert_eq!(peer(&peers, target).role, PeerRole::RemoteNode);
+
+        moderation.apply(&admin, &target, ModerationAction::Mute, 2);
+        peers.update(
+            Some(&Event::Moderation {
+                id: MessageId::from_signed(b"kick"),
+                from: admin,
+                target,
+                action: ModerationAction::Kick,
+                sent_timestamp: 3,
+            }),
+            &mut new_starters,
+            &sink,
+        );
+        let info = peer(&peers, target);
+        assert!(info.muted);
+        assert_eq!(info.status, PeerStatus::Offline);
+        assert_eq!(info.offline_reason, Some(OfflineReason::Kicked));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::{bail, Result};
+use iroh::NodeId;
+use n0_future::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use super::message::{MessageId, ReceivedMessage};
+use crate::utils::get_timestamp;
+
+/// Number of message ids remembered per sender for duplicate detection.
+const SEEN_PER_SENDER: usize = 1024;
+
+/// How far a signed message's claimed timestamp may be from our local clock.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptanceWindow {
+    /// Messages further than this from local time are dropped as replays.
+    pub max_age_secs: u64,
+    /// Messages further than this from local time are delivered but flagged as skewed.
+    pub max_skew_secs: u64,
+}
+
+impl Default for AcceptanceWindow {
+    fn default() -> Self {
+        Self {
+            max_age_secs: 10 * 60,
+            max_skew_secs: 30,
+        }
+    }
+}
+
+impl AcceptanceWindow {
+    fn max_age(&self) -> u64 {
+        Duration::from_secs(self.max_age_secs).as_micros() as u64
+    }
+    fn max_skew(&self) -> u64 {
+        Duration::from_secs(self.max_skew_secs).as_micros() as u64
+    }
+}
+
+/// Rejects rebroadcast and stale signed messages for a single room.
+///
+/// Messages are told apart by their [`MessageId`], which is derived from their
+/// canonical encoding, so a relayed copy can't pass for a new message.
+#[derive(Debug)]
+pub struct ReplayGuard {
+    window: AcceptanceWindow,
+    seen: HashMap<NodeId, SeenIds>,
+}
+
+impl ReplayGuard {
+    pub fn new(window: AcceptanceWindow) -> Self {
+        Self {
+            window,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Change how far message timestamps may drift from local time from now on.
+    pub fn set_window(&mut self, window: AcceptanceWindow) {
+        self.window = window;
+    }
+
+    /// Check a verified message against the window and the per-sender seen cache.
+    ///
+    /// Returns whether the sender's clock looks skewed, or an error if the
+    /// message must be dropped.
+    pub fn check(&mut self, message: &ReceivedMessage) -> Result<bool> {
+        let offset = get_timestamp().abs_diff(message.timestamp);
+        if offset > self.window.max_age() {
+            bail!(
+                "message from {} is {}s outside the acceptance window",
+                message.from.fmt_short(),
+                offset / 1_000_000
+            );
+        }
+        let seen = self.seen.entry(message.from).or_default();
+        if !seen.insert(message.id) {
+            bail!(
+                "replayed message {} from {}",
+                message.id,
+                message.from.fmt_short()
+            );
+        }
+        Ok(offset > self.window.max_skew())
+    }
+
+    /// Check a message served from a neighbor's history. These are expected to be
+    /// old, so only duplicates and messages dated further ahead than the window
+    /// allows are rejected. Returns false if the message must be dropped.
+    pub fn check_backfill(&mut self, message: &ReceivedMessage) -> bool {
+        if message.timestamp > get_timestamp() + self.window.max_age() {
+            return false;
+        }
+        self.seen
+            .entry(message.from)
+            .or_default()
+            .insert(message.id)
+    }
+}
+
+/// Bounded set of recently seen message ids, evicting the oldest first.
+#[derive(Debug, Default)]
+struct SeenIds {
+    order: VecDeque<MessageId>,
+    ids: HashSet<MessageId>,
+}
+
+impl SeenIds {
+    /// Returns false if the id has already been seen.
+    fn insert(&mut self, id: MessageId) -> bool {
+        if !self.ids.insert(id) {
+            return false;
+        }
+        self.order.push_back(id);
+        if self.order.len() > SEEN_PER_SENDER {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::testing::node_id;
+
+    const SECOND: u64 = 1_000_000;
+
+    fn message(from: NodeId, n: u8, timestamp: u64) -> ReceivedMessage {
+        ReceivedMessage {
+            id: MessageId::from_signed(&[n]),
+            version: 0,
+            timestamp,
+            from,
+            message: None,
+        }
+    }
+
+    #[test]
+    fn fresh_messages_are_accepted_once() {
+        let mut guard = ReplayGuard::new(AcceptanceWindow::default());
+        let from = node_id();
+        assert!(!guard.check(&message(from, 1, get_timestamp())).unwrap());
+        assert!(guard.check(&message(from, 1, get_timestamp())).is_err());
+        // Ids are remembered per sender.
+        assert!(guard.check(&message(node_id(), 1, get_timestamp())).is_ok());
+    }
+
+    #[test]
+    fn messages_outside_the_window_are_dropped() {
+        let window = AcceptanceWindow {
+            max_age_secs: 60,
+            max_skew_secs: 5,
+        };
+        let mut guard = ReplayGuard::new(window);
+        let from = node_id();
+        let now = get_timestamp();
+        assert!(guard.check(&message(from, 1, now - 120 * SECOND)).is_err());
+        assert!(guard.check(&message(from, 2, now + 120 * SECOND)).is_err());
+        // Too far off to trust, but not so far as to drop.
+        assert!(guard.check(&message(from, 3, now - 30 * SECOND)).unwrap());
+        assert!(guard.check(&message(from, 4, now + 30 * SECOND)).unwrap());
+    }
+
+    #[test]
+    fn backfill_is_checked_for_duplicates_and_future_dates() {
+        let mut guard = ReplayGuard::new(AcceptanceWindow::default());
+        let from = node_id();
+        let old = message(from, 1, 0);
+        assert!(guard.check_backfill(&old));
+        assert!(!guard.check_backfill(&old));
+        let future = get_timestamp() + 3600 * SECOND;
+        assert!(!guard.check_backfill(&message(from, 2, future)));
+        // Nor can it be replayed live once backfilled.
+        assert!(guard.check(&message(from, 1, get_timestamp())).is_err());
+    }
+
+    #[test]
+    fn changing_the_window_keeps_what_was_seen() {
+        let mut guard = ReplayGuard::new(AcceptanceWindow::default());
+        let from = node_id();
+        let now = get_timestamp();
+        guard.check(&message(from, 1, now)).unwrap();
+        guard.set_window(AcceptanceWindow {
+            max_age_secs: 60,
+            max_skew_secs: 5,
+        });
+        assert!(guard.check(&message(from, 1, now)).is_err());
+        assert!(guard.check(&message(from, 2, now - 120 * SECOND)).is_err());
+    }
+
+    #[test]
+    fn the_oldest_ids_are_forgotten() {
+        let mut seen = SeenIds::default();
+        let ids: Vec<_> = (0..=SEEN_PER_SENDER)
+            .map(|n| MessageId::from_signed(&n.to_be_bytes()))
+            .collect();
+        assert!(ids.iter().all(|id| seen.insert(*id)));
+        assert_eq!(seen.ids.len(), SEEN_PER_SENDER);
+        assert!(seen.insert(ids[0]));
+        assert!(!seen.insert(ids[SEEN_PER_SENDER]));
+    }
+}
@@ -0,0 +1,281 @@
+use anyhow::{ensure, Result};
+use iroh::{NodeId, SecretKey};
+use iroh_gossip::{net::GossipSender, proto::TopicId};
+use n0_future::task::AbortOnDropHandle;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+
+use super::{
+    blobs::Attachment,
+    crypto::{self, RoomSecret},
+    history::HistoryStore,
+    invite::{Invite, InviteId},
+    message::{
+        Message, MessageId, ReceiptKind, SignedMessage, MAX_REACTION_LEN, MAX_RECEIPT_BATCH,
+    },
+    moderation::{Moderation, ModerationAction},
+};
+
+/// Most often an ongoing [`Message::Typing`] is repeated.
+pub const TYPING_THROTTLE: Duration = Duration::from_secs(3);
+
+/// Our typing state, handed to the presence task to send in place of a presence.
+#[derive(Debug, Default)]
+pub struct TypingState {
+    pending: Option<bool>,
+    last_sent: Option<(bool, Instant)>,
+}
+
+impl TypingState {
+    /// Queue an update, unless it repeats the last one too soon or says we stopped
+    /// when we never started. Returns whether it was queued.
+    fn set(&mut self, active: bool) -> bool {
+        let now = Instant::now();
+        match self.last_sent {
+            Some((last, at))
+                if last == active && (!active || now.duration_since(at) < TYPING_THROTTLE) =>
+            {
+                return false
+            }
+            None if !active => return false,
+            _ => {}
+        }
+        self.pending = Some(active);
+        self.last_sent = Some((active, now));
+        true
+    }
+
+    /// The queued update, if any, for the presence task to send.
+    pub fn take(&mut self) -> Option<bool> {
+        self.pending.take()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatSender {
+    nickname: Arc<Mutex<String>>,
+    secret_key: SecretKey,
+    room_secret: Option<RoomSecret>,
+    sender: GossipSender,
+    trigger_presence: Arc<Notify>,
+    typing: Arc<Mutex<TypingState>>,
+    _presence_task: Arc<AbortOnDropHandle<()>>,
+    topic_id: TopicId,
+    history: HistoryStore,
+    moderation: Moderation,
+}
+
+impl ChatSender {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        nickname: Arc<Mutex<String>>,
+        secret_key: SecretKey,
+        room_secret: Option<RoomSecret>,
+        sender: GossipSender,
+        trigger_presence: Arc<Notify>,
+        typing: Arc<Mutex<TypingState>>,
+        presence_task: AbortOnDropHandle<()>,
+        topic_id: TopicId,
+        history: HistoryStore,
+        moderation: Moderation,
+    ) -> Self {
+        Self {
+            nickname,
+            secret_key,
+            room_secret,
+            sender,
+            trigger_presence,
+            typing,
+            _presence_task: Arc::new(presence_task),
+            topic_id,
+            history,
+            moderation,
+        }
+    }
+    /// Broadcast a chat message to the room, returning its [`MessageId`].
+    pub async fn send(&self, text: String) -> Result<MessageId> {
+        let nickname = self.nickname();
+        self.broadcast(Message::Message { text, nickname }).await
+    }
+
+    /// Broadcast a reply to another message of the room, returning its [`MessageId`].
+    pub async fn reply(&self, reply_to: MessageId, text: String) -> Result<MessageId> {
+        let nickname = self.nickname();
+        self.broadcast(Message::Reply {
+            text,
+            nickname,
+            reply_to,
+        })
+        .await
+    }
+
+    /// Add or take back an emoji reaction to a message of the room.
+    pub async fn react(&self, target_id: MessageId, emoji: String, add: bool) -> Result<MessageId> {
+        ensure!(
+            !emoji.is_empty() && emoji.len() <= MAX_REACTION_LEN,
+            "reactions must be a single emoji"
+        );
+        self.broadcast(Message::Reaction {
+            target_id,
+            emoji,
+            add,
+        })
+        .await
+    }
+
+    /// Acknowledge messages of the room, in batches of up to [`MAX_RECEIPT_BATCH`].
+    pub async fn send_receipts(&self, message_ids: &[MessageId], kind: ReceiptKind) -> Result<()> {
+        for batch in message_ids.chunks(MAX_RECEIPT_BATCH) {
+            self.broadcast(Message::Receipt {
+                up_to_ids: batch.to_vec(),
+                kind,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Act on a member of the room, which needs us to be one of its admins. Returns
+    /// the message's id and the timestamp it was signed with, which the action
+    /// takes effect at.
+    pub async fn moderate(
+        &self,
+        target: NodeId,
+        action: ModerationAction,
+    ) -> Result<(MessageId, u64)> {
+        let me = self.node_id();
+        ensure!(
+            self.moderation.permits(&me, &target, action),
+            "not allowed to {action} {}",
+            target.fmt_short()
+        );
+        let (id, timestamp) = self.broadcast_signed(action.to_message(target)).await?;
+        self.moderation.apply(&me, &target, action, timestamp);
+        Ok((id, timestamp))
+    }
+
+    /// Issue an invite to the room that expires at `expires_at` and lets in at
+    /// most `max_uses` newcomers, if set. Only invites to invite-only rooms can
+    /// be limited, since anyone with a ticket can join the others.
+    pub fn invite(&self, expires_at: Option<u64>, max_uses: Option<u32>) -> Result<Invite> {
+        ensure!(
+            self.moderation.may_invite(&self.node_id()),
+            "not allowed to invite to this room"
+        );
+        ensure!(
+            self.moderation.members().is_some() || (expires_at.is_none() && max_uses.is_none()),
+            "anyone with a ticket can join this room, so invites to it can't expire or run out"
+        );
+        ensure!(
+            max_uses != Some(0),
+            "invites must let in at least one newcomer"
+        );
+        Invite::issue(&self.secret_key, self.topic_id, expires_at, max_uses)
+    }
+
+    /// Stop an invite we or another member issued from letting anyone else in.
+    /// Returns the message's id and the timestamp it was signed with.
+    pub async fn revoke_invite(&self, invite_id: InviteId) -> Result<(MessageId, u64)> {
+        let (id, timestamp) = self
+            .broadcast_signed(Message::RevokeInvite { invite_id })
+            .await?;
+        self.moderation.revoke(self.node_id(), invite_id, timestamp);
+        Ok((id, timestamp))
+    }
+
+    /// Tell the room we are leaving, so peers see us offline right away.
+    pub async fn leave(&self) -> Result<()> {
+        self.broadcast(Message::Leave).await?;
+        Ok(())
+    }
+
+    /// Announce a file in the [`super::BlobStore`] to the room, returning its [`MessageId`].
+    pub async fn send_attachment(&self, attachment: Attachment) -> Result<MessageId> {
+        let Attachment {
+            hash,
+            name,
+            size,
+            mime,
+        } = attachment;
+        let nickname = self.nickname();
+        self.broadcast(Message::Attachment {
+            hash,
+            name,
+            size,
+            mime,
+            nickname,
+        })
+        .await
+    }
+
+    /// Replace the text of one of our own messages.
+    pub async fn edit(&self, target_id: MessageId, new_text: String) -> Result<MessageId> {
+        self.broadcast(Message::Edit {
+            target_id,
+            new_text,
+        })
+        .await
+    }
+
+    /// Delete one of our own messages, so it is no longer served to late joiners either.
+    pub async fn delete(&self, target_id: MessageId) -> Result<MessageId> {
+        let id = self.broadcast(Message::Delete { target_id }).await?;
+        self.history.remove(&self.topic_id, &target_id);
+        Ok(id)
+    }
+
+    /// Sign, seal and broadcast a message, keeping it to serve to late joiners if
+    /// it's part of the room's history.
+    async fn broadcast(&self, message: Message) -> Result<MessageId> {
+        let (id, _) = self.broadcast_signed(message).await?;
+        Ok(id)
+    }
+
+    /// Like [`Self::broadcast`], also returning the timestamp the message was signed with.
+    async fn broadcast_signed(&self, message: Message) -> Result<(MessageId, u64)> {
+        let stored = message.is_stored();
+        let deletes = message.deleted();
+        let (signed_message, timestamp) =
+            SignedMessage::sign_and_encode(&self.secret_key, message)?;
+        let id = MessageId::from_signed(&signed_message);
+        let sealed = crypto::seal(self.room_secret.as_ref(), signed_message)?;
+        self.sender.broadcast(sealed.clone().into()).await?;
+        if stored {
+            self.history.insert(
+                self.topic_id,
+                id,
+                self.node_id(),
+                timestamp,
+                sealed,
+                deletes,
+            );
+        }
+        Ok((id, timestamp))
+    }
+
+    /// The nickname our messages are currently sent with.
+    pub fn nickname(&self) -> String {
+        self.nickname.lock().expect("poisened").clone()
+    }
+
+    /// The node that signs our messages.
+    pub fn node_id(&self) -> NodeId {
+        self.secret_key.public()
+    }
+
+    pub fn set_nickname(&self, name: String) {
+        *self.nickname.lock().expect("poisened") = name;
+        self.trigger_presence.notify_waiters();
+    }
+
+    /// Tell the room whether we are typing, throttled to one update per [`TYPING_THROTTLE`].
+    pub fn set_typing(&self, active: bool) {
+        if self.typing.lock().expect("poisened").set(active) {
+            // Keep a permit if the presence task is busy, so the update isn't lost.
+            self.trigger_presence.notify_one();
+        }
+    }
+}
@@ -0,0 +1,13 @@
+//! Fixtures shared by the chat tests.
+
+use iroh::{NodeId, SecretKey};
+
+/// The key of a fresh peer.
+pub fn secret_key() -> SecretKey {
+    SecretKey::generate(rand::rngs::OsRng)
+}
+
+/// The id of a fresh peer.
+pub fn node_id() -> NodeId {
+    secret_key().public()
+}
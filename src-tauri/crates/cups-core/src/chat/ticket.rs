@@ -0,0 +1,245 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use iroh::NodeAddr;
+pub use iroh::NodeId;
+use iroh_base::ticket::Ticket;
+pub use iroh_gossip::proto::TopicId;
+use serde::{Deserialize, Serialize};
+
+use super::{crypto::RoomSecret, invite::Invite};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+/// Helper struct for frontend
+pub struct VisitedRoom {
+    pub id: String,
+    pub name: String,
+    pub ticket: String,
+    pub encrypted: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChatTicket {
+    pub topic_id: TopicId,
+    pub name: String,
+    pub bootstrap: BTreeSet<NodeId>,
+    /// Present for end-to-end encrypted rooms, absent for plaintext ones.
+    #[serde(default)]
+    pub secret: Option<RoomSecret>,
+    /// The node that created the room and moderates it, absent for rooms created
+    /// before moderation. Only counts if the room's topic derives from it.
+    #[serde(default)]
+    pub creator: Option<RoomCreator>,
+    /// Who besides the creator is let into an invite-only room, absent for rooms
+    /// anyone with a ticket can join.
+    #[serde(default)]
+    pub members: Option<BTreeSet<NodeId>>,
+    /// Limits on who may join with the ticket, absent for tickets valid forever.
+    #[serde(default)]
+    pub invite: Option<Invite>,
+    /// How to dial bootstrap nodes, so joining doesn't have to wait on discovery.
+    /// Empty in tickets that only name them.
+    #[serde(default)]
+    pub addrs: BTreeSet<NodeAddr>,
+}
+
+/// The original ticket layout, which only named the room and its bootstrap nodes.
+#[derive(Deserialize)]
+struct LegacyChatTicket {
+    topic_id: TopicId,
+    name: String,
+    bootstrap: BTreeSet<NodeId>,
+}
+
+/// The creator of a room with the salt its topic was derived from, so anyone
+/// holding a ticket can check the creator wasn't swapped for someone else.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoomCreator {
+    pub node_id: NodeId,
+    salt: [u8; 16],
+}
+
+impl RoomCreator {
+    fn generate(node_id: NodeId) -> Self {
+        Self {
+            node_id,
+            salt: rand::random(),
+        }
+    }
+
+    /// The topic of the room this node created with this salt.
+    fn topic_id(&self) -> TopicId {
+        let mut hasher = blake3::Hasher::new_derive_key("cups room topic v1");
+        hasher.update(self.node_id.as_bytes());
+        hasher.update(&self.salt);
+        TopicId::from_bytes(*hasher.finalize().as_bytes())
+    }
+}
+
+impl From<LegacyChatTicket> for ChatTicket {
+    fn from(ticket: LegacyChatTicket) -> Self {
+        Self {
+            topic_id: ticket.topic_id,
+            name: ticket.name,
+            bootstrap: ticket.bootstrap,
+            secret: None,
+            creator: None,
+            members: None,
+            invite: None,
+            addrs: BTreeSet::new(),
+        }
+    }
+}
+
+impl ChatTicket {
+    #[allow(unused)]
+    fn new_random() -> Self {
+        let topic_id = TopicId::from_bytes(rand::random());
+        Self::new(topic_id, "anonymous")
+    }
+    pub fn new_named(name: &str) -> Self {
+        let topic_id = TopicId::from_bytes(rand::random());
+        Self::new(topic_id, name)
+    }
+
+    pub fn new(topic_id: TopicId, name: &str) -> Self {
+        Self {
+            topic_id,
+            name: name.to_string(),
+            bootstrap: Default::default(),
+            secret: None,
+            creator: None,
+            members: None,
+            invite: None,
+            addrs: Default::default(),
+        }
+    }
+    /// Generate a fresh secret, making this an end-to-end encrypted room.
+    pub fn with_secret(mut self) -> Self {
+        self.secret = Some(RoomSecret::generate());
+        self
+    }
+    /// Record `creator` as the room's first admin, deriving the room's topic from
+    /// it so that it can't be replaced in copies of the ticket.
+    pub fn with_creator(mut self, creator: NodeId) -> Self {
+        let creator = RoomCreator::generate(creator);
+        self.topic_id = creator.topic_id();
+        self.creator = Some(creator);
+        self
+    }
+    /// The room's creator, if the ticket records one its topic derives from.
+    pub fn creator(&self) -> Option<NodeId> {
+        self.creator
+            .filter(|creator| creator.topic_id() == self.topic_id)
+            .map(|creator| creator.node_id)
+    }
+    /// Only let the creator and the members its admins add into the room.
+    pub fn invite_only(mut self) -> Self {
+        self.members = Some(BTreeSet::new());
+        self
+    }
+    /// Limit who may join with the ticket to what `invite` allows.
+    pub fn with_invite(mut self, invite: Invite) -> Self {
+        self.invite = Some(invite);
+        self
+    }
+    /// The same ticket without its invite, for rejoining a room we got into.
+    pub fn without_invite(mut self) -> Self {
+        self.invite = None;
+        self
+    }
+    pub fn deserialize(input: &str) -> Result<Self> {
+        <Self as Ticket>::deserialize(input).map_err(Into::into)
+    }
+    pub fn serialize(&self) -> String {
+        <Self as Ticket>::serialize(self)
+    }
+}
+
+impl Ticket for ChatTicket {
+    const KIND: &'static str = "chat";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(&self).unwrap()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, iroh_base::ticket::Error> {
+        // The legacy layout is a prefix of the current one, so try the current first.
+        let ticket: Self = postcard::from_bytes(bytes)
+            .or_else(|_| postcard::from_bytes::<LegacyChatTicket>(bytes).map(Into::into))?;
+        if ticket.creator.is_some() && ticket.creator().is_none() {
+            return Err(iroh_base::ticket::Error::Verify(
+                "ticket's creator doesn't match its room",
+            ));
+        }
+        Ok(ticket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::testing::node_id;
+
+    /// The original layout, as older builds still hand it out.
+    #[derive(Serialize)]
+    struct Legacy {
+        topic_id: TopicId,
+        name: String,
+        bootstrap: BTreeSet<NodeId>,
+    }
+
+    #[test]
+    fn legacy_tickets_still_open() {
+        let legacy = Legacy {
+            topic_id: TopicId::from_bytes(rand::random()),
+            name: "old".to_string(),
+            bootstrap: [node_id()].into(),
+        };
+        let bytes = postcard::to_stdvec(&legacy).unwrap();
+        let ticket = <ChatTicket as Ticket>::from_bytes(&bytes).unwrap();
+        assert_eq!(ticket.topic_id, legacy.topic_id);
+        assert_eq!(ticket.name, legacy.name);
+        assert_eq!(ticket.bootstrap, legacy.bootstrap);
+        assert!(ticket.secret.is_none());
+        assert!(ticket.creator().is_none());
+        assert!(ticket.members.is_none());
+        // Rejoining hands out the current layout.
+        assert_eq!(
+            ChatTicket::deserialize(&ticket.serialize()).unwrap(),
+            ticket
+        );
+    }
+
+    #[test]
+    fn current_tickets_round_trip() {
+        let mut ticket = ChatTicket::new_named("room")
+            .with_creator(node_id())
+            .with_secret()
+            .invite_only();
+        ticket.bootstrap.insert(node_id());
+        ticket.members = Some([node_id()].into());
+        assert_eq!(
+            ChatTicket::deserialize(&ticket.serialize()).unwrap(),
+            ticket
+        );
+    }
+
+    #[test]
+    fn rooms_are_bound_to_their_creator() {
+        let creator = node_id();
+        let ticket = ChatTicket::new_named("room").with_creator(creator);
+        assert_eq!(ticket.creator(), Some(creator));
+
+        // Claiming someone else's room doesn't hold up.
+        let mut stolen = ticket.clone();
+        stolen.creator.as_mut().unwrap().node_id = node_id();
+        assert!(stolen.creator().is_none());
+        assert!(ChatTicket::deserialize(&stolen.serialize()).is_err());
+
+        // Nor does claiming a room under another topic.
+        let mut moved = ticket;
+        moved.topic_id = TopicId::from_bytes(rand::random());
+        assert!(ChatTicket::deserialize(&moved.serialize()).is_err());
+    }
+}
@@ -0,0 +1,12 @@
+//! The chat behind Cups: rooms over iroh gossip, direct messages, attachments,
+//! peer tracking and message persistence. Frontends plug in through an
+//! [`EventSink`] to hear about what happens and a [`KeyValueStore`] for settings.
+
+pub mod api;
+pub mod chat;
+mod sink;
+pub mod state;
+pub mod utils;
+
+pub use sink::{EventSink, FanoutSink};
+pub use state::{KeyValueStore, MemoryStore};
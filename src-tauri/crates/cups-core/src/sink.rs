@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+/// Where a frontend hears about what happens in the chat.
+///
+/// Events are named (`chat-event`, `peers-event`, `direct-event`, ...) and carry
+/// a JSON payload, the way the Tauri app passes them on to the webview.
+pub trait EventSink: Send + Sync + 'static {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> anyhow::Result<()>;
+}
+
+impl dyn EventSink {
+    /// Serialize a payload and emit it, logging rather than failing if that doesn't work.
+    pub fn send(&self, event: &str, payload: impl Serialize) {
+        let result = serde_json::to_value(payload)
+            .map_err(anyhow::Error::from)
+            .and_then(|payload| self.emit(event, payload));
+        if let Err(e) = result {
+            tracing::error!("Failed to emit {} event: {}", event, e);
+        }
+    }
+}
+
+/// Delivers every event to each of a list of sinks, e.g. the UI and the local API.
+pub struct FanoutSink(pub Vec<Arc<dyn EventSink>>);
+
+impl EventSink for FanoutSink {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        for sink in &self.0 {
+            // Keep going, one sink failing shouldn't starve the others.
+            if let Err(e) = sink.emit(event, payload.clone()) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
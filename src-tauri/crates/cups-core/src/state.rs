@@ -0,0 +1,177 @@
+mod context;
+mod db;
+mod downloads;
+mod store;
+
+pub use context::AppContext;
+pub use db::{
+    ChatDb, DirectConversation, InviteRecord, IssuedInvite, ModerationRecord, PendingAmendment,
+    ReactionSummary, ReceiptSummary, StoredDirectMessage, StoredMessage,
+};
+pub use downloads::DownloadProgress;
+pub use store::{AppStore, KeyValueStore, MemoryStore};
+
+/// File name of the message database within the app's config directory.
+pub const CHAT_DB_FILE: &str = "chat.db";
+
+/// A step in the message database's schema, applied once in `version` order.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+pub fn generate_db_migrations() -> Vec<Migration> {
+    let migrations = vec![
+        Migration {
+            version: 1,
+            description: "create_initial_tables",
+            sql: "
+                CREATE TABLE conversations (
+                    id TEXT PRIMARY KEY,
+                    name TEXT,
+                    last_message_at INTEGER
+                );
+                CREATE TABLE messages (
+                    id TEXT PRIMARY KEY,
+                    conversation_id TEXT NOT NULL,
+                    content TEXT,
+                    created_at INTEGER NOT NULL,
+                    sender_id TEXT,
+                    nickname TEXT,
+                    -- The corrected foreign key with ON DELETE CASCADE --
+                    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+            ",
+        },
+        Migration {
+            version: 2,
+            description: "create_direct_messages",
+            sql: "
+                CREATE TABLE direct_messages (
+                    id TEXT PRIMARY KEY,
+                    -- The other side of the conversation, whoever sent the message --
+                    peer_id TEXT NOT NULL,
+                    sender_id TEXT NOT NULL,
+                    nickname TEXT,
+                    content TEXT,
+                    created_at INTEGER NOT NULL
+                );
+                CREATE INDEX direct_messages_by_peer ON direct_messages (peer_id, created_at);
+            ",
+        },
+        Migration {
+            version: 3,
+            description: "add_message_attachments",
+            sql: "
+                -- JSON {hash, name, size, mime} of a shared file, content is NULL for these --
+                ALTER TABLE messages ADD COLUMN attachment TEXT;
+            ",
+        },
+        Migration {
+            version: 4,
+            description: "add_message_edits",
+            sql: "
+                -- Send time of the latest edit by the author --
+                ALTER TABLE messages ADD COLUMN edited_at INTEGER;
+                -- Set when the author deleted the message, content and attachment are then NULL --
+                ALTER TABLE messages ADD COLUMN deleted_at INTEGER;
+                -- Edits and deletions of messages we don't have yet, applied once the message arrives if its author sent them, `content` is NULL for deletions --
+                CREATE TABLE pending_amendments (
+                    id TEXT PRIMARY KEY,
+                    conversation_id TEXT NOT NULL,
+                    target_id TEXT NOT NULL,
+                    sender_id TEXT NOT NULL,
+                    content TEXT,
+                    created_at INTEGER NOT NULL,
+                    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+                CREATE INDEX pending_amendments_by_target ON pending_amendments (target_id);
+            ",
+        },
+        Migration {
+            version: 5,
+            description: "add_message_replies",
+            sql: "
+                -- Id of the message this one answers, the root of a thread has none --
+                ALTER TABLE messages ADD COLUMN reply_to TEXT;
+                CREATE INDEX IF NOT EXISTS idx_messages_reply_to ON messages(reply_to);
+            ",
+        },
+        Migration {
+            version: 6,
+            description: "create_reactions",
+            sql: "
+                -- The latest reaction of each sender with each emoji, `added` is 0 once taken back --
+                CREATE TABLE reactions (
+                    message_id TEXT NOT NULL,
+                    conversation_id TEXT NOT NULL,
+                    sender_id TEXT NOT NULL,
+                    emoji TEXT NOT NULL,
+                    added INTEGER NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    PRIMARY KEY (message_id, sender_id, emoji),
+                    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+            ",
+        },
+        Migration {
+            version: 7,
+            description: "create_receipts",
+            sql: "
+                -- Peers that acknowledged a message, `kind` is 'delivered' or 'read' --
+                CREATE TABLE receipts (
+                    message_id TEXT NOT NULL,
+                    node_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    received_at INTEGER NOT NULL,
+                    PRIMARY KEY (message_id, node_id, kind),
+                    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+                );
+            ",
+        },
+        Migration {
+            version: 8,
+            description: "create_moderation",
+            sql: "
+                -- The latest moderation of each room member, `kind` is 'admin', 'ban', 'mute' or 'member', `active` is 0 once lifted --
+                CREATE TABLE moderation (
+                    conversation_id TEXT NOT NULL,
+                    node_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    active INTEGER NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    PRIMARY KEY (conversation_id, node_id, kind),
+                    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+            ",
+        },
+        Migration {
+            version: 9,
+            description: "create_invites",
+            sql: "
+                -- Invites we issued, `expires_at` and `max_uses` are NULL when unlimited --
+                CREATE TABLE invites (
+                    id TEXT PRIMARY KEY,
+                    conversation_id TEXT NOT NULL,
+                    expires_at INTEGER,
+                    max_uses INTEGER,
+                    created_at INTEGER NOT NULL,
+                    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+                -- Newcomers that joined with an invite and peers that revoked one, `kind` is 'redeemed' or 'revoked' --
+                CREATE TABLE invite_events (
+                    conversation_id TEXT NOT NULL,
+                    invite_id TEXT NOT NULL,
+                    node_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    at INTEGER NOT NULL,
+                    PRIMARY KEY (invite_id, node_id, kind),
+                    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+            ",
+        },
+    ];
+    migrations
+}
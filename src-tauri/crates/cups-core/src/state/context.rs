@@ -0,0 +1,1514 @@
+use crate::chat::{
+    channel::{Channel, ChatReceiver, TicketOpts, TopicId},
+    guess_mime,
+    peers::{PeerInfo, PeerMap, PeerRole, PeerStatus},
+    AcceptanceWindow, Attachment, BlobHash, BlobStore, ChatNode, ChatSender, ChatTicket,
+    DirectEvent, Event, InviteId, MessageId, MessageReceipts, ModerationAction, ReactionCount,
+    ReceiptKind, RoomEvent, RoomModeration, VisitedRoom, MAX_REACTION_LEN, MAX_RECEIPT_BATCH,
+};
+use crate::state::{
+    downloads::{unique_path, DownloadProgress, Downloads},
+    AppStore, ChatDb, IssuedInvite, PendingAmendment, ReactionSummary, ReceiptSummary,
+    StoredDirectMessage, StoredMessage,
+};
+use crate::{utils::get_timestamp, EventSink};
+use anyhow::anyhow;
+use iroh::{Endpoint, NodeId};
+use n0_future::{task::AbortOnDropHandle, StreamExt as _};
+use sqlx::types::Json;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    select,
+    sync::{mpsc, Mutex as TokioMutex},
+    time::{interval, timeout, Duration},
+};
+
+type Channels = Arc<TokioMutex<HashMap<TopicId, ActiveChannel>>>;
+
+/// Wait before the first attempt to rejoin a disconnected room, doubled on each failure.
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+/// Longest we wait for a room to take our goodbye before dropping it anyway.
+const LEAVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Holds information about a chat channel we are participating in.
+struct ActiveChannel {
+    inner: Channel,
+    peers: Arc<TokioMutex<PeerMap>>,
+    receiver_handle: AbortOnDropHandle<()>,
+}
+
+impl ActiveChannel {
+    /// Tell the room we are leaving, then stop listening to it. Returns its topic ID.
+    async fn leave(self) -> String {
+        match timeout(LEAVE_TIMEOUT, self.inner.sender().leave()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to announce leaving {}: {}", self.inner.id(), e),
+            Err(_) => tracing::warn!("Timed out announcing leaving {}", self.inner.id()),
+        }
+        self.receiver_handle.abort();
+        self.inner.id()
+    }
+}
+
+/// Holds the application's runtime context, including the iroh client,
+/// the rooms we have joined, and their background task handles.
+///
+/// Cheap to clone, clones share the same state.
+#[derive(Clone)]
+pub struct AppContext {
+    // The iroh client instance used for all interactions. Option<> because it's initialized async.
+    pub node: Arc<TokioMutex<Option<ChatNode>>>,
+    channels: Channels,
+    pub latest_ticket: Arc<TokioMutex<Option<String>>>,
+    // Where received and sent messages are persisted. Option<> because it's opened async.
+    pub db: Arc<TokioMutex<Option<ChatDb>>>,
+    direct_listener: Arc<TokioMutex<Option<AbortOnDropHandle<()>>>>,
+    downloads: Downloads,
+    // Rooms we send delivery and read receipts in.
+    receipt_rooms: Arc<TokioMutex<HashSet<TopicId>>>,
+    // Where chat, peer, direct and attachment events are delivered.
+    sink: Arc<dyn EventSink>,
+}
+
+impl AppContext {
+    /// Creates a new, empty AppContext that delivers its events to `sink`.
+    pub fn new(sink: Arc<dyn EventSink>) -> Self {
+        Self {
+            node: Arc::new(TokioMutex::new(None)),
+            channels: Arc::new(TokioMutex::new(HashMap::new())),
+            latest_ticket: Arc::new(TokioMutex::new(None)),
+            db: Arc::new(TokioMutex::new(None)),
+            direct_listener: Arc::new(TokioMutex::new(None)),
+            downloads: Downloads::default(),
+            receipt_rooms: Arc::new(TokioMutex::new(HashSet::new())),
+            sink,
+        }
+    }
+    /// Get a handle to the message database.
+    pub async fn get_db(&self) -> anyhow::Result<ChatDb> {
+        self.db
+            .lock()
+            .await
+            .clone()
+            .ok_or_else(|| anyhow!("Database not initialized"))
+    }
+    /// Return a list of the known members of this room's Gossip Swarm.
+    pub async fn get_peers(&self, topic: &TopicId) -> anyhow::Result<Vec<PeerInfo>> {
+        let peers = match self.channels.lock().await.get(topic) {
+            Some(channel) => channel.peers.clone(),
+            None => return Err(anyhow!("Could not get peers. Not in room {topic}.")),
+        };
+        let peers = peers.lock().await;
+        Ok(peers.to_vec())
+    }
+    /// Get the topic IDs of every room we are currently in.
+    pub async fn get_topic_ids(&self) -> Vec<TopicId> {
+        self.channels.lock().await.keys().copied().collect()
+    }
+    /// Generate a new ticket token string for a room.
+    pub async fn generate_ticket(
+        &self,
+        topic: &TopicId,
+        options: TicketOpts,
+    ) -> anyhow::Result<ChatTicket> {
+        match self.channels.lock().await.get(topic) {
+            Some(channel) => channel.inner.ticket(options),
+            None => Err(anyhow!("Could not generate ticket. Not in room {topic}.")),
+        }
+    }
+    /// Create a new room and join it, returning the ticket others can join it with.
+    pub async fn create_room(
+        &self,
+        name: &str,
+        nickname: &str,
+        encrypted: bool,
+        invite_only: bool,
+        store: &AppStore,
+    ) -> anyhow::Result<String> {
+        let node_guard = self.node.lock().await;
+        let Some(node) = node_guard.as_ref() else {
+            return Err(anyhow!("Node not initialized"));
+        };
+
+        // Create a new ticket to initialize the channel.
+        // generate_channel will ensure this node is part of the bootstrap.
+        let mut initial_ticket = ChatTicket::new_named(name).with_creator(node.node_id());
+        if encrypted {
+            initial_ticket = initial_ticket.with_secret();
+        }
+        if invite_only {
+            initial_ticket = initial_ticket.invite_only();
+        }
+
+        let mut channel = node
+            .generate_channel(initial_ticket, nickname.to_string())
+            .map_err(|e| anyhow!("Failed to generate channel: {}", e))?;
+
+        // Take the receiver from the Channel object to give to spawn_event_listener
+        let rx = channel
+            .take_receiver()
+            .ok_or_else(|| anyhow!("Receiver already taken from channel object"))?;
+
+        store.set_nickname(nickname)?;
+
+        let topic = channel.topic_id();
+        self.load_receipts(&topic, store).await;
+        // Store the active channel info
+        let topic_id_str = self.start_channel(channel, rx, nickname).await?;
+
+        tracing::info!("Created and joined room: {}", topic_id_str);
+
+        // Generate ticket string from the Channel instance to be shared
+        let ticket = self.generate_ticket(&topic, TicketOpts::all()).await?;
+        store.update_visited_room(ticket.clone())?;
+        let ticket_token = ticket.serialize();
+        *self.latest_ticket.lock().await = Some(ticket_token.clone());
+        Ok(ticket_token)
+    }
+    /// Join an existing room from a ticket string.
+    pub async fn join_room(
+        &self,
+        ticket: &str,
+        nickname: &str,
+        store: &AppStore,
+    ) -> anyhow::Result<()> {
+        if self.node.lock().await.is_none() {
+            return Err(anyhow!("Node not initialized"));
+        }
+
+        tracing::info!("deserializing ticket token: {}", ticket);
+        let chat_ticket = ChatTicket::deserialize(ticket)?;
+        // The invite got us in, it's not ours to hand on or to rejoin with.
+        let rejoin_ticket = chat_ticket.clone().without_invite();
+        *self.latest_ticket.lock().await = Some(rejoin_ticket.serialize());
+
+        let topic_id_str = self.enter_room(chat_ticket, nickname, store).await?;
+
+        store.set_nickname(nickname)?;
+        store.update_visited_room(rejoin_ticket)?;
+        tracing::info!("Joined room: {}", topic_id_str);
+        Ok(())
+    }
+    /// Subscribe to a room and start listening to it. Returns its topic ID.
+    async fn enter_room(
+        &self,
+        ticket: ChatTicket,
+        nickname: &str,
+        store: &AppStore,
+    ) -> anyhow::Result<String> {
+        let topic = ticket.topic_id;
+        let mut channel = match self.node.lock().await.as_ref() {
+            Some(node) => node
+                .generate_channel(ticket, nickname.to_string())
+                .map_err(|e| anyhow!("Failed to generate channel: {}", e))?,
+            None => return Err(anyhow!("Node not initialized")),
+        };
+
+        // Take the receiver from the Channel object
+        let rx = channel
+            .take_receiver()
+            .ok_or_else(|| anyhow!("Receiver already taken from channel object"))?;
+
+        // Store the active channel info
+        let topic_id_str = self.start_channel(channel, rx, nickname).await?;
+        self.load_receipts(&topic, store).await;
+        Ok(topic_id_str)
+    }
+    /// Leave a joined room, remembering its latest ticket to rejoin it with.
+    pub async fn leave_room(&self, topic: &TopicId, store: &AppStore) -> anyhow::Result<()> {
+        if let Ok(ticket) = self.generate_ticket(topic, TicketOpts::all()).await {
+            store.update_visited_room(ticket)?;
+        };
+        if let Some(id) = self.drop_channel(topic).await? {
+            tracing::info!("Left room: {}", id);
+        };
+        if let Some(node) = self.node.lock().await.as_ref() {
+            node.forget_room(topic);
+        }
+        Ok(())
+    }
+    /// Start sending receipts in a room if they were enabled for it.
+    async fn load_receipts(&self, topic: &TopicId, store: &AppStore) {
+        let enabled = store.get_receipt_rooms().contains(&topic.to_string());
+        let mut rooms = self.receipt_rooms.lock().await;
+        if enabled {
+            rooms.insert(*topic);
+        } else {
+            rooms.remove(topic);
+        }
+    }
+    /// Turn sending delivery and read receipts in a room on or off.
+    pub async fn set_receipts(
+        &self,
+        topic: &TopicId,
+        enabled: bool,
+        store: &AppStore,
+    ) -> anyhow::Result<()> {
+        store.set_receipts(&topic.to_string(), enabled)?;
+        self.load_receipts(topic, store).await;
+        Ok(())
+    }
+    /// Whether we send delivery and read receipts in a room.
+    pub async fn receipts_enabled(&self, topic: &TopicId) -> bool {
+        self.receipt_rooms.lock().await.contains(topic)
+    }
+    /// Tell a room we have read some of its messages, if receipts are enabled for it.
+    pub async fn mark_read(&self, topic: &TopicId, message_ids: &[String]) -> anyhow::Result<()> {
+        if !self.receipts_enabled(topic).await {
+            return Ok(());
+        }
+        let ids = message_ids
+            .iter()
+            .map(|id| id.parse())
+            .collect::<anyhow::Result<Vec<MessageId>>>()?;
+        self.get_sender(topic)
+            .await?
+            .send_receipts(&ids, ReceiptKind::Read)
+            .await
+    }
+    /// Who acknowledged the given messages of a room.
+    pub async fn get_receipts(
+        &self,
+        topic: &TopicId,
+        message_ids: &[String],
+    ) -> anyhow::Result<Vec<ReceiptSummary>> {
+        self.get_db().await?.get_receipts(topic, message_ids).await
+    }
+    /// The room most recently created or joined, with its latest ticket.
+    pub async fn latest_room(&self) -> anyhow::Result<Option<VisitedRoom>> {
+        let ticket_guard = self.latest_ticket.lock().await;
+        match ticket_guard.as_ref() {
+            Some(ticket_string) => {
+                let ticket = ChatTicket::deserialize(ticket_string)?;
+                Ok(Some(VisitedRoom {
+                    id: ticket.topic_id.to_string(),
+                    name: ticket.name,
+                    ticket: ticket_string.clone(),
+                    encrypted: ticket.secret.is_some(),
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+    /// Get a sender for messages on a room's channel.
+    pub async fn get_sender(&self, topic: &TopicId) -> anyhow::Result<ChatSender> {
+        match self.channels.lock().await.get(topic) {
+            Some(channel) => Ok(channel.inner.sender()),
+            None => Err(anyhow!("Could not get sender. Not in room {topic}.")),
+        }
+    }
+    /// Change how far message timestamps may drift from local time, in rooms
+    /// joined from now on.
+    pub async fn set_acceptance_window(
+        &self,
+        window: AcceptanceWindow,
+        store: &AppStore,
+    ) -> anyhow::Result<()> {
+        store.set_acceptance_window(window)?;
+        if let Some(node) = self.node.lock().await.as_ref() {
+            node.set_acceptance_window(window);
+        }
+        Ok(())
+    }
+    /// Broadcast a message to a room, optionally as a reply to another of its
+    /// messages, and persist it as sent by us.
+    pub async fn send_message(
+        &self,
+        topic: &TopicId,
+        text: String,
+        reply_to: Option<MessageId>,
+    ) -> anyhow::Result<MessageId> {
+        let sender = self.get_sender(topic).await?;
+        let id = match reply_to {
+            Some(parent) => sender.reply(parent, text.clone()).await?,
+            None => sender.send(text.clone()).await?,
+        };
+        self.persist_sent(topic, &sender, id, Some(text), None, reply_to)
+            .await?;
+        Ok(id)
+    }
+    /// Tell a room whether we are typing. Updates are throttled by the sender.
+    pub async fn set_typing(&self, topic: &TopicId, active: bool) -> anyhow::Result<()> {
+        self.get_sender(topic).await?.set_typing(active);
+        Ok(())
+    }
+    /// A message of a room and all replies below it, oldest first.
+    pub async fn get_thread(
+        &self,
+        topic: &TopicId,
+        message_id: &str,
+    ) -> anyhow::Result<Vec<StoredMessage>> {
+        self.get_db().await?.get_thread(topic, message_id).await
+    }
+    /// Share a file with a room and persist the announcement as sent by us.
+    pub async fn send_attachment(&self, topic: &TopicId, path: &Path) -> anyhow::Result<MessageId> {
+        let sender = self.get_sender(topic).await?;
+        let store = self.get_blobs().await?.0;
+        let (hash, size) = store.import(path).await?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+        let attachment = Attachment {
+            hash,
+            mime: guess_mime(&name).to_string(),
+            name,
+            size,
+        };
+        let id = sender.send_attachment(attachment.clone()).await?;
+        self.persist_sent(topic, &sender, id, None, Some(attachment), None)
+            .await?;
+        Ok(id)
+    }
+    /// Replace the text of one of our own messages in a room.
+    pub async fn edit_message(
+        &self,
+        topic: &TopicId,
+        message_id: &str,
+        new_text: String,
+    ) -> anyhow::Result<MessageId> {
+        let sender = self.get_sender(topic).await?;
+        let message = self.get_own_message(&sender, message_id).await?;
+        if message.attachment.is_some() {
+            return Err(anyhow!("Attachments can't be edited"));
+        }
+        let id = sender.edit(message_id.parse()?, new_text.clone()).await?;
+        let db = self.get_db().await?;
+        let author = sender.node_id().to_string();
+        if let Err(e) = db
+            .edit_message(message_id, &author, &new_text, get_timestamp() as i64)
+            .await
+        {
+            tracing::error!("Failed to persist edit of {}: {}", message_id, e);
+        }
+        Ok(id)
+    }
+    /// Act on a member of a room we moderate, as if a peer had told us of it.
+    pub async fn moderate(
+        &self,
+        topic: &TopicId,
+        node_id: &str,
+        action: ModerationAction,
+    ) -> anyhow::Result<MessageId> {
+        let sender = self.get_sender(topic).await?;
+        let target: NodeId = node_id.parse()?;
+        let (id, sent_timestamp) = sender.moderate(target, action).await?;
+        let event = Event::Moderation {
+            id,
+            from: sender.node_id(),
+            target,
+            action,
+            sent_timestamp,
+        };
+        if let Some(channel) = self.channels.lock().await.get(topic) {
+            channel
+                .peers
+                .lock()
+                .await
+                .update(Some(&event), &mut HashSet::new(), &*self.sink);
+        }
+        persist_event(*topic, &event, &self.get_db().await?).await;
+        emit_chat_event(&*self.sink, *topic, &event);
+        Ok(id)
+    }
+    /// Issue an invite to a room that expires after `expires_in` and lets in at most
+    /// `max_uses` newcomers, if set. Returns the ticket carrying it.
+    pub async fn create_invite(
+        &self,
+        topic: &TopicId,
+        expires_in: Option<Duration>,
+        max_uses: Option<u32>,
+    ) -> anyhow::Result<String> {
+        let sender = self.get_sender(topic).await?;
+        let now = get_timestamp();
+        let expires_at = expires_in.map(|expires_in| now + expires_in.as_micros() as u64);
+        let invite = sender.invite(expires_at, max_uses)?;
+        let ticket = self
+            .generate_ticket(topic, TicketOpts::all())
+            .await?
+            .with_invite(invite.clone());
+        self.get_db()
+            .await?
+            .insert_invite(
+                topic,
+                &invite.id().to_string(),
+                expires_at.map(|at| at as i64),
+                max_uses.map(i64::from),
+                now as i64,
+            )
+            .await?;
+        Ok(ticket.serialize())
+    }
+    /// Revoke an invite to a room we issued, or any invite if we are an admin.
+    pub async fn revoke_invite(
+        &self,
+        topic: &TopicId,
+        invite_id: &str,
+    ) -> anyhow::Result<MessageId> {
+        let sender = self.get_sender(topic).await?;
+        let db = self.get_db().await?;
+        let issued = db
+            .get_issued_invites(topic)
+            .await?
+            .iter()
+            .any(|invite| invite.id == invite_id);
+        let is_admin = match self.channels.lock().await.get(topic) {
+            Some(channel) => channel.inner.moderation().is_admin(&sender.node_id()),
+            None => false,
+        };
+        if !issued && !is_admin {
+            return Err(anyhow!("Only the issuer or an admin can revoke an invite"));
+        }
+        let invite_id: InviteId = invite_id.parse()?;
+        let (id, sent_timestamp) = sender.revoke_invite(invite_id).await?;
+        let event = Event::InviteRevoked {
+            id,
+            from: sender.node_id(),
+            invite_id,
+            sent_timestamp,
+        };
+        persist_event(*topic, &event, &db).await;
+        emit_chat_event(&*self.sink, *topic, &event);
+        Ok(id)
+    }
+    /// The invites we issued to a room, newest first.
+    pub async fn get_invites(&self, topic: &TopicId) -> anyhow::Result<Vec<IssuedInvite>> {
+        self.get_db().await?.get_issued_invites(topic).await
+    }
+    /// Who moderates a room and who they banned or muted.
+    pub async fn get_moderation(&self, topic: &TopicId) -> anyhow::Result<RoomModeration> {
+        match self.channels.lock().await.get(topic) {
+            Some(channel) => Ok(channel.inner.moderation().snapshot()),
+            None => Err(anyhow!("Not in room {topic}.")),
+        }
+    }
+    /// Delete one of our own messages in a room, leaving a tombstone for peers.
+    pub async fn delete_message(
+        &self,
+        topic: &TopicId,
+        message_id: &str,
+    ) -> anyhow::Result<MessageId> {
+        let sender = self.get_sender(topic).await?;
+        self.get_own_message(&sender, message_id).await?;
+        let id = sender.delete(message_id.parse()?).await?;
+        let db = self.get_db().await?;
+        let author = sender.node_id().to_string();
+        if let Err(e) = db
+            .delete_message(message_id, &author, get_timestamp() as i64)
+            .await
+        {
+            tracing::error!("Failed to persist deletion of {}: {}", message_id, e);
+        }
+        Ok(id)
+    }
+    /// Add or take back our reaction to a message, emitting the message's new reactions.
+    pub async fn react(
+        &self,
+        topic: &TopicId,
+        message_id: &str,
+        emoji: String,
+        add: bool,
+    ) -> anyhow::Result<MessageId> {
+        let sender = self.get_sender(topic).await?;
+        let target: MessageId = message_id.parse()?;
+        let id = sender.react(target, emoji.clone(), add).await?;
+        let db = self.get_db().await?;
+        let author = sender.node_id().to_string();
+        match db
+            .set_reaction(
+                topic,
+                message_id,
+                &author,
+                &emoji,
+                add,
+                get_timestamp() as i64,
+            )
+            .await
+        {
+            Ok(_) => emit_reaction_summary(*topic, target, &db, &*self.sink).await,
+            Err(e) => tracing::error!("Failed to persist reaction to {}: {}", message_id, e),
+        }
+        Ok(id)
+    }
+    /// The current reactions in a room to the given messages.
+    pub async fn get_reactions(
+        &self,
+        topic: &TopicId,
+        message_ids: &[String],
+    ) -> anyhow::Result<Vec<ReactionSummary>> {
+        self.get_db().await?.get_reactions(topic, message_ids).await
+    }
+    /// Look up a persisted message we sent that hasn't been deleted.
+    async fn get_own_message(
+        &self,
+        sender: &ChatSender,
+        message_id: &str,
+    ) -> anyhow::Result<StoredMessage> {
+        let message = self
+            .get_db()
+            .await?
+            .get_message(message_id)
+            .await?
+            .filter(|message| message.deleted_at.is_none())
+            .ok_or_else(|| anyhow!("Unknown message {message_id}"))?;
+        if message.sender_id != Some(sender.node_id().to_string()) {
+            return Err(anyhow!("Can only change your own messages"));
+        }
+        Ok(message)
+    }
+    /// Record a message we broadcast to a room.
+    async fn persist_sent(
+        &self,
+        topic: &TopicId,
+        sender: &ChatSender,
+        id: MessageId,
+        content: Option<String>,
+        attachment: Option<Attachment>,
+        reply_to: Option<MessageId>,
+    ) -> anyhow::Result<()> {
+        let message = StoredMessage {
+            id: id.to_string(),
+            conversation_id: topic.to_string(),
+            content,
+            created_at: get_timestamp() as i64,
+            sender_id: Some(sender.node_id().to_string()),
+            nickname: Some(sender.nickname()),
+            attachment: attachment.map(Json),
+            edited_at: None,
+            deleted_at: None,
+            reply_to: reply_to.map(|id| id.to_string()),
+        };
+        // The message is already out, so a failed write must not fail the send.
+        if let Err(e) = self.get_db().await?.insert_message(&message).await {
+            tracing::error!("Failed to persist sent message {}: {}", id, e);
+        }
+        Ok(())
+    }
+    /// The attachment store and the endpoint to fetch attachments with.
+    async fn get_blobs(&self) -> anyhow::Result<(BlobStore, Endpoint)> {
+        let node_guard = self.node.lock().await;
+        let Some(node) = node_guard.as_ref() else {
+            return Err(anyhow!("Node not initialized"));
+        };
+        Ok((node.blobs().clone(), node.endpoint().clone()))
+    }
+    /// Look up the attachment announced by a persisted message.
+    async fn get_attachment(
+        &self,
+        message_id: &str,
+    ) -> anyhow::Result<(StoredMessage, Attachment)> {
+        let message = self
+            .get_db()
+            .await?
+            .get_message(message_id)
+            .await?
+            .ok_or_else(|| anyhow!("Unknown message {message_id}"))?;
+        let attachment = message
+            .attachment
+            .clone()
+            .ok_or_else(|| anyhow!("Message {message_id} has no attachment"))?;
+        Ok((message, attachment.0))
+    }
+    /// Start fetching a message's attachment from its sender, falling back to
+    /// the room's online peers. Progress is emitted as `attachment-event`s.
+    pub async fn download_attachment(&self, message_id: &str) -> anyhow::Result<BlobHash> {
+        let (message, attachment) = self.get_attachment(message_id).await?;
+        let topic: TopicId = message.conversation_id.parse()?;
+        let mut providers: Vec<NodeId> = message
+            .sender_id
+            .iter()
+            .filter_map(|id| id.parse().ok())
+            .collect();
+        if let Ok(peers) = self.get_peers(&topic).await {
+            for peer in peers {
+                let online = peer.role != PeerRole::Myself && peer.status == PeerStatus::Online;
+                if online && !providers.contains(&peer.id) {
+                    providers.push(peer.id);
+                }
+            }
+        }
+        let hash = attachment.hash;
+        let (store, endpoint) = self.get_blobs().await?;
+        self.downloads
+            .start(
+                store,
+                endpoint,
+                topic,
+                attachment,
+                providers,
+                self.sink.clone(),
+            )
+            .await;
+        Ok(hash)
+    }
+    /// Stop fetching an attachment. Returns false if it wasn't being fetched.
+    pub async fn cancel_download(&self, hash: &BlobHash) -> bool {
+        self.downloads.cancel(hash, &*self.sink).await
+    }
+    /// How much of a message's attachment we have.
+    pub async fn get_download_progress(
+        &self,
+        message_id: &str,
+    ) -> anyhow::Result<DownloadProgress> {
+        let (_, attachment) = self.get_attachment(message_id).await?;
+        if let Some(progress) = self.downloads.progress(&attachment.hash).await {
+            return Ok(progress);
+        }
+        let complete = self.get_blobs().await?.0.has(&attachment.hash);
+        Ok(DownloadProgress {
+            hash: attachment.hash,
+            received: if complete { attachment.size } else { 0 },
+            size: attachment.size,
+            complete,
+        })
+    }
+    /// Save a downloaded attachment into `dir` under its own name, returning where it went.
+    pub async fn save_attachment(&self, message_id: &str, dir: &Path) -> anyhow::Result<PathBuf> {
+        let (_, attachment) = self.get_attachment(message_id).await?;
+        let destination = unique_path(dir, &attachment.name);
+        self.get_blobs()
+            .await?
+            .0
+            .export(&attachment.hash, &destination)
+            .await?;
+        Ok(destination)
+    }
+    /// Send a message straight to a peer and persist it as sent by us.
+    pub async fn send_direct_message(
+        &self,
+        peer: NodeId,
+        nickname: String,
+        text: String,
+    ) -> anyhow::Result<MessageId> {
+        let node_guard = self.node.lock().await;
+        let Some(node) = node_guard.as_ref() else {
+            return Err(anyhow!("Node not initialized"));
+        };
+        let id = node
+            .send_direct(peer, nickname.clone(), text.clone())
+            .await?;
+        let message = StoredDirectMessage {
+            id: id.to_string(),
+            peer_id: peer.to_string(),
+            sender_id: node.node_id().to_string(),
+            nickname: Some(nickname),
+            content: Some(text),
+            created_at: get_timestamp() as i64,
+        };
+        // The peer already has the message, so a failed write must not fail the send.
+        if let Err(e) = self.get_db().await?.insert_direct_message(&message).await {
+            tracing::error!("Failed to persist sent direct message {}: {}", id, e);
+        }
+        Ok(id)
+    }
+    /// Spawns a background task to persist direct messages sent to us and emit them.
+    pub async fn start_direct_listener(
+        &self,
+        mut receiver: mpsc::Receiver<DirectEvent>,
+    ) -> anyhow::Result<()> {
+        let db = self.get_db().await?;
+        let sink = self.sink.clone();
+        let handle = AbortOnDropHandle::new(n0_future::task::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                if let Some(message) = StoredDirectMessage::from_event(&event) {
+                    if let Err(e) = db.insert_direct_message(&message).await {
+                        tracing::error!("Failed to persist direct message {}: {}", message.id, e);
+                    }
+                }
+                sink.send("direct-event", &event);
+            }
+        }));
+        *self.direct_listener.lock().await = Some(handle);
+        Ok(())
+    }
+    /// Say goodbye to a room and close our connection to it.  Returns deactivated topic ID.
+    pub async fn drop_channel(&self, topic: &TopicId) -> anyhow::Result<Option<String>> {
+        let channel = self.channels.lock().await.remove(topic);
+        match channel {
+            Some(channel) => Ok(Some(channel.leave().await)),
+            None => Ok(None),
+        }
+    }
+    /// Say goodbye to every room and close our connection to them.  Returns deactivated topic IDs.
+    pub async fn drop_channels(&self) -> anyhow::Result<Vec<String>> {
+        let channels: Vec<_> = self.channels.lock().await.drain().collect();
+        let mut ids = Vec::with_capacity(channels.len());
+        for (_, channel) in channels {
+            ids.push(channel.leave().await);
+        }
+        Ok(ids)
+    }
+    /// Stop listening to every room and shut the node down without telling the
+    /// rooms, to start it again with [`Self::resume`]. Returns a ticket for each room.
+    pub async fn suspend(&self) -> Vec<ChatTicket> {
+        let mut tickets = Vec::new();
+        for topic in self.get_topic_ids().await {
+            match self.generate_ticket(&topic, TicketOpts::all()).await {
+                Ok(ticket) => tickets.push(ticket),
+                Err(e) => tracing::error!("Failed to build ticket to rejoin {}: {}", topic, e),
+            }
+        }
+        // Dropping a channel stops listening to it, without a goodbye.
+        self.channels.lock().await.clear();
+        if let Some(node) = self.node.lock().await.take() {
+            node.shutdown().await;
+        }
+        tickets
+    }
+    /// Rejoin the rooms [`Self::suspend`] stopped listening to, once the node is
+    /// back up. Which room was joined last and the tickets saved to rejoin each
+    /// are left as they were.
+    pub async fn resume(&self, tickets: Vec<ChatTicket>, nickname: &str, store: &AppStore) {
+        for ticket in tickets {
+            let topic = ticket.topic_id;
+            if let Err(e) = self.enter_room(ticket, nickname, store).await {
+                tracing::error!("Failed to rejoin room {}: {}", topic, e);
+            }
+        }
+    }
+    /// Leave every room and shut the node down, before the app exits.
+    pub async fn shutdown(&self) {
+        if let Err(e) = self.drop_channels().await {
+            tracing::error!("Failed to leave rooms: {}", e);
+        }
+        if let Some(node) = self.node.lock().await.take() {
+            node.shutdown().await;
+        }
+    }
+    pub async fn start_channel(
+        &self,
+        domain_channel: Channel,
+        receiver: n0_future::stream::Boxed<anyhow::Result<Event>>,
+        nickname: &str,
+    ) -> anyhow::Result<String> {
+        let topic = domain_channel.topic_id();
+        let db = self.get_db().await?;
+        db.ensure_conversation(&topic, domain_channel.name())
+            .await?;
+        // Rejoining a room replaces our previous connection to it, without a goodbye.
+        if let Some(previous) = self.channels.lock().await.remove(&topic) {
+            previous.receiver_handle.abort();
+        }
+        // Bans and roles we saw before still hold.
+        for record in db.get_moderation(&topic).await? {
+            let (Ok(node_id), Some(action)) = (
+                record.node_id.parse(),
+                ModerationAction::from_record(&record.kind, record.active),
+            ) else {
+                continue;
+            };
+            domain_channel
+                .moderation()
+                .restore(node_id, action, record.updated_at as u64);
+        }
+        // As do uses and revocations of invites.
+        for record in db.get_invite_events(&topic).await? {
+            let (Ok(invite_id), Ok(node_id)) = (record.invite_id.parse(), record.node_id.parse())
+            else {
+                continue;
+            };
+            match record.kind.as_str() {
+                "redeemed" => domain_channel
+                    .moderation()
+                    .restore_redemption(invite_id, node_id),
+                "revoked" => {
+                    domain_channel
+                        .moderation()
+                        .revoke(node_id, invite_id, record.at as u64)
+                }
+                _ => {}
+            }
+        }
+        let peers = Arc::new(TokioMutex::new(PeerMap::new(
+            topic,
+            domain_channel.moderation().clone(),
+        )));
+        // Spawn the event listener task
+        let me = domain_channel.sender().node_id();
+        let receiver_handle = self.spawn_event_listener(topic, me, peers.clone(), db, receiver);
+        let active_channel = ActiveChannel {
+            inner: domain_channel,
+            peers,
+            receiver_handle,
+        };
+        active_channel
+            .inner
+            .sender()
+            .set_nickname(nickname.to_string());
+        let topic_id_str = active_channel.inner.id();
+        // Store the active channel info
+        self.channels.lock().await.insert(topic, active_channel);
+        tracing::info!("Active channel SET for topic: {}", topic_id_str);
+        Ok(topic_id_str)
+    }
+    /// Spawns a background task to listen for a room's chat events and emit them.
+    fn spawn_event_listener(
+        &self,
+        topic: TopicId,
+        me: NodeId,
+        peers: Arc<TokioMutex<PeerMap>>,
+        db: ChatDb,
+        mut receiver: n0_future::stream::Boxed<anyhow::Result<Event>>,
+    ) -> AbortOnDropHandle<()> {
+        let mut tick_interval = interval(Duration::from_secs(2));
+        let channels = self.channels.clone();
+        let latest_ticket = self.latest_ticket.clone();
+        let node = self.node.clone();
+        let sink = self.sink.clone();
+        let receipt_rooms = self.receipt_rooms.clone();
+
+        // messages received since the last tick, to acknowledge if receipts are enabled.
+        let mut delivered: Vec<(NodeId, MessageId)> = Vec::new();
+        // keep track of newly 'joined' peers to look out for their first
+        // presense message.
+        let mut new_starters: HashSet<NodeId> = HashSet::new();
+        // every neighbor we have had in this room, to bootstrap from if we have to rejoin.
+        let mut seen: HashSet<NodeId> = HashSet::new();
+
+        AbortOnDropHandle::new(n0_future::task::spawn(async move {
+            loop {
+                select! {
+                    biased; // Optional: prioritize receiver events if both are ready
+                    event_result = receiver.next() => { // `receiver` is moved into the task
+                        if let Some(Ok(event)) = &event_result {
+                            remember_neighbors(event, &mut seen);
+                            collect_deliveries(event, &mut delivered);
+                        }
+                        let removed = matches!(
+                            &event_result,
+                            Some(Ok(Event::Moderation { target, action, .. })) if *target == me && action.removes()
+                        );
+                        if handle_event(topic, event_result, &peers, &channels, &latest_ticket, &db, &*sink, &mut new_starters).await {
+                            // The stream ended: keep trying to rejoin until we do, or leave the room.
+                            match reconnect(topic, &node, &channels, &latest_ticket, &seen, &*sink).await {
+                                Some(new_receiver) => receiver = new_receiver,
+                                None => break,
+                            }
+                        };
+                        if removed {
+                            // Leave as asked, which also stops this task.
+                            tracing::info!("Removed from room {}", topic);
+                            // Let go of the lock before saying goodbye, which can take a while.
+                            let channel = channels.lock().await.remove(&topic);
+                            if let Some(channel) = channel {
+                                channel.leave().await;
+                            }
+                            break;
+                        }
+                    },
+                    _ = tick_interval.tick() => {
+                        // This branch runs every second
+                        peers.lock().await.update(None, &mut new_starters, &*sink);
+                        if !delivered.is_empty() {
+                            send_delivered(topic, std::mem::take(&mut delivered), &channels, &receipt_rooms).await;
+                        }
+                    },
+                }
+            }
+        }))
+    }
+}
+
+/// Keep track of every neighbor seen in a room.
+fn remember_neighbors(event: &Event, seen: &mut HashSet<NodeId>) {
+    match event {
+        Event::Joined { neighbors } => seen.extend(neighbors),
+        Event::NeighborUp { node_id } => {
+            seen.insert(*node_id);
+        }
+        _ => {}
+    }
+}
+
+/// Resubscribe to a room whose event stream ended, backing off exponentially
+/// between attempts. Bootstraps from the room's latest ticket plus every
+/// neighbor we have seen. Returns `None` if we are no longer in the room.
+async fn reconnect(
+    topic: TopicId,
+    node: &Arc<TokioMutex<Option<ChatNode>>>,
+    channels: &Channels,
+    latest_ticket: &Arc<TokioMutex<Option<String>>>,
+    seen: &HashSet<NodeId>,
+    sink: &dyn EventSink,
+) -> Option<ChatReceiver> {
+    let mut delay = RECONNECT_INITIAL_DELAY;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        tracing::info!(
+            "Reconnecting to room {} in {:?} (attempt {})",
+            topic,
+            delay,
+            attempt
+        );
+        emit_chat_event(
+            sink,
+            topic,
+            &Event::Reconnecting {
+                attempt,
+                delay_ms: delay.as_millis() as u64,
+            },
+        );
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+
+        let (mut ticket, nickname) = match channels.lock().await.get(&topic) {
+            Some(channel) => match channel.inner.ticket(TicketOpts::all()) {
+                Ok(ticket) => (ticket, channel.inner.sender().nickname()),
+                Err(e) => {
+                    tracing::error!("Failed to build ticket to rejoin {}: {}", topic, e);
+                    continue;
+                }
+            },
+            None => return None,
+        };
+        if let Some(latest) = latest_ticket
+            .lock()
+            .await
+            .as_deref()
+            .and_then(|ticket| ChatTicket::deserialize(ticket).ok())
+            .filter(|latest| latest.topic_id == topic)
+        {
+            ticket.bootstrap.extend(latest.bootstrap);
+        }
+        ticket.bootstrap.extend(seen.iter().copied());
+
+        let channel = match node.lock().await.as_ref() {
+            Some(node) => node.generate_channel(ticket, nickname),
+            None => Err(anyhow!("Node not initialized")),
+        };
+        let mut channel = match channel {
+            Ok(channel) => channel,
+            Err(e) => {
+                tracing::warn!("Failed to rejoin room {}: {}", topic, e);
+                continue;
+            }
+        };
+        let Some(receiver) = channel.take_receiver() else {
+            continue;
+        };
+        match channels.lock().await.get_mut(&topic) {
+            // Replacing the channel drops the old sender and its presence task.
+            Some(active) => active.inner = channel,
+            None => return None,
+        }
+        tracing::info!("Reconnected to room {}", topic);
+        emit_chat_event(sink, topic, &Event::Reconnected);
+        return Some(receiver);
+    }
+}
+
+fn emit_chat_event(sink: &dyn EventSink, topic: TopicId, event: &Event) {
+    sink.send("chat-event", RoomEvent::new(topic, event));
+}
+
+/// Handle the event stream, if we want to break the loop we return True.
+#[allow(clippy::too_many_arguments)]
+async fn handle_event(
+    topic: TopicId,
+    event_result: Option<anyhow::Result<Event>>,
+    peers_clone: &Arc<TokioMutex<PeerMap>>,
+    channels_clone: &Channels,
+    latest_ticket_clone: &Arc<TokioMutex<Option<String>>>,
+    db: &ChatDb,
+    sink: &dyn EventSink,
+    new_starters: &mut HashSet<NodeId>,
+) -> bool {
+    match event_result {
+        Some(Ok(event)) => {
+            // for any event, check if we've updated the peers list (and emit peer-events)
+            peers_clone
+                .lock()
+                .await
+                .update(Some(&event), new_starters, sink);
+            // persist chat messages before the frontend hears of them
+            let released = persist_event(topic, &event, db).await;
+            // emit a chat-event for each event, with reactions and receipts as
+            // summaries once they're recorded
+            let reacted = reaction_targets(&event);
+            if let Some(event) = without_aggregated(&event) {
+                emit_chat_event(sink, topic, &event);
+            }
+            // then the edits and deletions that were waiting on its messages
+            for event in &released {
+                emit_chat_event(sink, topic, event);
+            }
+            for target in reacted {
+                emit_reaction_summary(topic, target, db, sink).await;
+            }
+            if let Event::Receipt { message_ids, .. } = &event {
+                emit_receipts(topic, message_ids, db, sink).await;
+            }
+            // If a peer joins or a new neighbor comes up, update the latest_ticket
+            update_ticket(topic, &event, sink, latest_ticket_clone, channels_clone).await;
+        }
+        Some(Err(e)) => {
+            tracing::error!("Error receiving chat event: {}", e);
+            sink.send(
+                "chat-error",
+                RoomEvent::new(
+                    topic,
+                    Event::Errorred {
+                        message: e.to_string(),
+                    },
+                ),
+            );
+        }
+        None => {
+            tracing::info!("Chat event stream ended.");
+            emit_chat_event(sink, topic, &Event::Disconnected);
+            return true; // Stop listening when the stream ends
+        }
+    };
+    false // Continue listening
+}
+
+/// Write any chat messages carried by the event to the database, and apply edits
+/// and deletions to the messages they target. Returns the edits and deletions that
+/// were waiting on the messages, now applied.
+async fn persist_event(topic: TopicId, event: &Event, db: &ChatDb) -> Vec<Event> {
+    let mut released = Vec::new();
+    let events = match event {
+        Event::Backfill { messages, .. } => messages.iter().collect(),
+        event => vec![event],
+    };
+    for event in events {
+        let result = match event {
+            Event::MessageEdited {
+                id,
+                from,
+                target_id,
+                new_text,
+                sent_timestamp,
+                ..
+            } => {
+                let amendment = PendingAmendment {
+                    id: id.to_string(),
+                    target_id: target_id.to_string(),
+                    sender_id: from.to_string(),
+                    content: Some(new_text.clone()),
+                    created_at: *sent_timestamp as i64,
+                };
+                amend(topic, amendment, db).await
+            }
+            Event::MessageDeleted {
+                id,
+                from,
+                target_id,
+                sent_timestamp,
+                ..
+            } => {
+                let amendment = PendingAmendment {
+                    id: id.to_string(),
+                    target_id: target_id.to_string(),
+                    sender_id: from.to_string(),
+                    content: None,
+                    created_at: *sent_timestamp as i64,
+                };
+                amend(topic, amendment, db).await
+            }
+            Event::Reaction {
+                from,
+                target_id,
+                emoji,
+                add,
+                sent_timestamp,
+                ..
+            } => {
+                if emoji.is_empty() || emoji.len() > MAX_REACTION_LEN {
+                    tracing::warn!("Ignoring oversized reaction from {}", from.fmt_short());
+                    continue;
+                }
+                db.set_reaction(
+                    &topic,
+                    &target_id.to_string(),
+                    &from.to_string(),
+                    emoji,
+                    *add,
+                    *sent_timestamp as i64,
+                )
+                .await
+                .map(drop)
+            }
+            Event::Receipt {
+                from,
+                message_ids,
+                kind,
+                sent_timestamp,
+                ..
+            } => {
+                let ids: Vec<String> = message_ids
+                    .iter()
+                    .take(MAX_RECEIPT_BATCH)
+                    .map(|id| id.to_string())
+                    .collect();
+                db.add_receipts(
+                    &topic,
+                    &ids,
+                    &from.to_string(),
+                    *kind,
+                    *sent_timestamp as i64,
+                )
+                .await
+            }
+            Event::Moderation {
+                target,
+                action,
+                sent_timestamp,
+                ..
+            } => match action.as_record() {
+                Some((kind, active)) => db
+                    .set_moderation(
+                        &topic,
+                        &target.to_string(),
+                        kind,
+                        active,
+                        *sent_timestamp as i64,
+                    )
+                    .await
+                    .map(drop),
+                None => Ok(()),
+            },
+            Event::InviteRedeemed {
+                from,
+                invite_id,
+                sent_timestamp,
+                ..
+            } => {
+                // Only has an effect on invite-only rooms, where it made `from` a member.
+                let at = *sent_timestamp as i64;
+                let from = from.to_string();
+                match db
+                    .add_invite_event(&topic, &invite_id.to_string(), &from, "redeemed", at)
+                    .await
+                {
+                    Ok(()) => db
+                        .set_moderation(&topic, &from, "member", true, at)
+                        .await
+                        .map(drop),
+                    Err(e) => Err(e),
+                }
+            }
+            Event::InviteRevoked {
+                from,
+                invite_id,
+                sent_timestamp,
+                ..
+            } => {
+                db.add_invite_event(
+                    &topic,
+                    &invite_id.to_string(),
+                    &from.to_string(),
+                    "revoked",
+                    *sent_timestamp as i64,
+                )
+                .await
+            }
+            event => match StoredMessage::from_event(&topic, event) {
+                Some(message) => match db.insert_message(&message).await {
+                    Ok(()) => apply_pending_amendments(&message, db)
+                        .await
+                        .map(|events| released.extend(events)),
+                    Err(e) => Err(e),
+                },
+                None => continue,
+            },
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to persist chat event: {}", e);
+        }
+    }
+    released
+}
+
+/// Apply an edit or deletion to the message it targets, or keep it for when the
+/// message arrives if we don't have it yet.
+async fn amend(topic: TopicId, amendment: PendingAmendment, db: &ChatDb) -> anyhow::Result<()> {
+    let (target, sender, at) = (
+        &amendment.target_id,
+        &amendment.sender_id,
+        amendment.created_at,
+    );
+    let changed = match &amendment.content {
+        Some(text) => db.edit_message(target, sender, text, at).await?,
+        None => db.delete_message(target, sender, at).await?,
+    };
+    if !changed {
+        db.park_amendment(&topic, &amendment).await?;
+    }
+    Ok(())
+}
+
+/// Apply the edits and deletions the author of a newly stored message sent before
+/// it arrived, returning them as events for the frontend.
+async fn apply_pending_amendments(
+    message: &StoredMessage,
+    db: &ChatDb,
+) -> anyhow::Result<Vec<Event>> {
+    let Some(sender) = &message.sender_id else {
+        return Ok(Vec::new());
+    };
+    let mut events = Vec::new();
+    for amendment in db.take_pending_amendments(&message.id, sender).await? {
+        let at = amendment.created_at;
+        let changed = match &amendment.content {
+            Some(text) => db.edit_message(&message.id, sender, text, at).await?,
+            None => db.delete_message(&message.id, sender, at).await?,
+        };
+        if !changed {
+            continue;
+        }
+        let (id, from, target_id) = (amendment.id.parse()?, sender.parse()?, message.id.parse()?);
+        events.push(match amendment.content {
+            Some(new_text) => Event::MessageEdited {
+                id,
+                from,
+                target_id,
+                new_text,
+                sent_timestamp: at as u64,
+                clock_skewed: false,
+            },
+            None => Event::MessageDeleted {
+                id,
+                from,
+                target_id,
+                sent_timestamp: at as u64,
+                clock_skewed: false,
+            },
+        });
+    }
+    Ok(events)
+}
+
+/// Messages whose reactions changed with the event.
+fn reaction_targets(event: &Event) -> HashSet<MessageId> {
+    let events = match event {
+        Event::Backfill { messages, .. } => messages.iter().collect(),
+        event => vec![event],
+    };
+    events
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::Reaction { target_id, .. } => Some(*target_id),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The event as the frontend should see it, which is without raw reactions and receipts.
+fn without_aggregated(event: &Event) -> Option<Event> {
+    match event {
+        Event::Reaction { .. } | Event::Receipt { .. } => None,
+        Event::Backfill { from, messages } => Some(Event::Backfill {
+            from: *from,
+            messages: messages
+                .iter()
+                .filter(|event| !matches!(event, Event::Reaction { .. }))
+                .cloned()
+                .collect(),
+        }),
+        event => Some(event.clone()),
+    }
+}
+
+/// Emit who has acknowledged the messages, as persisted.
+async fn emit_receipts(
+    topic: TopicId,
+    message_ids: &[MessageId],
+    db: &ChatDb,
+    sink: &dyn EventSink,
+) {
+    let ids: Vec<String> = message_ids.iter().map(|id| id.to_string()).collect();
+    let summaries = match db.get_receipts(&topic, &ids).await {
+        Ok(summaries) => summaries,
+        Err(e) => {
+            tracing::error!("Failed to load receipts: {}", e);
+            return;
+        }
+    };
+    let parse = |nodes: Vec<String>| nodes.iter().filter_map(|node| node.parse().ok()).collect();
+    let receipts: Vec<_> = summaries
+        .into_iter()
+        .filter_map(|summary| {
+            Some(MessageReceipts {
+                message_id: summary.message_id.parse().ok()?,
+                delivered: parse(summary.delivered.0),
+                read: parse(summary.read.0),
+            })
+        })
+        .collect();
+    if !receipts.is_empty() {
+        emit_chat_event(sink, topic, &Event::ReceiptsUpdated { receipts });
+    }
+}
+
+/// Keep track of chat messages to acknowledge as delivered.
+fn collect_deliveries(event: &Event, delivered: &mut Vec<(NodeId, MessageId)>) {
+    let events = match event {
+        Event::Backfill { messages, .. } => messages.iter().collect(),
+        event => vec![event],
+    };
+    for event in events {
+        if let Event::MessageReceived { id, from, .. }
+        | Event::AttachmentReceived { id, from, .. } = event
+        {
+            delivered.push((*from, *id));
+        }
+    }
+}
+
+/// Acknowledge messages sent by others as delivered, if receipts are enabled for the room.
+async fn send_delivered(
+    topic: TopicId,
+    delivered: Vec<(NodeId, MessageId)>,
+    channels: &Channels,
+    receipt_rooms: &Arc<TokioMutex<HashSet<TopicId>>>,
+) {
+    if !receipt_rooms.lock().await.contains(&topic) {
+        return;
+    }
+    let Some(sender) = channels
+        .lock()
+        .await
+        .get(&topic)
+        .map(|channel| channel.inner.sender())
+    else {
+        return;
+    };
+    let ids: Vec<_> = delivered
+        .into_iter()
+        .filter(|(from, _)| *from != sender.node_id())
+        .map(|(_, id)| id)
+        .collect();
+    if ids.is_empty() {
+        return;
+    }
+    if let Err(e) = sender.send_receipts(&ids, ReceiptKind::Delivered).await {
+        tracing::warn!("Failed to send delivery receipts to {}: {}", topic, e);
+    }
+}
+
+/// Emit the aggregated reactions to a message, as persisted.
+async fn emit_reaction_summary(
+    topic: TopicId,
+    target: MessageId,
+    db: &ChatDb,
+    sink: &dyn EventSink,
+) {
+    let summaries = match db.get_reactions(&topic, &[target.to_string()]).await {
+        Ok(summaries) => summaries,
+        Err(e) => {
+            tracing::error!("Failed to count reactions to {}: {}", target, e);
+            return;
+        }
+    };
+    let reactions = summaries
+        .into_iter()
+        .map(|summary| ReactionCount {
+            emoji: summary.emoji,
+            senders: summary
+                .senders
+                .0
+                .iter()
+                .filter_map(|sender| sender.parse().ok())
+                .collect(),
+        })
+        .collect();
+    emit_chat_event(
+        sink,
+        topic,
+        &Event::ReactionSummary {
+            target_id: target,
+            reactions,
+        },
+    );
+}
+
+/// If a peer joins or a new neighbor comes up, update the latest_ticket
+/// with new peer nodes to assist reconnections.
+async fn update_ticket(
+    topic: TopicId,
+    event: &Event,
+    sink: &dyn EventSink,
+    latest_ticket_clone: &Arc<TokioMutex<Option<String>>>,
+    channels_clone: &Channels,
+) {
+    match &event {
+        Event::Joined { .. } | Event::NeighborUp { .. } => {
+            tracing::debug!("Peer event detected, attempting to update latest ticket.");
+            if let Some(channel) = channels_clone.lock().await.get(&topic) {
+                match channel.inner.ticket(TicketOpts::all()) {
+                    Ok(new_ticket) => {
+                        let mut latest_ticket = latest_ticket_clone.lock().await;
+                        // Only track the ticket of the room most recently opened.
+                        let is_latest = latest_ticket
+                            .as_deref()
+                            .and_then(|ticket| ChatTicket::deserialize(ticket).ok())
+                            .is_some_and(|ticket| ticket.topic_id == topic);
+                        if is_latest {
+                            *latest_ticket = Some(new_ticket.serialize());
+                            tracing::info!(
+                                "Updated latest_ticket due to new peer joining/neighbor up."
+                            );
+                        }
+                        sink.send("ticket-updated", RoomEvent::new(topic, new_ticket));
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to regenerate ticket after peer event: {}", e);
+                    }
+                }
+            }
+        }
+        _ => {} // Other events do not trigger ticket update
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::testing::node_id;
+
+    #[tokio::test]
+    async fn amendments_apply_once_their_message_arrives() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let topic = ChatTicket::new_named("room").topic_id;
+        let (author, other) = (node_id(), node_id());
+        let target_id = MessageId::from_signed(b"message");
+        let deletion = |id: &[u8], from| Event::MessageDeleted {
+            id: MessageId::from_signed(id),
+            from,
+            target_id,
+            sent_timestamp: 2,
+            clock_skewed: false,
+        };
+
+        assert!(persist_event(topic, &deletion(b"forged", other), &db)
+            .await
+            .is_empty());
+        assert!(persist_event(topic, &deletion(b"delete", author), &db)
+            .await
+            .is_empty());
+
+        let message = Event::MessageReceived {
+            id: target_id,
+            from: author,
+            text: "oops".to_string(),
+            nickname: "alice".to_string(),
+            sent_timestamp: 1,
+            clock_skewed: false,
+            reply_to: None,
+        };
+        let released = persist_event(topic, &message, &db).await;
+        assert!(matches!(
+            released.as_slice(),
+            [Event::MessageDeleted { from, .. }] if *from == author
+        ));
+        let stored = db.get_message(&target_id.to_string()).await.unwrap();
+        assert_eq!(stored.unwrap().deleted_at, Some(2));
+    }
+}
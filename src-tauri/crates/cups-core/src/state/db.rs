@@ -0,0 +1,1024 @@
+use std::path::Path;
+
+use anyhow::Result;
+use n0_future::boxed::BoxFuture;
+use serde::Serialize;
+use sqlx::{
+    error::BoxDynError,
+    migrate::{Migration as SqlxMigration, MigrationSource, MigrationType, Migrator},
+    sqlite::{SqliteConnectOptions, SqlitePool},
+    types::Json,
+    FromRow,
+};
+
+use super::generate_db_migrations;
+use crate::chat::{channel::TopicId, Attachment, DirectEvent, Event, NodeId, ReceiptKind};
+
+/// A row of the `messages` table, as returned to the frontend.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct StoredMessage {
+    pub id: String,
+    pub conversation_id: String,
+    pub content: Option<String>,
+    pub created_at: i64,
+    pub sender_id: Option<String>,
+    pub nickname: Option<String>,
+    pub attachment: Option<Json<Attachment>>,
+    pub edited_at: Option<i64>,
+    pub deleted_at: Option<i64>,
+    pub reply_to: Option<String>,
+}
+
+impl StoredMessage {
+    /// The persisted form of a received chat message, if the event is one.
+    pub fn from_event(topic: &TopicId, event: &Event) -> Option<Self> {
+        match event {
+            Event::MessageReceived {
+                id,
+                from,
+                text,
+                nickname,
+                sent_timestamp,
+                reply_to,
+                ..
+            } => Some(Self {
+                id: id.to_string(),
+                conversation_id: topic.to_string(),
+                content: Some(text.clone()),
+                created_at: *sent_timestamp as i64,
+                sender_id: Some(from.to_string()),
+                nickname: Some(nickname.clone()),
+                attachment: None,
+                edited_at: None,
+                deleted_at: None,
+                reply_to: reply_to.map(|id| id.to_string()),
+            }),
+            Event::AttachmentReceived {
+                id,
+                from,
+                nickname,
+                attachment,
+                sent_timestamp,
+                ..
+            } => Some(Self {
+                id: id.to_string(),
+                conversation_id: topic.to_string(),
+                content: None,
+                created_at: *sent_timestamp as i64,
+                sender_id: Some(from.to_string()),
+                nickname: Some(nickname.clone()),
+                attachment: Some(Json(attachment.clone())),
+                edited_at: None,
+                deleted_at: None,
+                reply_to: None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A row of the `pending_amendments` table, an edit or deletion of a message we
+/// don't have yet.
+#[derive(Debug, Clone, FromRow)]
+pub struct PendingAmendment {
+    pub id: String,
+    pub target_id: String,
+    pub sender_id: String,
+    /// The new text of an edit, `None` for a deletion.
+    pub content: Option<String>,
+    pub created_at: i64,
+}
+
+/// Everyone currently reacting to a message with one emoji, as returned to the frontend.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ReactionSummary {
+    pub message_id: String,
+    pub emoji: String,
+    pub senders: Json<Vec<String>>,
+}
+
+/// The peers that acknowledged a message, as returned to the frontend.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ReceiptSummary {
+    pub message_id: String,
+    pub delivered: Json<Vec<String>>,
+    pub read: Json<Vec<String>>,
+}
+
+/// A lasting moderation of a room member, in effect or lifted.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ModerationRecord {
+    pub node_id: String,
+    pub kind: String,
+    pub active: bool,
+    pub updated_at: i64,
+}
+
+/// An invite we issued to a room, with how many newcomers joined with it.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct IssuedInvite {
+    pub id: String,
+    pub conversation_id: String,
+    pub expires_at: Option<i64>,
+    pub max_uses: Option<i64>,
+    pub created_at: i64,
+    pub uses: i64,
+    pub revoked: bool,
+}
+
+/// A newcomer that joined a room with an invite, or a peer that revoked one.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct InviteRecord {
+    pub invite_id: String,
+    pub node_id: String,
+    pub kind: String,
+    pub at: i64,
+}
+
+/// A row of the `direct_messages` table, as returned to the frontend.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct StoredDirectMessage {
+    pub id: String,
+    pub peer_id: String,
+    pub sender_id: String,
+    pub nickname: Option<String>,
+    pub content: Option<String>,
+    pub created_at: i64,
+}
+
+impl StoredDirectMessage {
+    /// The persisted form of a received direct message, if the event is one.
+    pub fn from_event(event: &DirectEvent) -> Option<Self> {
+        match event {
+            DirectEvent::MessageReceived {
+                id,
+                from,
+                text,
+                nickname,
+                sent_timestamp,
+            } => Some(Self {
+                id: id.to_string(),
+                peer_id: from.to_string(),
+                sender_id: from.to_string(),
+                nickname: Some(nickname.clone()),
+                content: Some(text.clone()),
+                created_at: *sent_timestamp as i64,
+            }),
+            DirectEvent::UnsupportedMessage { .. } => None,
+        }
+    }
+}
+
+/// A peer we have exchanged direct messages with.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DirectConversation {
+    pub peer_id: String,
+    /// The nickname the peer last messaged us with, if they ever have.
+    pub nickname: Option<String>,
+    pub last_message_at: i64,
+}
+
+/// The backend's connection to `chat.db`, so messages are kept whether or not
+/// the webview is around to see them.
+#[derive(Debug, Clone)]
+pub struct ChatDb {
+    pool: SqlitePool,
+}
+
+impl ChatDb {
+    /// Open (creating if needed) the database file and bring its schema up to date.
+    pub async fn open(path: &Path) -> Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await?;
+        Migrator::new(ChatMigrations).await?.run(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    /// A throwaway database, gone once dropped.
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await?;
+        Migrator::new(ChatMigrations).await?.run(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    /// Make sure a room has a conversation row, naming it if it has none yet.
+    pub async fn ensure_conversation(&self, topic: &TopicId, name: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO conversations (id, name) VALUES ($1, $2)
+             ON CONFLICT(id) DO UPDATE SET name = COALESCE(conversations.name, excluded.name)",
+        )
+        .bind(topic.to_string())
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Store a message, ignoring it if we already have one with the same id.
+    pub async fn insert_message(&self, message: &StoredMessage) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        // The room may have been deleted from the UI while we are still in it.
+        sqlx::query("INSERT OR IGNORE INTO conversations (id) VALUES ($1)")
+            .bind(&message.conversation_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO messages (id, conversation_id, sender_id, nickname, content, created_at, attachment, reply_to)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(&message.id)
+        .bind(&message.conversation_id)
+        .bind(&message.sender_id)
+        .bind(&message.nickname)
+        .bind(&message.content)
+        .bind(message.created_at)
+        .bind(&message.attachment)
+        .bind(&message.reply_to)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "UPDATE conversations SET last_message_at = MAX(COALESCE(last_message_at, 0), $1)
+             WHERE id = $2",
+        )
+        .bind(message.created_at)
+        .bind(&message.conversation_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Replace the text of a message, if `sender` wrote it and this is the latest edit.
+    /// Returns whether the message was changed.
+    pub async fn edit_message(&self, id: &str, sender: &str, text: &str, at: i64) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE messages SET content = $1, edited_at = $2
+             WHERE id = $3 AND sender_id = $4 AND deleted_at IS NULL AND attachment IS NULL
+               AND COALESCE(edited_at, 0) < $2",
+        )
+        .bind(text)
+        .bind(at)
+        .bind(id)
+        .bind(sender)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Blank out a message `sender` wrote, keeping it as a tombstone.
+    /// Returns whether the message was changed.
+    pub async fn delete_message(&self, id: &str, sender: &str, at: i64) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE messages SET content = NULL, attachment = NULL, deleted_at = $1
+             WHERE id = $2 AND sender_id = $3 AND deleted_at IS NULL",
+        )
+        .bind(at)
+        .bind(id)
+        .bind(sender)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Keep an edit or deletion of a message we don't have yet, until it arrives.
+    /// Ignored if we do have the message.
+    pub async fn park_amendment(
+        &self,
+        topic: &TopicId,
+        amendment: &PendingAmendment,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT OR IGNORE INTO conversations (id) VALUES ($1)")
+            .bind(topic.to_string())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO pending_amendments (id, conversation_id, target_id, sender_id, content, created_at)
+             SELECT $1, $2, $3, $4, $5, $6
+             WHERE NOT EXISTS (SELECT 1 FROM messages WHERE id = $3)",
+        )
+        .bind(&amendment.id)
+        .bind(topic.to_string())
+        .bind(&amendment.target_id)
+        .bind(&amendment.sender_id)
+        .bind(&amendment.content)
+        .bind(amendment.created_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// The edits and deletions `sender` made to a message before it arrived, oldest
+    /// first. Everyone else's are dropped, as only the author may amend a message.
+    pub async fn take_pending_amendments(
+        &self,
+        target_id: &str,
+        sender: &str,
+    ) -> Result<Vec<PendingAmendment>> {
+        let mut tx = self.pool.begin().await?;
+        let amendments = sqlx::query_as::<_, PendingAmendment>(
+            "SELECT id, target_id, sender_id, content, created_at FROM pending_amendments
+             WHERE target_id = $1 AND sender_id = $2
+             ORDER BY created_at ASC",
+        )
+        .bind(target_id)
+        .bind(sender)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM pending_amendments WHERE target_id = $1")
+            .bind(target_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(amendments)
+    }
+
+    /// Record a sender adding or taking back a reaction, unless we already have a later
+    /// one of theirs with the same emoji, so duplicate and late deliveries change nothing.
+    /// Returns whether the reactions to the message changed.
+    pub async fn set_reaction(
+        &self,
+        topic: &TopicId,
+        message_id: &str,
+        sender: &str,
+        emoji: &str,
+        add: bool,
+        at: i64,
+    ) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT OR IGNORE INTO conversations (id) VALUES ($1)")
+            .bind(topic.to_string())
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query(
+            "INSERT INTO reactions (message_id, conversation_id, sender_id, emoji, added, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT(message_id, sender_id, emoji) DO UPDATE
+             SET added = excluded.added, updated_at = excluded.updated_at
+             WHERE excluded.updated_at > reactions.updated_at",
+        )
+        .bind(message_id)
+        .bind(topic.to_string())
+        .bind(sender)
+        .bind(emoji)
+        .bind(add)
+        .bind(at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The current reactions in a room to the given messages, each emoji in the order
+    /// it was first used.
+    pub async fn get_reactions(
+        &self,
+        topic: &TopicId,
+        message_ids: &[String],
+    ) -> Result<Vec<ReactionSummary>> {
+        Ok(sqlx::query_as(
+            "SELECT message_id, emoji, json_group_array(sender_id) AS senders
+             FROM reactions
+             WHERE added = 1 AND conversation_id = $1
+                 AND message_id IN (SELECT value FROM json_each($2))
+             GROUP BY message_id, emoji
+             ORDER BY MIN(updated_at)",
+        )
+        .bind(topic.to_string())
+        .bind(Json(message_ids))
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Record a peer acknowledging messages of a room. Receipts for messages we don't
+    /// have in that room are dropped, and reading a message also counts as it being
+    /// delivered.
+    pub async fn add_receipts(
+        &self,
+        topic: &TopicId,
+        message_ids: &[String],
+        node: &str,
+        kind: ReceiptKind,
+        at: i64,
+    ) -> Result<()> {
+        let mut kinds = vec![kind];
+        if kind == ReceiptKind::Read {
+            kinds.push(ReceiptKind::Delivered);
+        }
+        let mut tx = self.pool.begin().await?;
+        for kind in kinds {
+            sqlx::query(
+                "INSERT OR IGNORE INTO receipts (message_id, node_id, kind, received_at)
+                 SELECT id, $1, $2, $3 FROM messages
+                 WHERE conversation_id = $4 AND id IN (SELECT value FROM json_each($5))",
+            )
+            .bind(node)
+            .bind(kind.as_str())
+            .bind(at)
+            .bind(topic.to_string())
+            .bind(Json(message_ids))
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Who acknowledged the given messages of a room, leaving out messages nobody has.
+    pub async fn get_receipts(
+        &self,
+        topic: &TopicId,
+        message_ids: &[String],
+    ) -> Result<Vec<ReceiptSummary>> {
+        Ok(sqlx::query_as(
+            "SELECT message_id,
+                json_group_array(node_id) FILTER (WHERE kind = 'delivered') AS delivered,
+                json_group_array(node_id) FILTER (WHERE kind = 'read') AS read
+             FROM receipts JOIN messages ON messages.id = receipts.message_id
+             WHERE conversation_id = $1 AND message_id IN (SELECT value FROM json_each($2))
+             GROUP BY message_id",
+        )
+        .bind(topic.to_string())
+        .bind(Json(message_ids))
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Record a lasting moderation of a room member, unless we already have a later one
+    /// of the same kind. Returns whether it changed anything.
+    pub async fn set_moderation(
+        &self,
+        topic: &TopicId,
+        node_id: &str,
+        kind: &str,
+        active: bool,
+        at: i64,
+    ) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT OR IGNORE INTO conversations (id) VALUES ($1)")
+            .bind(topic.to_string())
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query(
+            "INSERT INTO moderation (conversation_id, node_id, kind, active, updated_at)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT(conversation_id, node_id, kind) DO UPDATE
+             SET active = excluded.active, updated_at = excluded.updated_at
+             WHERE excluded.updated_at > moderation.updated_at",
+        )
+        .bind(topic.to_string())
+        .bind(node_id)
+        .bind(kind)
+        .bind(active)
+        .bind(at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The latest moderation of each kind of each member of a room, with when it
+    /// changed.
+    pub async fn get_moderation(&self, topic: &TopicId) -> Result<Vec<ModerationRecord>> {
+        Ok(sqlx::query_as(
+            "SELECT node_id, kind, active, updated_at FROM moderation WHERE conversation_id = $1",
+        )
+        .bind(topic.to_string())
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Remember an invite we issued to a room.
+    pub async fn insert_invite(
+        &self,
+        topic: &TopicId,
+        id: &str,
+        expires_at: Option<i64>,
+        max_uses: Option<i64>,
+        created_at: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO invites (id, conversation_id, expires_at, max_uses, created_at)
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(id)
+        .bind(topic.to_string())
+        .bind(expires_at)
+        .bind(max_uses)
+        .bind(created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record that `node_id` joined a room with an invite or revoked it, where
+    /// `kind` is 'redeemed' or 'revoked'. Only the first of each is kept.
+    pub async fn add_invite_event(
+        &self,
+        topic: &TopicId,
+        invite_id: &str,
+        node_id: &str,
+        kind: &str,
+        at: i64,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT OR IGNORE INTO conversations (id) VALUES ($1)")
+            .bind(topic.to_string())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO invite_events (conversation_id, invite_id, node_id, kind, at)
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(topic.to_string())
+        .bind(invite_id)
+        .bind(node_id)
+        .bind(kind)
+        .bind(at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Every use and revocation of an invite seen in a room.
+    pub async fn get_invite_events(&self, topic: &TopicId) -> Result<Vec<InviteRecord>> {
+        Ok(sqlx::query_as(
+            "SELECT invite_id, node_id, kind, at FROM invite_events WHERE conversation_id = $1",
+        )
+        .bind(topic.to_string())
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// The invites we issued to a room, newest first.
+    pub async fn get_issued_invites(&self, topic: &TopicId) -> Result<Vec<IssuedInvite>> {
+        Ok(sqlx::query_as(
+            "SELECT invites.*,
+                (SELECT COUNT(*) FROM invite_events
+                 WHERE invite_id = invites.id AND kind = 'redeemed') AS uses,
+                EXISTS (SELECT 1 FROM invite_events
+                 WHERE invite_id = invites.id AND kind = 'revoked') AS revoked
+             FROM invites WHERE conversation_id = $1
+             ORDER BY created_at DESC",
+        )
+        .bind(topic.to_string())
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Look up a single message by its id.
+    pub async fn get_message(&self, id: &str) -> Result<Option<StoredMessage>> {
+        Ok(sqlx::query_as("SELECT * FROM messages WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    /// Up to `limit` of a room's messages that come before the `before` message,
+    /// given as its timestamp and id (or the newest if `None`), oldest first.
+    /// Messages sent at the same time are ordered by id, so none are skipped.
+    pub async fn get_messages(
+        &self,
+        topic: &TopicId,
+        before: Option<(i64, &str)>,
+        limit: u32,
+    ) -> Result<Vec<StoredMessage>> {
+        let (before, before_id) = before.unwrap_or((i64::MAX, ""));
+        let mut messages: Vec<StoredMessage> = sqlx::query_as(
+            "SELECT * FROM messages WHERE conversation_id = $1 AND (created_at, id) < ($2, $3)
+             ORDER BY created_at DESC, id DESC LIMIT $4",
+        )
+        .bind(topic.to_string())
+        .bind(before)
+        .bind(before_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        messages.reverse();
+        Ok(messages)
+    }
+
+    /// A message of a room and every reply below it, however deeply nested, oldest first.
+    /// Replies sent in other rooms are left out, whatever they claim to answer.
+    pub async fn get_thread(&self, topic: &TopicId, id: &str) -> Result<Vec<StoredMessage>> {
+        Ok(sqlx::query_as(
+            "WITH RECURSIVE thread(id) AS (
+                 SELECT $2
+                 UNION SELECT messages.id FROM messages JOIN thread ON messages.reply_to = thread.id
+                 WHERE messages.conversation_id = $1
+             )
+             SELECT * FROM messages WHERE conversation_id = $1 AND id IN thread
+             ORDER BY created_at, id",
+        )
+        .bind(topic.to_string())
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Store a direct message, ignoring it if we already have one with the same id.
+    pub async fn insert_direct_message(&self, message: &StoredDirectMessage) -> Result<()> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO direct_messages (id, peer_id, sender_id, nickname, content, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(&message.id)
+        .bind(&message.peer_id)
+        .bind(&message.sender_id)
+        .bind(&message.nickname)
+        .bind(&message.content)
+        .bind(message.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Up to `limit` direct messages exchanged with a peer that come before the
+    /// `before` message, given as its timestamp and id (or the newest if `None`),
+    /// oldest first.
+    pub async fn get_direct_messages(
+        &self,
+        peer: &NodeId,
+        before: Option<(i64, &str)>,
+        limit: u32,
+    ) -> Result<Vec<StoredDirectMessage>> {
+        let (before, before_id) = before.unwrap_or((i64::MAX, ""));
+        let mut messages: Vec<StoredDirectMessage> = sqlx::query_as(
+            "SELECT * FROM direct_messages WHERE peer_id = $1 AND (created_at, id) < ($2, $3)
+             ORDER BY created_at DESC, id DESC LIMIT $4",
+        )
+        .bind(peer.to_string())
+        .bind(before)
+        .bind(before_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        messages.reverse();
+        Ok(messages)
+    }
+
+    /// Every peer we have direct messages with, most recently active first.
+    pub async fn list_direct_conversations(&self) -> Result<Vec<DirectConversation>> {
+        Ok(sqlx::query_as(
+            "SELECT peer_id, MAX(created_at) AS last_message_at,
+                (SELECT nickname FROM direct_messages AS theirs
+                 WHERE theirs.peer_id = dm.peer_id AND theirs.sender_id = dm.peer_id
+                 ORDER BY created_at DESC LIMIT 1) AS nickname
+             FROM direct_messages AS dm
+             GROUP BY peer_id
+             ORDER BY last_message_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
+
+/// Feeds [`generate_db_migrations`] to sqlx. A frontend that also opens the
+/// database (like the Tauri sql plugin) must be given the same migrations, so
+/// the checksums match and whichever side opens it first applies them.
+#[derive(Debug)]
+struct ChatMigrations;
+
+impl MigrationSource<'static> for ChatMigrations {
+    fn resolve(self) -> BoxFuture<std::result::Result<Vec<SqlxMigration>, BoxDynError>> {
+        Box::pin(async move {
+            Ok(generate_db_migrations()
+                .into_iter()
+                .map(|migration| {
+                    SqlxMigration::new(
+                        migration.version,
+                        migration.description.into(),
+                        MigrationType::ReversibleUp,
+                        migration.sql.into(),
+                        false,
+                    )
+                })
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{testing::node_id, ChatTicket};
+
+    fn message(topic: &TopicId, id: &str, sender: &str, created_at: i64) -> StoredMessage {
+        StoredMessage {
+            id: id.to_string(),
+            conversation_id: topic.to_string(),
+            content: Some(format!("message {id}")),
+            created_at,
+            sender_id: Some(sender.to_string()),
+            nickname: Some("alice".to_string()),
+            attachment: None,
+            edited_at: None,
+            deleted_at: None,
+            reply_to: None,
+        }
+    }
+
+    fn amendment(id: &str, target: &str, sender: &str, content: Option<&str>) -> PendingAmendment {
+        PendingAmendment {
+            id: id.to_string(),
+            target_id: target.to_string(),
+            sender_id: sender.to_string(),
+            content: content.map(str::to_string),
+            created_at: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn messages_are_stored_once_per_room() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let (room, other) = (
+            ChatTicket::new_named("room").topic_id,
+            ChatTicket::new_named("other").topic_id,
+        );
+        let author = node_id().to_string();
+        db.insert_message(&message(&room, "a", &author, 1))
+            .await
+            .unwrap();
+        db.insert_message(&message(&room, "a", &author, 1))
+            .await
+            .unwrap();
+        db.insert_message(&message(&other, "b", &author, 2))
+            .await
+            .unwrap();
+
+        let stored = db.get_messages(&room, None, 10).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].id, "a");
+        assert_eq!(stored[0].content.as_deref(), Some("message a"));
+    }
+
+    #[tokio::test]
+    async fn pages_of_messages_skip_none_sent_at_the_same_time() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let topic = ChatTicket::new_named("room").topic_id;
+        let author = node_id().to_string();
+        for (id, at) in [("a", 1), ("b", 2), ("c", 2), ("d", 2), ("e", 3)] {
+            db.insert_message(&message(&topic, id, &author, at))
+                .await
+                .unwrap();
+        }
+
+        let mut pages = Vec::new();
+        let mut before: Option<(i64, String)> = None;
+        loop {
+            let cursor = before.as_ref().map(|(at, id)| (*at, id.as_str()));
+            let page = db.get_messages(&topic, cursor, 2).await.unwrap();
+            let Some(oldest) = page.first() else {
+                break;
+            };
+            before = Some((oldest.created_at, oldest.id.clone()));
+            pages.push(page.iter().map(|m| m.id.clone()).collect::<Vec<_>>());
+        }
+        assert_eq!(pages, [vec!["d", "e"], vec!["b", "c"], vec!["a"]]);
+    }
+
+    #[tokio::test]
+    async fn pages_of_direct_messages_skip_none_sent_at_the_same_time() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let peer = node_id();
+        for (id, at) in [("a", 1), ("b", 1), ("c", 2)] {
+            let message = StoredDirectMessage {
+                id: id.to_string(),
+                peer_id: peer.to_string(),
+                sender_id: peer.to_string(),
+                nickname: None,
+                content: Some(format!("message {id}")),
+                created_at: at,
+            };
+            db.insert_direct_message(&message).await.unwrap();
+        }
+
+        let newest = db.get_direct_messages(&peer, None, 2).await.unwrap();
+        let ids: Vec<_> = newest.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["b", "c"]);
+        let older = db
+            .get_direct_messages(&peer, Some((1, "b")), 2)
+            .await
+            .unwrap();
+        let ids: Vec<_> = older.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["a"]);
+    }
+
+    #[tokio::test]
+    async fn threads_hold_nested_replies_of_their_room() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let (room, other) = (
+            ChatTicket::new_named("room").topic_id,
+            ChatTicket::new_named("other").topic_id,
+        );
+        let author = node_id().to_string();
+        let reply = |topic, id, parent: &str, at| StoredMessage {
+            reply_to: Some(parent.to_string()),
+            ..message(topic, id, &author, at)
+        };
+        for message in [
+            message(&room, "a", &author, 1),
+            reply(&room, "b", "a", 2),
+            reply(&room, "c", "b", 3),
+            message(&room, "d", &author, 4),
+            reply(&other, "e", "a", 5),
+        ] {
+            db.insert_message(&message).await.unwrap();
+        }
+
+        let thread = db.get_thread(&room, "a").await.unwrap();
+        let ids: Vec<_> = thread.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b", "c"]);
+        // Nor can the thread be read from another room.
+        let thread = db.get_thread(&other, "a").await.unwrap();
+        let ids: Vec<_> = thread.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["e"]);
+    }
+
+    #[tokio::test]
+    async fn reactions_keep_each_senders_latest_change() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let topic = ChatTicket::new_named("room").topic_id;
+        let (alice, bob) = (node_id().to_string(), node_id().to_string());
+        let ids = ["a".to_string()];
+
+        assert!(db
+            .set_reaction(&topic, "a", &alice, "👍", true, 1)
+            .await
+            .unwrap());
+        assert!(db
+            .set_reaction(&topic, "a", &bob, "👍", true, 2)
+            .await
+            .unwrap());
+        assert!(db
+            .set_reaction(&topic, "a", &bob, "🎉", true, 3)
+            .await
+            .unwrap());
+        // Taken back, then a stale add arrives late and changes nothing.
+        assert!(db
+            .set_reaction(&topic, "a", &alice, "👍", false, 5)
+            .await
+            .unwrap());
+        assert!(!db
+            .set_reaction(&topic, "a", &alice, "👍", true, 4)
+            .await
+            .unwrap());
+
+        let summaries = db.get_reactions(&topic, &ids).await.unwrap();
+        let reactions: Vec<_> = summaries
+            .iter()
+            .map(|summary| (summary.emoji.as_str(), summary.senders.0.clone()))
+            .collect();
+        assert_eq!(
+            reactions,
+            [("👍", vec![bob.clone()]), ("🎉", vec![bob.clone()])]
+        );
+    }
+
+    #[tokio::test]
+    async fn reactions_are_counted_per_room() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let (room, other) = (
+            ChatTicket::new_named("room").topic_id,
+            ChatTicket::new_named("other").topic_id,
+        );
+        let sender = node_id().to_string();
+        db.set_reaction(&other, "a", &sender, "👍", true, 1)
+            .await
+            .unwrap();
+
+        let ids = ["a".to_string()];
+        assert!(db.get_reactions(&room, &ids).await.unwrap().is_empty());
+        assert_eq!(db.get_reactions(&other, &ids).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn receipts_are_kept_for_messages_of_their_room() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let (room, other) = (
+            ChatTicket::new_named("room").topic_id,
+            ChatTicket::new_named("other").topic_id,
+        );
+        let (author, reader) = (node_id().to_string(), node_id().to_string());
+        db.insert_message(&message(&room, "a", &author, 1))
+            .await
+            .unwrap();
+        db.insert_message(&message(&other, "b", &author, 1))
+            .await
+            .unwrap();
+
+        // Unknown messages and those of other rooms are dropped.
+        let ids = ["a", "b", "unknown"].map(str::to_string);
+        db.add_receipts(&room, &ids, &reader, ReceiptKind::Read, 2)
+            .await
+            .unwrap();
+        // Acknowledging twice keeps one of each.
+        db.add_receipts(&room, &ids, &reader, ReceiptKind::Delivered, 3)
+            .await
+            .unwrap();
+
+        let receipts = db.get_receipts(&room, &ids).await.unwrap();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].message_id, "a");
+        // Reading a message implies it was delivered.
+        assert_eq!(receipts[0].delivered.0, vec![reader.clone()]);
+        assert_eq!(receipts[0].read.0, vec![reader.clone()]);
+        assert!(db.get_receipts(&other, &ids).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_authors_amend_their_messages() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let topic = ChatTicket::new_named("room").topic_id;
+        let (author, other) = (node_id().to_string(), node_id().to_string());
+        db.insert_message(&message(&topic, "a", &author, 1))
+            .await
+            .unwrap();
+
+        assert!(!db.edit_message("a", &other, "forged", 2).await.unwrap());
+        assert!(!db.delete_message("a", &other, 2).await.unwrap());
+        assert!(db.edit_message("a", &author, "fixed", 2).await.unwrap());
+        // Edits arriving out of order don't roll the text back.
+        assert!(!db.edit_message("a", &author, "stale", 1).await.unwrap());
+
+        let stored = db.get_message("a").await.unwrap().unwrap();
+        assert_eq!(stored.content.as_deref(), Some("fixed"));
+        assert_eq!(stored.edited_at, Some(2));
+    }
+
+    #[tokio::test]
+    async fn deleted_messages_leave_a_tombstone() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let topic = ChatTicket::new_named("room").topic_id;
+        let author = node_id().to_string();
+        db.insert_message(&message(&topic, "a", &author, 1))
+            .await
+            .unwrap();
+
+        assert!(db.delete_message("a", &author, 3).await.unwrap());
+        assert!(!db.delete_message("a", &author, 4).await.unwrap());
+        assert!(!db.edit_message("a", &author, "back", 5).await.unwrap());
+        // Receiving the message again doesn't bring it back.
+        db.insert_message(&message(&topic, "a", &author, 1))
+            .await
+            .unwrap();
+
+        let stored = db.get_message("a").await.unwrap().unwrap();
+        assert_eq!(stored.content, None);
+        assert_eq!(stored.deleted_at, Some(3));
+    }
+
+    #[tokio::test]
+    async fn amendments_wait_for_their_message() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let topic = ChatTicket::new_named("room").topic_id;
+        let (author, other) = (node_id().to_string(), node_id().to_string());
+
+        let edit = amendment("edit", "a", &author, Some("fixed"));
+        db.park_amendment(&topic, &edit).await.unwrap();
+        db.park_amendment(&topic, &amendment("forged", "a", &other, None))
+            .await
+            .unwrap();
+        // Parking twice keeps one.
+        db.park_amendment(&topic, &edit).await.unwrap();
+
+        let taken = db.take_pending_amendments("a", &author).await.unwrap();
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].id, "edit");
+        // Taken once, along with everyone else's.
+        assert!(db
+            .take_pending_amendments("a", &other)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn amendments_of_messages_we_have_are_not_parked() {
+        let db = ChatDb::in_memory().await.unwrap();
+        let topic = ChatTicket::new_named("room").topic_id;
+        let (author, other) = (node_id().to_string(), node_id().to_string());
+        db.insert_message(&message(&topic, "a", &author, 1))
+            .await
+            .unwrap();
+
+        db.park_amendment(&topic, &amendment("forged", "a", &other, None))
+            .await
+            .unwrap();
+        assert!(db
+            .take_pending_amendments("a", &other)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use iroh::{Endpoint, NodeId};
+use n0_future::task::{self, AbortOnDropHandle};
+use serde::Serialize;
+use tokio::{
+    select,
+    sync::{watch, Mutex as TokioMutex},
+    time::{interval, Duration},
+};
+
+use crate::{
+    chat::{channel::TopicId, Attachment, BlobHash, BlobStore},
+    EventSink,
+};
+
+/// How often progress of a running download is emitted.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Status of an attachment download, emitted to the frontend as `attachment-event`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum DownloadEvent {
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        hash: BlobHash,
+        received: u64,
+        size: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    Completed { hash: BlobHash },
+    #[serde(rename_all = "camelCase")]
+    Failed { hash: BlobHash, error: String },
+    #[serde(rename_all = "camelCase")]
+    Cancelled { hash: BlobHash },
+}
+
+/// How much of an attachment we have.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadProgress {
+    pub hash: BlobHash,
+    pub received: u64,
+    pub size: u64,
+    /// The attachment is fully downloaded and can be saved.
+    pub complete: bool,
+}
+
+struct Download {
+    progress: watch::Receiver<u64>,
+    size: u64,
+    _task: AbortOnDropHandle<()>,
+}
+
+/// Attachment downloads running in the background, cancelled when dropped.
+#[derive(Clone, Default)]
+pub struct Downloads(Arc<TokioMutex<HashMap<BlobHash, Download>>>);
+
+impl Downloads {
+    /// Start fetching an attachment shared in `topic` from the given providers,
+    /// in order. Does nothing if it is already being fetched.
+    pub async fn start(
+        &self,
+        store: BlobStore,
+        endpoint: Endpoint,
+        topic: TopicId,
+        attachment: Attachment,
+        providers: Vec<NodeId>,
+        sink: Arc<dyn EventSink>,
+    ) {
+        let mut downloads = self.0.lock().await;
+        let hash = attachment.hash;
+        let size = attachment.size;
+        if downloads.contains_key(&hash) {
+            return;
+        }
+        let (progress_tx, progress_rx) = watch::channel(0);
+        let this = self.clone();
+        let task = task::spawn(async move {
+            let download = store.download(&endpoint, topic, &attachment, &providers, &progress_tx);
+            tokio::pin!(download);
+            let mut tick = interval(PROGRESS_INTERVAL);
+            let result = loop {
+                select! {
+                    result = &mut download => break result,
+                    _ = tick.tick() => {
+                        let received = *progress_tx.borrow();
+                        emit(&*sink, DownloadEvent::Progress { hash, received, size });
+                    }
+                }
+            };
+            match result {
+                Ok(()) => emit(&*sink, DownloadEvent::Completed { hash }),
+                Err(e) => {
+                    tracing::warn!("Failed to download attachment {}: {}", hash, e);
+                    emit(
+                        &*sink,
+                        DownloadEvent::Failed {
+                            hash,
+                            error: e.to_string(),
+                        },
+                    )
+                }
+            }
+            // Last, as dropping our own entry aborts this task.
+            this.0.lock().await.remove(&hash);
+        });
+        downloads.insert(
+            hash,
+            Download {
+                progress: progress_rx,
+                size,
+                _task: AbortOnDropHandle::new(task),
+            },
+        );
+    }
+
+    /// Stop a running download. Returns false if there was none.
+    pub async fn cancel(&self, hash: &BlobHash, sink: &dyn EventSink) -> bool {
+        let cancelled = self.0.lock().await.remove(hash).is_some();
+        if cancelled {
+            emit(sink, DownloadEvent::Cancelled { hash: *hash });
+        }
+        cancelled
+    }
+
+    /// Progress of a running download, if there is one.
+    pub async fn progress(&self, hash: &BlobHash) -> Option<DownloadProgress> {
+        self.0
+            .lock()
+            .await
+            .get(hash)
+            .map(|download| DownloadProgress {
+                hash: *hash,
+                received: *download.progress.borrow(),
+                size: download.size,
+                complete: false,
+            })
+    }
+}
+
+fn emit(sink: &dyn EventSink, event: DownloadEvent) {
+    sink.send("attachment-event", event);
+}
+
+/// A path in `dir` to save a file called `name` to, without overwriting anything.
+pub fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    // Only ever use the final component, the name comes from a peer.
+    let name = Path::new(name)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "attachment".to_string());
+    let path = dir.join(&name);
+    if !path.exists() {
+        return path;
+    }
+    let stem = Path::new(&name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = Path::new(&name)
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| dir.join(format!("{stem} ({n}){extension}")))
+        .find(|path| !path.exists())
+        .expect("ran out of file names")
+}
@@ -0,0 +1,154 @@
+use iroh::SecretKey;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    api::ApiConfig,
+    chat::{AcceptanceWindow, ChatTicket, NetworkConfig},
+    utils::get_timestamp,
+};
+
+/// Persistent storage for the application's settings, as JSON values by key.
+pub trait KeyValueStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<serde_json::Value>;
+    fn set(&self, key: &str, value: serde_json::Value);
+}
+
+/// A [`KeyValueStore`] that only lives as long as the process.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore(Arc<Mutex<HashMap<String, serde_json::Value>>>);
+
+impl KeyValueStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<serde_json::Value> {
+        self.0.lock().expect("poisened").get(key).cloned()
+    }
+    fn set(&self, key: &str, value: serde_json::Value) {
+        self.0
+            .lock()
+            .expect("poisened")
+            .insert(key.to_string(), value);
+    }
+}
+
+/// The application's settings: nickname, node key and visited rooms.
+#[derive(Clone)]
+pub struct AppStore(Arc<dyn KeyValueStore>);
+
+impl AppStore {
+    pub fn new(store: Arc<dyn KeyValueStore>) -> Self {
+        Self(store)
+    }
+    pub fn get_nickname(&self) -> Option<String> {
+        self.0
+            .get("nickname")
+            .and_then(|val| serde_json::from_value(val).ok())
+    }
+    pub fn set_nickname(&self, nickname: &str) -> anyhow::Result<()> {
+        self.0.set("nickname", serde_json::to_value(nickname)?);
+        Ok(())
+    }
+    fn get_visited_rooms_inner(&self) -> HashMap<String, (u64, ChatTicket)> {
+        self.0
+            .get("visited")
+            .map(|val| serde_json::from_value(val).unwrap_or_default())
+            .unwrap_or_default()
+    }
+    /// Return the list of visited rooms in order of most recently visited
+    pub fn get_visited_rooms(&self) -> Vec<ChatTicket> {
+        let rooms = self.get_visited_rooms_inner();
+        let mut list: Vec<_> = rooms.values().cloned().collect();
+        list.sort_by_key(|room| room.0);
+        list.into_iter().map(|room| room.1).collect()
+    }
+    /// Add or update a room in the list of visited rooms
+    pub fn update_visited_room(&self, ticket: ChatTicket) -> anyhow::Result<()> {
+        let visited = get_timestamp();
+        let mut rooms = self.get_visited_rooms_inner();
+        rooms
+            .entry(ticket.topic_id.to_string())
+            .and_modify(|(v, t)| {
+                *v = visited;
+                *t = ticket.clone();
+            })
+            .or_insert((visited, ticket));
+        self.0.set("visited", serde_json::to_value(rooms)?);
+        Ok(())
+    }
+    /// Delete a room from the list of visited rooms
+    pub fn delete_visited_room(&self, topic_id: &str) -> anyhow::Result<()> {
+        let mut rooms = self.get_visited_rooms_inner();
+        if rooms.remove_entry(topic_id).is_none() {
+            tracing::info!("{} not found when deleting rooms", topic_id);
+        };
+        self.0.set("visited", serde_json::to_value(rooms)?);
+        Ok(())
+    }
+    /// How far message timestamps may drift from local time before being rejected.
+    pub fn get_acceptance_window(&self) -> AcceptanceWindow {
+        self.0
+            .get("acceptance_window")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default()
+    }
+    pub fn set_acceptance_window(&self, window: AcceptanceWindow) -> anyhow::Result<()> {
+        self.0
+            .set("acceptance_window", serde_json::to_value(window)?);
+        Ok(())
+    }
+    /// How the node finds and reaches peers, applied when it's spawned.
+    pub fn get_network_config(&self) -> NetworkConfig {
+        self.0
+            .get("network")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default()
+    }
+    pub fn set_network_config(&self, config: &NetworkConfig) -> anyhow::Result<()> {
+        self.0.set("network", serde_json::to_value(config)?);
+        Ok(())
+    }
+    /// How to serve the local API, if it is enabled.
+    pub fn get_api_config(&self) -> Option<ApiConfig> {
+        self.0
+            .get("api")
+            .and_then(|val| serde_json::from_value(val).ok())
+    }
+    pub fn set_api_config(&self, config: Option<&ApiConfig>) -> anyhow::Result<()> {
+        self.0.set("api", serde_json::to_value(config)?);
+        Ok(())
+    }
+    /// Rooms we send delivery and read receipts in.
+    pub fn get_receipt_rooms(&self) -> Vec<String> {
+        self.0
+            .get("receipts")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default()
+    }
+    pub fn set_receipts(&self, topic_id: &str, enabled: bool) -> anyhow::Result<()> {
+        let mut rooms = self.get_receipt_rooms();
+        rooms.retain(|room| room != topic_id);
+        if enabled {
+            rooms.push(topic_id.to_string());
+        }
+        self.0.set("receipts", serde_json::to_value(rooms)?);
+        Ok(())
+    }
+    pub fn get_secret_key(&self) -> anyhow::Result<SecretKey> {
+        match self.0.get("key") {
+            Some(val) => match serde_json::from_value::<SecretKey>(val) {
+                Ok(key) => Ok(key),
+                Err(_) => {
+                    let key = SecretKey::generate(rand::rngs::OsRng);
+                    self.0.set("key", serde_json::to_value(&key)?);
+                    Ok(key)
+                }
+            },
+            None => {
+                let key = SecretKey::generate(rand::rngs::OsRng);
+                self.0.set("key", serde_json::to_value(&key)?);
+                Ok(key)
+            }
+        }
+    }
+}
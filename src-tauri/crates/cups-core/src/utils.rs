@@ -0,0 +1,57 @@
+use std::time::SystemTime;
+
+/// Generate a Unix timestamp in Micros.
+pub fn get_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as u64
+}
+
+/// Implement `Display`, `FromStr` and serde for a 32 byte hash newtype.
+///
+/// Hex string for the frontend, raw bytes on the wire.
+macro_rules! hex_id {
+    ($name:ident) => {
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                write!(f, "{}", ::blake3::Hash::from_bytes(self.0).to_hex())
+            }
+        }
+
+        impl ::std::str::FromStr for $name {
+            type Err = ::anyhow::Error;
+            fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+                let hash = ::blake3::Hash::from_hex(s)?;
+                Ok(Self(*hash.as_bytes()))
+            }
+        }
+
+        impl ::serde::Serialize for $name {
+            fn serialize<S: ::serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> ::std::result::Result<S::Ok, S::Error> {
+                if serializer.is_human_readable() {
+                    serializer.collect_str(self)
+                } else {
+                    ::serde::Serialize::serialize(&self.0, serializer)
+                }
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D: ::serde::Deserializer<'de>>(
+                deserializer: D,
+            ) -> ::std::result::Result<Self, D::Error> {
+                if deserializer.is_human_readable() {
+                    let s = <String as ::serde::Deserialize>::deserialize(deserializer)?;
+                    s.parse().map_err(::serde::de::Error::custom)
+                } else {
+                    <[u8; 32] as ::serde::Deserialize>::deserialize(deserializer).map(Self)
+                }
+            }
+        }
+    };
+}
+pub(crate) use hex_id;
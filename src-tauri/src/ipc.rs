@@ -1,57 +1,53 @@
-use crate::{
-    chat::{channel::TicketOpts, ChatTicket, NodeId, VisitedRoom},
-    state::{AppContext, AppStore},
+use crate::state::{
+    acquire_store, AppContext, DirectConversation, DownloadProgress, IssuedInvite, ReactionSummary,
+    ReceiptSummary, StoredDirectMessage, StoredMessage,
 };
 use anyhow::anyhow;
+use cups_core::api::{ApiConfig, LocalApi, DEFAULT_API_PORT};
+use cups_core::chat::{
+    channel::{TicketOpts, TopicId},
+    AcceptanceWindow, BlobHash, MessageId, ModerationAction, NetworkConfig, NodeId, RoomModeration,
+    VisitedRoom,
+};
+use std::{path::PathBuf, time::Duration};
+use tauri::Manager as _;
+
+/// Parse a room topic ID passed from the frontend.
+fn parse_topic(topic: &str) -> anyhow::Result<TopicId> {
+    topic
+        .parse()
+        .map_err(|e| anyhow!("Invalid topic ID {}: {}", topic, e))
+}
+
+/// Parse a peer's node ID passed from the frontend.
+fn parse_node_id(node_id: &str) -> anyhow::Result<NodeId> {
+    node_id
+        .parse()
+        .map_err(|e| anyhow!("Invalid node ID {}: {}", node_id, e))
+}
 
 #[tauri::command]
 /// Create a new room and return the information required to send
 /// an out-of-band Join Code to others to connect.
 pub async fn create_room(
-    name: String,     // room name
-    nickname: String, // user name
+    name: String,              // room name
+    nickname: String,          // user name
+    encrypted: Option<bool>,   // end-to-end encrypt the room, defaults to true
+    invite_only: Option<bool>, // only let in members added by admins, defaults to false
     state: tauri::State<'_, AppContext>,
     app: tauri::AppHandle,
 ) -> tauri::Result<String> {
-    let node_guard = state.node.lock().await;
-    let Some(node) = node_guard.as_ref() else {
-        return Err(anyhow!("Node not initialized").into());
-    };
-
-    // Leave any existing room first
-    leave_room(state.clone(), app.clone()).await?;
-
-    let store = AppStore::acquire(&app)?;
-    // Create a new ticket to initialize the channel.
-    // generate_channel will ensure this node is part of the bootstrap.
-    let initial_ticket = ChatTicket::new_named(&name);
-
-    // Use generate_channel from [chat::channel]
-    let mut channel = node
-        .generate_channel(initial_ticket, nickname.clone())
-        .map_err(|e| anyhow!("Failed to generate channel: {}", e))?;
-
-    // Take the receiver from the Channel object to give to spawn_event_listener
-    let rx = channel
-        .take_receiver()
-        .ok_or_else(|| anyhow!("Receiver already taken from channel object"))?;
-
-    store.set_nickname(&nickname)?;
-
-    // Store the active channel info
-    state.start_channel(channel, &app, rx, &nickname).await?;
-
-    // Get the topic_id from the established channel for logging
-    let topic_id_str = state.get_topic_id().await?;
-
-    tracing::info!("Created and joined room: {}", topic_id_str);
-
-    // Generate ticket string from the Channel instance to be shared
-    let ticket = state.generate_ticket(TicketOpts::all()).await?;
-    store.update_visited_room(ticket.clone())?;
-    let ticket_token = ticket.serialize();
-    *state.latest_ticket.lock().await = Some(ticket_token.clone());
-    Ok(ticket_token)
+    let store = acquire_store(&app)?;
+    let ticket = state
+        .create_room(
+            &name,
+            &nickname,
+            encrypted.unwrap_or(true),
+            invite_only.unwrap_or(false),
+            &store,
+        )
+        .await?;
+    Ok(ticket)
 }
 
 #[tauri::command]
@@ -62,104 +58,465 @@ pub async fn join_room(
     state: tauri::State<'_, AppContext>,
     app: tauri::AppHandle,
 ) -> tauri::Result<()> {
-    let node_guard = state.node.lock().await;
-    let Some(node) = node_guard.as_ref() else {
-        return Err(anyhow!("Node not initialized").into());
-    };
-
-    // Leave any existing room first
-    leave_room(state.clone(), app.clone()).await?;
-
-    tracing::info!("deserializing ticket token: {}", ticket);
-    let chat_ticket = ChatTicket::deserialize(&ticket)?;
-    *state.latest_ticket.lock().await = Some(ticket.clone());
-
-    // Use generate_channel from chat::channel
-    let mut channel = node
-        .generate_channel(chat_ticket.clone(), nickname.clone())
-        .map_err(|e| anyhow!("Failed to generate channel: {}", e))?;
-
-    // Take the receiver from the Channel object
-    let rx = channel
-        .take_receiver()
-        .ok_or_else(|| anyhow!("Receiver already taken from channel object"))?;
-
-    // Store the active channel info
-    state.start_channel(channel, &app, rx, &nickname).await?;
-
-    // Get the topic_id from the established channel for logging
-    let topic_id_str = state.get_topic_id().await?;
-
-    tracing::info!(
-        "Active channel SET in join_room for topic: {}",
-        topic_id_str
-    );
-    let store = AppStore::acquire(&app)?;
-    store.set_nickname(&nickname)?;
-    store.update_visited_room(chat_ticket)?;
-    tracing::info!("Joined room: {}", topic_id_str);
+    let store = acquire_store(&app)?;
+    state.join_room(&ticket, &nickname, &store).await?;
     Ok(())
 }
 
 #[tauri::command]
-/// Send a message to the room
+/// Send a message to the room, optionally replying to one of its messages,
+/// returning the id it was sent with
 pub async fn send_message(
+    topic: String,
     message: String,
+    reply_to: Option<String>,
     state: tauri::State<'_, AppContext>,
     _app: tauri::AppHandle, // Marked as unused, can be removed if not needed by Tauri
+) -> tauri::Result<MessageId> {
+    let reply_to = reply_to.as_deref().map(str::parse).transpose()?;
+    let id = state
+        .send_message(&parse_topic(&topic)?, message, reply_to)
+        .await?;
+    Ok(id)
+}
+
+#[tauri::command]
+/// Tell the room whether we are typing, throttled by the backend
+pub async fn set_typing(
+    topic: String,
+    active: bool,
+    state: tauri::State<'_, AppContext>,
 ) -> tauri::Result<()> {
-    let sender = state.get_sender().await?;
-    sender.send(message).await?;
+    state.set_typing(&parse_topic(&topic)?, active).await?;
     Ok(())
 }
 
+#[tauri::command]
+/// Turn sending delivery and read receipts in a room on or off
+pub async fn set_receipts(
+    topic: String,
+    enabled: bool,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> tauri::Result<()> {
+    let store = acquire_store(&app)?;
+    state
+        .set_receipts(&parse_topic(&topic)?, enabled, &store)
+        .await?;
+    Ok(())
+}
+
+#[tauri::command]
+/// Whether we send delivery and read receipts in a room
+pub async fn get_receipts_enabled(
+    topic: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<bool> {
+    Ok(state.receipts_enabled(&parse_topic(&topic)?).await)
+}
+
+#[tauri::command]
+/// Tell the room we have read some of its messages, if receipts are enabled for it
+pub async fn mark_read(
+    topic: String,
+    message_ids: Vec<String>,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<()> {
+    state.mark_read(&parse_topic(&topic)?, &message_ids).await?;
+    Ok(())
+}
+
+#[tauri::command]
+/// Get who acknowledged the given messages of a room
+pub async fn get_receipts(
+    topic: String,
+    message_ids: Vec<String>,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<Vec<ReceiptSummary>> {
+    Ok(state
+        .get_receipts(&parse_topic(&topic)?, &message_ids)
+        .await?)
+}
+
+#[tauri::command]
+/// Get a message of a room and all replies below it, oldest first
+pub async fn get_thread(
+    topic: String,
+    message_id: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<Vec<StoredMessage>> {
+    Ok(state.get_thread(&parse_topic(&topic)?, &message_id).await?)
+}
+
+#[tauri::command]
+/// Replace the text of one of our own messages, returning the id of the edit
+pub async fn edit_message(
+    topic: String,
+    message_id: String,
+    text: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<MessageId> {
+    let id = state
+        .edit_message(&parse_topic(&topic)?, &message_id, text)
+        .await?;
+    Ok(id)
+}
+
+#[tauri::command]
+/// Delete one of our own messages, returning the id of the deletion
+pub async fn delete_message(
+    topic: String,
+    message_id: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<MessageId> {
+    let id = state
+        .delete_message(&parse_topic(&topic)?, &message_id)
+        .await?;
+    Ok(id)
+}
+
+#[tauri::command]
+/// Add or take back our emoji reaction to a message, returning the id it was sent with
+pub async fn react(
+    topic: String,
+    message_id: String,
+    emoji: String,
+    add: bool,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<MessageId> {
+    let id = state
+        .react(&parse_topic(&topic)?, &message_id, emoji, add)
+        .await?;
+    Ok(id)
+}
+
+#[tauri::command]
+/// Act on a member of a room we moderate, returning the id it was sent with
+pub async fn moderate(
+    topic: String,
+    node_id: String,
+    action: ModerationAction,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<MessageId> {
+    let id = state
+        .moderate(&parse_topic(&topic)?, &node_id, action)
+        .await?;
+    Ok(id)
+}
+
+#[tauri::command]
+/// Let a node into an invite-only room we moderate
+pub async fn add_member(
+    topic: String,
+    node_id: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<MessageId> {
+    let id = state
+        .moderate(&parse_topic(&topic)?, &node_id, ModerationAction::AddMember)
+        .await?;
+    Ok(id)
+}
+
+#[tauri::command]
+/// Remove a node from an invite-only room we moderate
+pub async fn remove_member(
+    topic: String,
+    node_id: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<MessageId> {
+    let id = state
+        .moderate(
+            &parse_topic(&topic)?,
+            &node_id,
+            ModerationAction::RemoveMember,
+        )
+        .await?;
+    Ok(id)
+}
+
+#[tauri::command]
+/// Issue an invite to a room that expires after `expires_in_secs` and lets in at
+/// most `max_uses` newcomers, if set. Only invites to invite-only rooms can be
+/// limited. Returns the ticket carrying it
+pub async fn create_invite(
+    topic: String,
+    expires_in_secs: Option<u64>,
+    max_uses: Option<u32>,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<String> {
+    let ticket = state
+        .create_invite(
+            &parse_topic(&topic)?,
+            expires_in_secs.map(Duration::from_secs),
+            max_uses,
+        )
+        .await?;
+    Ok(ticket)
+}
+
+#[tauri::command]
+/// Revoke an invite to a room, telling its members to refuse anyone else using it
+pub async fn revoke_invite(
+    topic: String,
+    invite_id: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<MessageId> {
+    Ok(state
+        .revoke_invite(&parse_topic(&topic)?, &invite_id)
+        .await?)
+}
+
+#[tauri::command]
+/// Get the invites we issued to a room, newest first
+pub async fn get_invites(
+    topic: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<Vec<IssuedInvite>> {
+    Ok(state.get_invites(&parse_topic(&topic)?).await?)
+}
+
+#[tauri::command]
+/// Get who moderates a room and who they banned or muted
+pub async fn get_moderation(
+    topic: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<RoomModeration> {
+    Ok(state.get_moderation(&parse_topic(&topic)?).await?)
+}
+
+#[tauri::command]
+/// Get the current reactions in a room to the given messages, grouped by emoji
+pub async fn get_reactions(
+    topic: String,
+    message_ids: Vec<String>,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<Vec<ReactionSummary>> {
+    Ok(state
+        .get_reactions(&parse_topic(&topic)?, &message_ids)
+        .await?)
+}
+
+#[tauri::command]
+/// Get up to `limit` persisted messages of a room before the one sent at the
+/// `before` timestamp in micros with id `before_id`, or the latest if omitted.
+/// Oldest first.
+pub async fn get_messages(
+    topic: String,
+    before: Option<i64>,
+    before_id: Option<String>,
+    limit: u32,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<Vec<StoredMessage>> {
+    let db = state.get_db().await?;
+    let before = before.map(|at| (at, before_id.as_deref().unwrap_or_default()));
+    Ok(db
+        .get_messages(&parse_topic(&topic)?, before, limit)
+        .await?)
+}
+
+#[tauri::command]
+/// Share a file with the room, e.g. one dropped onto the window, returning
+/// the id of the message announcing it
+pub async fn send_attachment(
+    topic: String,
+    path: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<MessageId> {
+    let id = state
+        .send_attachment(&parse_topic(&topic)?, &PathBuf::from(path))
+        .await?;
+    Ok(id)
+}
+
+#[tauri::command]
+/// Start fetching a message's attachment in the background, returning its hash.
+/// Progress is reported with `attachment-event`s.
+pub async fn download_attachment(
+    message_id: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<BlobHash> {
+    Ok(state.download_attachment(&message_id).await?)
+}
+
+#[tauri::command]
+/// Stop fetching an attachment. Returns false if it wasn't being fetched.
+pub async fn cancel_download(
+    hash: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<bool> {
+    let hash: BlobHash = hash
+        .parse()
+        .map_err(|e| anyhow!("Invalid attachment hash {}: {}", hash, e))?;
+    Ok(state.cancel_download(&hash).await)
+}
+
+#[tauri::command]
+/// Returns how much of a message's attachment has been downloaded
+pub async fn get_download_progress(
+    message_id: String,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<DownloadProgress> {
+    Ok(state.get_download_progress(&message_id).await?)
+}
+
+#[tauri::command]
+/// Save a downloaded attachment into `dir`, or the Downloads folder if omitted,
+/// returning the path it was saved to
+pub async fn save_attachment(
+    message_id: String,
+    dir: Option<String>,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> tauri::Result<PathBuf> {
+    let dir = match dir {
+        Some(dir) => PathBuf::from(dir),
+        None => app.path().download_dir()?,
+    };
+    Ok(state.save_attachment(&message_id, &dir).await?)
+}
+
+#[tauri::command]
+/// Send a private message straight to a peer, returning the id it was sent with
+pub async fn send_direct_message(
+    peer: String,
+    message: String,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> tauri::Result<MessageId> {
+    let nickname = acquire_store(&app)?.get_nickname().unwrap_or_default();
+    let id = state
+        .send_direct_message(parse_node_id(&peer)?, nickname, message)
+        .await?;
+    Ok(id)
+}
+
+#[tauri::command]
+/// Get up to `limit` persisted direct messages exchanged with a peer before the
+/// one sent at the `before` timestamp in micros with id `before_id`, or the
+/// latest if omitted. Oldest first.
+pub async fn get_direct_messages(
+    peer: String,
+    before: Option<i64>,
+    before_id: Option<String>,
+    limit: u32,
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<Vec<StoredDirectMessage>> {
+    let db = state.get_db().await?;
+    let before = before.map(|at| (at, before_id.as_deref().unwrap_or_default()));
+    Ok(db
+        .get_direct_messages(&parse_node_id(&peer)?, before, limit)
+        .await?)
+}
+
+#[tauri::command]
+/// Returns the peers we have direct conversations with, most recently active first
+pub async fn list_direct_conversations(
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<Vec<DirectConversation>> {
+    let db = state.get_db().await?;
+    Ok(db.list_direct_conversations().await?)
+}
+
 #[tauri::command]
 /// Set a new nickname for this node.
 pub async fn set_nickname(nickname: String, app: tauri::AppHandle) -> tauri::Result<()> {
     tracing::info!("Nickname set to: {}", &nickname);
-    AppStore::acquire(&app)?.set_nickname(&nickname)?;
+    acquire_store(&app)?.set_nickname(&nickname)?;
     Ok(())
 }
 
 #[tauri::command]
 /// Get the stored nickname for this node.
 pub async fn get_nickname(app: tauri::AppHandle) -> tauri::Result<Option<String>> {
-    let nickname = AppStore::acquire(&app)?.get_nickname();
+    let nickname = acquire_store(&app)?.get_nickname();
     tracing::info!("Nickname retrieved as: {:?}", &nickname);
     Ok(nickname)
 }
 
+#[tauri::command]
+/// Choose how far message timestamps may drift from local time, in rooms joined from now on.
+pub async fn set_acceptance_window(
+    window: AcceptanceWindow,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> tauri::Result<()> {
+    tracing::info!("Acceptance window set to: {:?}", window);
+    let store = acquire_store(&app)?;
+    state.set_acceptance_window(window, &store).await?;
+    Ok(())
+}
+
+#[tauri::command]
+/// Get how far message timestamps may drift from local time.
+pub async fn get_acceptance_window(app: tauri::AppHandle) -> tauri::Result<AcceptanceWindow> {
+    Ok(acquire_store(&app)?.get_acceptance_window())
+}
+
+#[tauri::command]
+/// Choose how the node finds and reaches peers, from its next start on.
+pub async fn set_network_config(config: NetworkConfig, app: tauri::AppHandle) -> tauri::Result<()> {
+    tracing::info!("Network config set to: {:?}", config);
+    acquire_store(&app)?.set_network_config(&config)?;
+    Ok(())
+}
+
+#[tauri::command]
+/// Get how the node finds and reaches peers.
+pub async fn get_network_config(app: tauri::AppHandle) -> tauri::Result<NetworkConfig> {
+    Ok(acquire_store(&app)?.get_network_config())
+}
+
+#[tauri::command]
+/// Restart the node with the stored network config, rejoining the rooms we are in.
+pub async fn restart_node(
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> tauri::Result<()> {
+    let store = acquire_store(&app)?;
+    let nickname = store.get_nickname().unwrap_or_default();
+    // Rooms aren't told we are leaving, since we are back right away.
+    let tickets = state.suspend().await;
+    crate::init_context(app.clone()).await?;
+    state.resume(tickets, &nickname, &store).await;
+    tracing::info!("Node restarted.");
+    Ok(())
+}
+
 #[tauri::command]
 /// Get the stored room ticket string
 pub async fn get_latest_ticket(
     state: tauri::State<'_, AppContext>,
 ) -> tauri::Result<Option<VisitedRoom>> {
-    let ticket_guard = state.latest_ticket.lock().await;
-    match ticket_guard.as_ref() {
-        Some(ticket_string) => {
-            let ticket = ChatTicket::deserialize(ticket_string)?;
-            Ok(Some(VisitedRoom {
-                id: ticket.topic_id.to_string(),
-                name: ticket.name,
-                ticket: ticket_string.clone(),
-            }))
-        }
-        None => Ok(None),
+    Ok(state.latest_room().await?)
+}
+
+#[tauri::command]
+/// Returns the rooms we are currently joined to
+pub async fn get_active_rooms(
+    state: tauri::State<'_, AppContext>,
+) -> tauri::Result<Vec<VisitedRoom>> {
+    let mut rooms = Vec::new();
+    for topic in state.get_topic_ids().await {
+        let ticket = state.generate_ticket(&topic, TicketOpts::all()).await?;
+        rooms.push(VisitedRoom {
+            id: topic.to_string(),
+            name: ticket.name.clone(),
+            ticket: ticket.serialize(),
+            encrypted: ticket.secret.is_some(),
+        });
     }
+    Ok(rooms)
 }
 
 #[tauri::command]
-/// Leave the currently joined room
+/// Leave a joined room
 pub async fn leave_room(
+    topic: String,
     state: tauri::State<'_, AppContext>,
     app: tauri::AppHandle,
 ) -> tauri::Result<()> {
-    if let Ok(ticket) = state.generate_ticket(TicketOpts::all()).await {
-        AppStore::acquire(&app)?.update_visited_room(ticket)?;
-    };
-    if let Some(id) = state.drop_channel().await? {
-        tracing::info!("Left room: {}", id);
-    };
+    let store = acquire_store(&app)?;
+    state.leave_room(&parse_topic(&topic)?, &store).await?;
     Ok(())
 }
 
@@ -175,7 +532,7 @@ pub async fn get_node_id(state: tauri::State<'_, AppContext>) -> tauri::Result<N
 #[tauri::command]
 /// Returns the list of visited rooms in order of most recently visited
 pub async fn get_visited_rooms(app: tauri::AppHandle) -> tauri::Result<Vec<VisitedRoom>> {
-    let store = AppStore::acquire(&app)?;
+    let store = acquire_store(&app)?;
     Ok(store
         .get_visited_rooms()
         .iter()
@@ -183,6 +540,7 @@ pub async fn get_visited_rooms(app: tauri::AppHandle) -> tauri::Result<Vec<Visit
             id: ticket.topic_id.to_string(),
             name: ticket.name.clone(),
             ticket: ticket.serialize(),
+            encrypted: ticket.secret.is_some(),
         })
         .rev()
         .collect())
@@ -191,8 +549,51 @@ pub async fn get_visited_rooms(app: tauri::AppHandle) -> tauri::Result<Vec<Visit
 #[tauri::command]
 /// Remove a visited room by room topic_id
 pub async fn delete_visited_room(topic: String, app: tauri::AppHandle) -> tauri::Result<()> {
-    let store = AppStore::acquire(&app)?;
+    let store = acquire_store(&app)?;
     tracing::info!("deleting topic: {}", topic);
     store.delete_visited_room(&topic)?;
     Ok(())
 }
+
+#[tauri::command]
+/// Serve the local bot API on localhost, on `port` or the one it last used,
+/// and keep serving it on future launches. Returns where and the token to use.
+pub async fn enable_local_api(
+    port: Option<u16>,
+    state: tauri::State<'_, AppContext>,
+    api: tauri::State<'_, LocalApi>,
+    app: tauri::AppHandle,
+) -> tauri::Result<ApiConfig> {
+    let store = acquire_store(&app)?;
+    // Keep the token clients already have, unless there is none yet.
+    let mut config = store
+        .get_api_config()
+        .unwrap_or_else(|| ApiConfig::generate(DEFAULT_API_PORT));
+    if let Some(port) = port {
+        config.port = port;
+    }
+    api.start(config.clone(), state.inner().clone(), store.clone())
+        .await?;
+    store.set_api_config(Some(&config))?;
+    tracing::info!("Local API enabled on port {}", config.port);
+    Ok(config)
+}
+
+#[tauri::command]
+/// Stop serving the local bot API and disconnect its clients
+pub async fn disable_local_api(
+    api: tauri::State<'_, LocalApi>,
+    app: tauri::AppHandle,
+) -> tauri::Result<()> {
+    acquire_store(&app)?.set_api_config(None)?;
+    if api.stop().await {
+        tracing::info!("Local API disabled");
+    }
+    Ok(())
+}
+
+#[tauri::command]
+/// Returns where the local bot API is served and its token, if it is running
+pub async fn get_local_api(api: tauri::State<'_, LocalApi>) -> tauri::Result<Option<ApiConfig>> {
+    Ok(api.config().await)
+}
@@ -1,13 +1,13 @@
+use std::sync::Arc;
+
 use anyhow::anyhow;
-use state::AppStore;
+use cups_core::{api::LocalApi, chat, FanoutSink};
 use tauri::Manager as _;
 
-use crate::state::{generate_db_migrations, SQL_CHAT_DB};
+use crate::state::{acquire_store, sql_migrations, ChatDb, TauriSink, CHAT_DB_FILE, SQL_CHAT_DB};
 
-mod chat;
 mod ipc;
 mod state;
-mod utils;
 
 /// Initialize the Application Context from disk.
 async fn init_context(app: tauri::AppHandle) -> tauri::Result<()> {
@@ -17,18 +17,42 @@ async fn init_context(app: tauri::AppHandle) -> tauri::Result<()> {
         tracing::info!("Iroh node already initialized. Skipping re-initialization.");
         return Ok(());
     }
-    *state.latest_ticket.lock().await = None;
+
+    // Open the message database where the sql plugin keeps it
+    {
+        let dir = app.path().app_config_dir()?;
+        std::fs::create_dir_all(&dir)?;
+        let db = ChatDb::open(&dir.join(CHAT_DB_FILE))
+            .await
+            .map_err(|e| anyhow!("Failed to open database: {}", e))?;
+        *state.db.lock().await = Some(db);
+    }
 
     // Spawn the Iroh node
     {
-        let key = AppStore::acquire(&app)?.get_secret_key()?;
-        let node = chat::ChatNode::spawn(Some(key))
+        let store = acquire_store(&app)?;
+        let key = store.get_secret_key()?;
+        let blobs_dir = app.path().app_data_dir()?.join("blobs");
+        let node = chat::ChatNode::spawn(Some(key), blobs_dir, store.get_network_config())
             .await
-            .map_err(|e| anyhow!("Failed to spawn node: {}", e))?;
+            .map_err(|e| anyhow!("Failed to spawn node: {}", e))?
+            .with_acceptance_window(store.get_acceptance_window());
+        if let Some(receiver) = node.take_direct_receiver() {
+            state.start_direct_listener(receiver).await?;
+        }
 
         *node_guard = Some(node); // Store the newly spawned node
     };
-    state.drop_channel().await?; // Reset active channel on init
+    state.drop_channels().await?; // Reset active channels on init
+
+    // Serve the local API if it was enabled
+    let store = acquire_store(&app)?;
+    if let Some(config) = store.get_api_config() {
+        let api = app.state::<LocalApi>();
+        if let Err(e) = api.start(config, state.inner().clone(), store).await {
+            tracing::error!("Failed to start local API: {}", e);
+        }
+    }
 
     tracing::info!("Iroh node initialized.");
     Ok(())
@@ -47,14 +71,22 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(
             tauri_plugin_sql::Builder::default()
-                .add_migrations(SQL_CHAT_DB, generate_db_migrations())
+                .add_migrations(SQL_CHAT_DB, sql_migrations())
                 .build(),
         )
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
-        .manage(state::AppContext::new()) // Register the state with Tauri
         .setup(|app| {
+            // Register the state with Tauri, delivering its events to the webview
+            // and to local API clients
+            let api = LocalApi::default();
+            let sink = Arc::new(FanoutSink(vec![
+                Arc::new(TauriSink(app.handle().clone())),
+                Arc::new(api.events()),
+            ]));
+            app.manage(state::AppContext::new(sink));
+            app.manage(api);
             #[cfg(debug_assertions)] // only include this code on debug builds
             app.get_webview_window("main").unwrap().open_devtools();
             let handle = app.handle().clone();
@@ -68,14 +100,56 @@ pub fn run() {
             ipc::create_room,
             ipc::join_room,
             ipc::send_message,
+            ipc::edit_message,
+            ipc::delete_message,
+            ipc::get_messages,
+            ipc::get_thread,
+            ipc::set_typing,
+            ipc::set_receipts,
+            ipc::get_receipts_enabled,
+            ipc::mark_read,
+            ipc::get_receipts,
+            ipc::react,
+            ipc::get_reactions,
+            ipc::moderate,
+            ipc::get_moderation,
+            ipc::add_member,
+            ipc::remove_member,
+            ipc::create_invite,
+            ipc::revoke_invite,
+            ipc::get_invites,
+            ipc::send_attachment,
+            ipc::download_attachment,
+            ipc::cancel_download,
+            ipc::get_download_progress,
+            ipc::save_attachment,
+            ipc::send_direct_message,
+            ipc::get_direct_messages,
+            ipc::list_direct_conversations,
             ipc::leave_room,
             ipc::get_latest_ticket,
+            ipc::get_active_rooms,
             ipc::get_node_id,
             ipc::set_nickname,
             ipc::get_nickname,
+            ipc::set_acceptance_window,
+            ipc::get_acceptance_window,
+            ipc::set_network_config,
+            ipc::get_network_config,
+            ipc::restart_node,
             ipc::get_visited_rooms,
             ipc::delete_visited_room,
+            ipc::enable_local_api,
+            ipc::disable_local_api,
+            ipc::get_local_api,
         ])
-        .run(tauri::generate_context!()) // Run the Tauri application
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Tell our rooms we are leaving and close the endpoint before exiting.
+            if let tauri::RunEvent::Exit = event {
+                let state = app.state::<state::AppContext>();
+                tauri::async_runtime::block_on(state.shutdown());
+            }
+        });
 }
@@ -1,34 +1,56 @@
-mod context;
-mod store;
+//! Plugs the Tauri app into [`cups_core`]: events go to the webview, settings
+//! to the store plugin and the message database is shared with the sql plugin.
 
-pub use context::AppContext;
-pub use store::AppStore;
-use tauri_plugin_sql::{Migration, MigrationKind};
+use std::sync::Arc;
+
+use anyhow::Context as _;
+pub use cups_core::state::*;
+use cups_core::{EventSink, KeyValueStore};
+use tauri::{AppHandle, Emitter as _, Wry};
+use tauri_plugin_sql::MigrationKind;
+use tauri_plugin_store::{Store, StoreExt as _};
 
 pub const SQL_CHAT_DB: &str = "sqlite:chat.db";
 
-pub fn generate_db_migrations() -> Vec<Migration> {
-    let migrations = vec![Migration {
-        version: 1,
-        description: "create_initial_tables",
-        kind: MigrationKind::Up,
-        sql: "
-                CREATE TABLE conversations (
-                    id TEXT PRIMARY KEY,
-                    name TEXT,
-                    last_message_at INTEGER
-                );
-                CREATE TABLE messages (
-                    id TEXT PRIMARY KEY,
-                    conversation_id TEXT NOT NULL,
-                    content TEXT,
-                    created_at INTEGER NOT NULL,
-                    sender_id TEXT,
-                    nickname TEXT,
-                    -- The corrected foreign key with ON DELETE CASCADE --
-                    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
-                );
-            ",
-    }];
-    migrations
+/// The message database migrations, for the sql plugin.
+pub fn sql_migrations() -> Vec<tauri_plugin_sql::Migration> {
+    generate_db_migrations()
+        .into_iter()
+        .map(|migration| tauri_plugin_sql::Migration {
+            version: migration.version,
+            description: migration.description,
+            sql: migration.sql,
+            kind: MigrationKind::Up,
+        })
+        .collect()
+}
+
+/// Emits events to the webview.
+pub struct TauriSink(pub AppHandle);
+
+impl EventSink for TauriSink {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> anyhow::Result<()> {
+        Ok(self.0.emit(event, payload)?)
+    }
+}
+
+/// Keeps settings in the store plugin's `store.json`.
+struct TauriStore(Arc<Store<Wry>>);
+
+impl KeyValueStore for TauriStore {
+    fn get(&self, key: &str) -> Option<serde_json::Value> {
+        self.0.get(key)
+    }
+    fn set(&self, key: &str, value: serde_json::Value) {
+        self.0.set(key, value);
+    }
+}
+
+/// Get a handle for the persistent background store of this application
+pub fn acquire_store(app: &AppHandle) -> anyhow::Result<AppStore> {
+    const STORE: &str = "store.json";
+    let store = app
+        .store(STORE)
+        .context("failed to open store when saving game state.")?;
+    Ok(AppStore::new(Arc::new(TauriStore(store))))
 }
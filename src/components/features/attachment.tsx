@@ -0,0 +1,118 @@
+import { useEffect, useState } from "react";
+import { listen } from "@tauri-apps/api/event";
+import { MdClose, MdDownload, MdSave } from "react-icons/md";
+import { Attachment } from "types";
+import { DownloadEvent } from "types/events";
+import {
+  cancelDownload,
+  downloadAttachment,
+  getDownloadProgress,
+  saveAttachment,
+} from "services/ipc";
+import { notify } from "services/notifications";
+
+type DownloadStatus = "idle" | "downloading" | "complete";
+
+/** Human readable file size. */
+function formatSize(bytes: number): string {
+  if (bytes < 1024) return `${bytes} B`;
+  if (bytes < 1024 * 1024) return `${(bytes / 1024).toFixed(1)} KiB`;
+  return `${(bytes / 1024 / 1024).toFixed(1)} MiB`;
+}
+
+interface AttachmentProps {
+  /** Id of the message that shared the file */
+  messageId: string;
+  attachment: Attachment;
+}
+
+/** A shared file, with controls to download it from peers and save it to disk. */
+export default function AttachmentBubble({
+  messageId,
+  attachment,
+}: AttachmentProps) {
+  const [status, setStatus] = useState<DownloadStatus>("idle");
+  const [received, setReceived] = useState(0);
+
+  useEffect(() => {
+    getDownloadProgress(messageId).then((progress) => {
+      if (!progress) return;
+      setReceived(progress.received);
+      if (progress.complete) setStatus("complete");
+      else if (progress.received > 0) setStatus("downloading");
+    });
+    const unlisten = listen<DownloadEvent>("attachment-event", (event) => {
+      if (event.payload.hash !== attachment.hash) return;
+      switch (event.payload.type) {
+        case "progress":
+          setStatus("downloading");
+          setReceived(event.payload.received);
+          break;
+        case "completed":
+          setStatus("complete");
+          setReceived(attachment.size);
+          break;
+        case "failed":
+          setStatus("idle");
+          notify(
+            `❌ ${attachment.name}: ${event.payload.error}`,
+            "downloadFailed"
+          );
+          break;
+        case "cancelled":
+          setStatus("idle");
+          setReceived(0);
+          break;
+      }
+    });
+    return () => {
+      unlisten.then((drop) => drop());
+    };
+  }, [messageId, attachment]);
+
+  const onDownload = async () => {
+    if (await downloadAttachment(messageId)) setStatus("downloading");
+  };
+  const onSave = async () => {
+    const path = await saveAttachment(messageId);
+    if (path) notify(`💾 saved to ${path}`, "attachmentSaved");
+  };
+
+  return (
+    <div className="flex flex-row items-center space-x-2">
+      <span>
+        📎 {attachment.name} ({formatSize(attachment.size)})
+      </span>
+      {status === "idle" && (
+        <button
+          className="btn btn-xs"
+          onClick={onDownload}
+          aria-label="Download file"
+        >
+          <MdDownload />
+        </button>
+      )}
+      {status === "downloading" && (
+        <>
+          <progress
+            className="progress progress-info w-24"
+            value={received}
+            max={attachment.size}
+          />
+          <button
+            className="btn btn-xs"
+            onClick={() => cancelDownload(attachment.hash)}
+            aria-label="Cancel download"
+          >
+            <MdClose />
+          </button>
+        </>
+      )}
+      {status === "complete" && (
+        <button className="btn btn-xs" onClick={onSave} aria-label="Save file">
+          <MdSave />
+        </button>
+      )}
+    </div>
+  );
+}
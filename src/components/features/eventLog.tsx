@@ -112,6 +112,51 @@ const RenderEvent: React.FC<{ event: ChatEvent }> = ({ event }) => {
           </p>
         </Card>
       );
+    case "reconnecting":
+      return (
+        <Card title="Reconnecting">
+          <Property label="Attempt">{event.attempt}</Property>
+          <Property label="In">{`${event.delayMs / 1000}s`}</Property>
+        </Card>
+      );
+    case "reconnected":
+      return (
+        <Card title="Reconnected">
+          <p className="text-xs opacity-80">Rejoined the room.</p>
+        </Card>
+      );
+    case "moderation":
+      return (
+        <Card title="Moderation">
+          <Property label="From">{event.from}</Property>
+          <Property label="Action">{event.action}</Property>
+          <Property label="Target">{event.target}</Property>
+          <Property label="Timestamp">
+            {formatDate(event.sentTimestamp / 1000)}
+          </Property>
+        </Card>
+      );
+    case "inviteRedeemed":
+      return (
+        <Card title="Invite Used">
+          <Property label="From">{event.from}</Property>
+          <Property label="Invite">{event.inviteId}</Property>
+          <Property label="Issuer">{event.invite.terms.issuer}</Property>
+          <Property label="Timestamp">
+            {formatDate(event.sentTimestamp / 1000)}
+          </Property>
+        </Card>
+      );
+    case "inviteRevoked":
+      return (
+        <Card title="Invite Revoked">
+          <Property label="From">{event.from}</Property>
+          <Property label="Invite">{event.inviteId}</Property>
+          <Property label="Timestamp">
+            {formatDate(event.sentTimestamp / 1000)}
+          </Property>
+        </Card>
+      );
     case "errored":
       return (
         <Card title="Error Occurred">
@@ -7,6 +7,7 @@ const LobbyForm: React.FC<ModalProps> = ({ isOpen, onClose }) => {
   const [nickname, setNickname] = useState<string>();
   const [roomName, setRoomName] = useState<string>();
   const [ticket, setTicket] = useState<string>();
+  const [inviteOnly, setInviteOnly] = useState<boolean>(false);
   const inputRef = useRef<HTMLInputElement | null>(null);
 
   useEffect(() => {
@@ -36,7 +37,7 @@ const LobbyForm: React.FC<ModalProps> = ({ isOpen, onClose }) => {
                   window.location.href = "/chat";
                 }
               } else if (roomName) {
-                if (await createRoom(nickname, roomName)) {
+                if (await createRoom(nickname, roomName, true, inviteOnly)) {
                   window.location.href = "/chat";
                 }
               }
@@ -53,6 +54,15 @@ const LobbyForm: React.FC<ModalProps> = ({ isOpen, onClose }) => {
             onChange={(e) => setRoomName(e.target.value)}
             required // Optional: makes the browser enforce that the field is filled
           />
+          <label className="label cursor-pointer space-x-2">
+            <input
+              type="checkbox"
+              className="checkbox checkbox-primary"
+              checked={inviteOnly}
+              onChange={(e) => setInviteOnly(e.target.checked)}
+            />
+            <span>Invite only</span>
+          </label>
           <button
             disabled={!nickname || !roomName}
             type="submit"
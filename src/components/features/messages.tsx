@@ -1,17 +1,32 @@
 import React, { useState, useEffect, useRef, useCallback } from "react";
 import { MdSend } from "react-icons/md";
-import { addMessage, eventToMessage } from "services/db";
+import { getCurrentWebview } from "@tauri-apps/api/webview";
 import {
   sendMessage,
+  sendAttachment,
+  editMessage,
+  deleteMessage,
+  react,
+  setTyping,
+  markRead,
   getNodeId,
   getNickname,
   getLatestTicket,
+  getThread,
 } from "services/ipc";
-import { VisitedRoom } from "types";
-import { MessageReceivedEvent } from "types/events";
+import { messageToEvent } from "services/db";
+import { Attachment, VisitedRoom } from "types";
+import {
+  MessageDeletedEvent,
+  MessageEditedEvent,
+  MessageReceipts,
+  MessageReceivedEvent,
+  ReactionCount,
+} from "types/events";
 import { useInfiniteScroll } from "hooks/useInfiniteScroll";
 import { useScrollToBottom } from "hooks/useScrollToBottom";
 import { formatDate } from "utils";
+import AttachmentBubble from "components/features/attachment";
 
 interface DisplayMessage {
   /** NodeId of the sender */
@@ -26,6 +41,35 @@ interface DisplayMessage {
   isMine: boolean;
   /** Unique ID for React's key prop */
   displayId: string;
+  /** Set when the message shares a file, displayId is then the message id */
+  attachment?: Attachment;
+  /** Id of the message once it was sent, needed to edit or delete it */
+  id?: string;
+  /** Send time of the author's latest edit */
+  editedAt?: number;
+  /** The author deleted the message */
+  deleted?: boolean;
+  /** The message this one answers */
+  replyTo?: string | null;
+}
+
+function toDisplayMessage(
+  msg: MessageReceivedEvent,
+  myNodeId: string | null
+): DisplayMessage {
+  return {
+    from: msg.from,
+    text: msg.text,
+    nickname: msg.nickname,
+    sentTimestamp: msg.sentTimestamp,
+    isMine: myNodeId ? msg.from === myNodeId : false, // Determine if the message is from the current user
+    displayId: msg.id,
+    attachment: msg.attachment,
+    id: msg.id,
+    editedAt: msg.editedAt,
+    deleted: msg.deleted,
+    replyTo: msg.replyTo,
+  };
 }
 
 interface MessageProps {
@@ -37,20 +81,41 @@ interface MessageProps {
   isLoadingMore: boolean;
   /** True if there are more older messages to load */
   hasMoreOldMessages: boolean;
+  /** Apply an edit or deletion of ours to the messages from the database */
+  onAmend: (amendment: MessageEditedEvent | MessageDeletedEvent) => void;
+  /** Reactions to the messages, by message id */
+  reactions: Record<string, ReactionCount[]>;
+  /** Who acknowledged the messages, by message id */
+  receipts: Record<string, MessageReceipts>;
   /** Flag for it any peers are online */
   peersOnline: boolean;
+  /** Nicknames of the peers currently typing */
+  typingPeers: string[];
 }
 
 const SCROLL_TOP_THRESHOLD = 50; // pixels
+/** Offered under every message, others can be sent from the backend or API */
+const QUICK_REACTIONS = ["👍", "❤️", "😂", "🎉"];
 
 const Messages: React.FC<MessageProps> = ({
   dbMessages,
   onLoadMore,
   isLoadingMore,
   hasMoreOldMessages,
+  onAmend,
+  reactions,
+  receipts,
   peersOnline,
+  typingPeers,
 }) => {
   const [inputValue, setInputValue] = useState("");
+  // The message of ours being edited in the input, if any
+  const [editing, setEditing] = useState<DisplayMessage | null>(null);
+  // The message the input is answering, if any
+  const [replyingTo, setReplyingTo] = useState<DisplayMessage | null>(null);
+  // Id of the message whose thread is shown instead of the whole room
+  const [threadRoot, setThreadRoot] = useState<string | null>(null);
+  const [threadMessages, setThreadMessages] = useState<DisplayMessage[]>([]);
   const [submitting, setSubmitting] = useState(false);
   const [myNodeId, setMyNodeId] = useState<string | null>(null);
   const [myNickname, setMyNickname] = useState<string | null>(null);
@@ -85,32 +150,136 @@ const Messages: React.FC<MessageProps> = ({
     fetchUserDetails();
   }, []);
 
+  // Share files dropped onto the window with the room
+  useEffect(() => {
+    if (!ticket || !myNodeId || !myNickname) return;
+    const unlisten = getCurrentWebview().onDragDropEvent(async (event) => {
+      if (event.payload.type !== "drop") return;
+      for (const path of event.payload.paths) {
+        const id = await sendAttachment(ticket.id, path);
+        if (!id) continue;
+        const name = path.split(/[\\/]/).pop() ?? path;
+        const sentTimestamp = Date.now() * 1000;
+        setLocalSentMessages((prev) => [
+          ...prev,
+          {
+            id,
+            from: myNodeId,
+            text: `📎 ${name}`,
+            nickname: myNickname,
+            sentTimestamp,
+            isMine: true,
+            displayId: `local-${myNodeId}-${sentTimestamp}`,
+          },
+        ]);
+      }
+    });
+    return () => {
+      unlisten.then((drop) => drop());
+    };
+  }, [ticket, myNodeId, myNickname]);
+
   // Combine and sort messages whenever dbMessages or localSentMessages change
   useEffect(() => {
-    const remoteDisplayMessages: DisplayMessage[] = dbMessages.map((msg) => ({
-      // Spread common properties from MessageReceivedEvent
-      from: msg.from,
-      text: msg.text,
-      nickname: msg.nickname,
-      sentTimestamp: msg.sentTimestamp,
-      isMine: myNodeId ? msg.from === myNodeId : false, // Determine if the message is from the current user
-      displayId: `remote-${msg.from}-${msg.sentTimestamp}`,
-    }));
+    const remoteDisplayMessages: DisplayMessage[] = dbMessages.map((msg) =>
+      toDisplayMessage(msg, myNodeId)
+    );
 
     const allMessages = [...localSentMessages, ...remoteDisplayMessages];
     allMessages.sort((a, b) => a.sentTimestamp - b.sentTimestamp);
     setDisplayedMessages(allMessages);
   }, [dbMessages, localSentMessages, myNodeId]);
 
+  // Messages of others already acknowledged as read
+  const markedRead = useRef<Set<string>>(new Set());
+  useEffect(() => {
+    if (!ticket || !document.hasFocus()) return;
+    const unread = displayedMessages
+      .filter((msg) => !msg.isMine && msg.id && !markedRead.current.has(msg.id))
+      .map((msg) => msg.id!);
+    if (unread.length === 0) return;
+    unread.forEach((id) => markedRead.current.add(id));
+    markRead(ticket.id, unread);
+  }, [displayedMessages, ticket]);
+
+  // Load the open thread from the database, again whenever new messages come in
+  useEffect(() => {
+    if (!ticket || !threadRoot) return;
+    getThread(ticket.id, threadRoot)
+      .then((thread) =>
+        setThreadMessages(
+          thread.map((msg) => toDisplayMessage(messageToEvent(msg), myNodeId))
+        )
+      )
+      .catch((error) => console.error("Failed to load thread:", error));
+  }, [ticket, threadRoot, displayedMessages, myNodeId]);
+
+  // Apply an edit or deletion we sent to both our local and persisted messages
+  const applyOwnAmendment = (
+    amendment: MessageEditedEvent | MessageDeletedEvent
+  ) => {
+    setLocalSentMessages((prev) =>
+      prev.map((msg) => {
+        if (msg.id !== amendment.targetId) return msg;
+        return amendment.type === "messageDeleted"
+          ? { ...msg, text: "", attachment: undefined, deleted: true }
+          : {
+              ...msg,
+              text: amendment.newText,
+              editedAt: amendment.sentTimestamp,
+            };
+      })
+    );
+    onAmend(amendment);
+  };
+
+  const handleEditMessage = async (message: DisplayMessage, text: string) => {
+    if (!ticket || !myNodeId || !message.id) return;
+    const id = await editMessage(ticket.id, message.id, text);
+    if (!id) return;
+    applyOwnAmendment({
+      type: "messageEdited",
+      id,
+      from: myNodeId,
+      targetId: message.id,
+      newText: text,
+      sentTimestamp: Date.now() * 1000,
+      clockSkewed: false,
+    });
+  };
+
+  const handleDeleteMessage = async (message: DisplayMessage) => {
+    if (!ticket || !myNodeId || !message.id) return;
+    const id = await deleteMessage(ticket.id, message.id);
+    if (!id) return;
+    applyOwnAmendment({
+      type: "messageDeleted",
+      id,
+      from: myNodeId,
+      targetId: message.id,
+      sentTimestamp: Date.now() * 1000,
+      clockSkewed: false,
+    });
+  };
+
   const handleSendMessage = async (
     e: React.FormEvent<HTMLFormElement> | React.KeyboardEvent<HTMLInputElement>
   ) => {
     e.preventDefault();
+    if (editing && inputValue.trim()) {
+      setSubmitting(true);
+      await handleEditMessage(editing, inputValue.trim());
+      setEditing(null);
+      setInputValue("");
+      setSubmitting(false);
+      return;
+    }
     if (inputValue.trim() && myNodeId && myNickname) {
       setSubmitting(true);
 
       const messageToSend = inputValue.trim();
       const sentTimestamp = Date.now() * 1000;
+      const replyTo = replyingTo?.id;
       const newLocalMessage: DisplayMessage = {
         from: myNodeId,
         text: messageToSend,
@@ -118,22 +287,23 @@ const Messages: React.FC<MessageProps> = ({
         sentTimestamp,
         isMine: true,
         displayId: `local-${myNodeId}-${sentTimestamp}`, // Unique ID for local message
+        replyTo,
       };
-      const newDBMessage: MessageReceivedEvent = {
-        type: "messageReceived",
-        from: myNodeId,
-        nickname: myNickname,
-        text: messageToSend,
-        sentTimestamp: Date.now() * 1000,
-      };
-
       setLocalSentMessages((prev) => [...prev, newLocalMessage]);
       setInputValue(""); // Clear input
+      setReplyingTo(null);
+      if (ticket) setTyping(ticket.id, false);
 
       try {
-        if (ticket) await addMessage(eventToMessage(newDBMessage, ticket));
-        await sendMessage(messageToSend);
-        // Message is already displayed locally. No further action on success needed here.
+        if (!ticket) throw new Error("no room to send to");
+        const id = await sendMessage(ticket.id, messageToSend, replyTo);
+        if (!id) throw new Error("message was not sent");
+        // Message is persisted by the backend and already displayed locally, keep its id to edit it.
+        setLocalSentMessages((prev) =>
+          prev.map((msg) =>
+            msg.displayId === newLocalMessage.displayId ? { ...msg, id } : msg
+          )
+        );
       } catch (error) {
         console.error("Failed to send message via IPC:", error);
         setLocalSentMessages((prev) =>
@@ -172,12 +342,55 @@ const Messages: React.FC<MessageProps> = ({
 
   return (
     <div className="flex flex-col flex-1 w-full min-h-0">
+      {threadRoot && (
+        <div className="flex flex-row justify-between px-2 text-sm border-b border-base-300">
+          <span className="font-semibold">Thread</span>
+          <button className="link" onClick={() => setThreadRoot(null)}>
+            back to room
+          </button>
+        </div>
+      )}
       <MessageArea
-        displayedMessages={displayedMessages}
-        onLoadMore={onLoadMore}
+        displayedMessages={threadRoot ? threadMessages : displayedMessages}
+        onLoadMore={threadRoot ? async () => {} : onLoadMore}
         isLoadingMore={isLoadingMore}
-        hasMoreOldMessages={hasMoreOldMessages}
+        hasMoreOldMessages={threadRoot ? false : hasMoreOldMessages}
+        onEdit={(message) => {
+          setReplyingTo(null);
+          setEditing(message);
+          setInputValue(message.text);
+          textareaRef.current?.focus();
+        }}
+        onDelete={handleDeleteMessage}
+        onReply={(message) => {
+          setEditing(null);
+          setReplyingTo(message);
+          textareaRef.current?.focus();
+        }}
+        onOpenThread={setThreadRoot}
+        reactions={reactions}
+        receipts={receipts}
+        myNodeId={myNodeId}
+        onReact={(message, emoji, add) => {
+          if (ticket && message.id) react(ticket.id, message.id, emoji, add);
+        }}
       />
+      {typingPeers.length > 0 && (
+        <div className="px-2 text-xs italic opacity-75">
+          {typingPeers.join(", ")} {typingPeers.length === 1 ? "is" : "are"}{" "}
+          typing...
+        </div>
+      )}
+      {replyingTo && (
+        <div className="flex flex-row justify-between px-2 text-sm bg-blue-950">
+          <span className="truncate opacity-75">
+            Replying to {replyingTo.nickname}: {replyingTo.text}
+          </span>
+          <button className="link" onClick={() => setReplyingTo(null)}>
+            cancel
+          </button>
+        </div>
+      )}
       <form
         className="flex flex-row space-x-2 p-2 border-t border-base-300 bg-blue-950"
         onSubmit={handleSendMessage}
@@ -185,13 +398,30 @@ const Messages: React.FC<MessageProps> = ({
         <textarea
           ref={textareaRef}
           className="textarea textarea-bordered textarea-info w-full"
-          placeholder={peersOnline ? "Message" : "No peers online"}
+          placeholder={
+            !peersOnline
+              ? "No peers online"
+              : editing
+                ? "Edit message"
+                : "Message"
+          }
           disabled={!peersOnline}
           rows={1}
           value={inputValue}
-          onChange={(e) => setInputValue(e.target.value)}
+          onChange={(e) => {
+            setInputValue(e.target.value);
+            // Edits aren't announced, only new messages
+            if (ticket && !editing) {
+              setTyping(ticket.id, e.target.value.trim() !== "");
+            }
+          }}
           onKeyDown={(e) => {
-            if (e.key === "Enter" && !e.shiftKey) {
+            if (e.key === "Escape" && editing) {
+              setEditing(null);
+              setInputValue("");
+            } else if (e.key === "Escape" && replyingTo) {
+              setReplyingTo(null);
+            } else if (e.key === "Enter" && !e.shiftKey) {
               e.preventDefault();
               if (!submitting && inputValue.trim()) {
                 handleSendMessage(e as any); // Cast for simplicity
@@ -220,7 +450,28 @@ const MessageArea: React.FC<{
   onLoadMore: () => Promise<void>;
   isLoadingMore: boolean;
   hasMoreOldMessages: boolean;
-}> = ({ displayedMessages, onLoadMore, isLoadingMore, hasMoreOldMessages }) => {
+  onEdit: (message: DisplayMessage) => void;
+  onDelete: (message: DisplayMessage) => void;
+  onReply: (message: DisplayMessage) => void;
+  onOpenThread: (messageId: string) => void;
+  reactions: Record<string, ReactionCount[]>;
+  receipts: Record<string, MessageReceipts>;
+  myNodeId: string | null;
+  onReact: (message: DisplayMessage, emoji: string, add: boolean) => void;
+}> = ({
+  displayedMessages,
+  onLoadMore,
+  isLoadingMore,
+  hasMoreOldMessages,
+  onEdit,
+  onDelete,
+  onReply,
+  onOpenThread,
+  reactions,
+  receipts,
+  myNodeId,
+  onReact,
+}) => {
   const messagesEndRef = useRef<null | HTMLDivElement>(null);
   const scrollContainerRef = useRef<null | HTMLDivElement>(null);
   const { isAdjustingScroll } = useInfiniteScroll({
@@ -270,6 +521,18 @@ const MessageArea: React.FC<{
       )}
       {displayedMessages.map((message) => {
         const chatAlignment = message.isMine ? "chat-end" : "chat-start";
+        const messageReactions = (message.id && reactions[message.id]) || [];
+        const reactedWith = (emoji: string) =>
+          messageReactions.some(
+            (reaction) =>
+              reaction.emoji === emoji &&
+              myNodeId !== null &&
+              reaction.senders.includes(myNodeId)
+          );
+        const receipt = message.id ? receipts[message.id] : undefined;
+        const parent = message.replyTo
+          ? displayedMessages.find((msg) => msg.id === message.replyTo)
+          : undefined;
 
         return (
           <div key={message.displayId} className={`chat ${chatAlignment}`}>
@@ -282,8 +545,101 @@ const MessageArea: React.FC<{
               <time className="text-xs opacity-50">
                 {formatDate(message.sentTimestamp / 1000)}
               </time>
+              {message.editedAt && !message.deleted && (
+                <span className="ml-1 text-xs opacity-50">(edited)</span>
+              )}
+              {message.isMine && receipt && (
+                <span
+                  className="ml-1 text-xs opacity-50"
+                  title={`Delivered to ${receipt.delivered.length}, read by ${receipt.read.length}`}
+                >
+                  {receipt.read.length > 0
+                    ? `✓✓ ${receipt.read.length}`
+                    : `✓ ${receipt.delivered.length}`}
+                </span>
+              )}
+            </div>
+            {message.replyTo && (
+              <button
+                className="chat-header link text-xs opacity-75 truncate max-w-xs"
+                onClick={() => onOpenThread(message.replyTo!)}
+              >
+                ↳{" "}
+                {parent
+                  ? `${parent.nickname}: ${parent.deleted ? "message deleted" : parent.text}`
+                  : "reply to an earlier message"}
+              </button>
+            )}
+            <div className="chat-bubble wrap-anywhere">
+              {message.deleted ? (
+                <span className="italic opacity-50">Message deleted</span>
+              ) : message.attachment ? (
+                <AttachmentBubble
+                  messageId={message.displayId}
+                  attachment={message.attachment}
+                />
+              ) : (
+                message.text
+              )}
             </div>
-            <div className="chat-bubble wrap-anywhere">{message.text}</div>
+            {!message.deleted && messageReactions.length > 0 && (
+              <div className="chat-footer space-x-1">
+                {messageReactions.map((reaction) => (
+                  <button
+                    key={reaction.emoji}
+                    className={`badge badge-sm ${
+                      reactedWith(reaction.emoji) ? "badge-info" : "badge-ghost"
+                    }`}
+                    onClick={() =>
+                      onReact(
+                        message,
+                        reaction.emoji,
+                        !reactedWith(reaction.emoji)
+                      )
+                    }
+                  >
+                    {reaction.emoji} {reaction.senders.length}
+                  </button>
+                ))}
+              </div>
+            )}
+            {message.id && !message.deleted && (
+              <div className="chat-footer space-x-2">
+                <button
+                  className="link text-xs opacity-50"
+                  onClick={() => onReply(message)}
+                >
+                  reply
+                </button>
+                {QUICK_REACTIONS.filter((emoji) => !reactedWith(emoji)).map(
+                  (emoji) => (
+                    <button
+                      key={emoji}
+                      className="text-xs opacity-50 hover:opacity-100"
+                      onClick={() => onReact(message, emoji, true)}
+                    >
+                      {emoji}
+                    </button>
+                  )
+                )}
+                {message.isMine && !message.attachment && (
+                  <button
+                    className="link text-xs opacity-50"
+                    onClick={() => onEdit(message)}
+                  >
+                    edit
+                  </button>
+                )}
+                {message.isMine && (
+                  <button
+                    className="link text-xs opacity-50"
+                    onClick={() => onDelete(message)}
+                  >
+                    delete
+                  </button>
+                )}
+              </div>
+            )}
           </div>
         );
       })}
@@ -1,13 +1,63 @@
 import Modal from "components/elements/modal";
-import { useCallback, useState } from "react";
-import { PeerInfo, PeerStatus } from "types";
+import { useCallback, useEffect, useState } from "react";
+import {
+  addMember,
+  getModeration,
+  getNodeId,
+  moderate,
+  removeMember,
+} from "services/ipc";
+import { PeerInfo, PeerStatus, RoomModeration } from "types";
+import { ModerationAction } from "types/events";
 
-const PeerInfoModal: React.FC<{ peers: PeerInfo[] }> = ({ peers }) => {
+const PeerInfoModal: React.FC<{ topic?: string; peers: PeerInfo[] }> = ({
+  topic,
+  peers,
+}) => {
   const [openPeers, setOpenPeers] = useState<boolean>(false);
+  const [myNodeId, setMyNodeId] = useState<string>("");
+  const [moderation, setModeration] = useState<RoomModeration | null>(null);
+  const [newMember, setNewMember] = useState<string>("");
   const online = useCallback(() => {
     return peers.filter((p) => p.status === "Online");
   }, [peers]);
 
+  useEffect(() => {
+    getNodeId().then(setMyNodeId);
+  }, []);
+  // Roles and bans show up in the peer list, so refresh along with it.
+  useEffect(() => {
+    if (topic && openPeers) getModeration(topic).then(setModeration);
+  }, [topic, openPeers, peers]);
+
+  const isCreator = moderation?.creator === myNodeId;
+  const isAdmin = isCreator || !!moderation?.admins.includes(myNodeId);
+  const inviteOnly = !!moderation?.members;
+  // Mirrors the backend: nobody acts on the creator, only the creator on admins.
+  const canModerate = (peer: PeerInfo) =>
+    isAdmin &&
+    peer.id !== myNodeId &&
+    peer.id !== moderation?.creator &&
+    (isCreator || !moderation?.admins.includes(peer.id));
+
+  const act = async (peer: PeerInfo, action: ModerationAction) => {
+    if (!topic) return;
+    await moderate(topic, peer.id, action);
+    setModeration(await getModeration(topic));
+  };
+
+  // Members have to be added before they are handed a ticket.
+  const invite = async () => {
+    if (!topic || !newMember) return;
+    if (await addMember(topic, newMember.trim())) setNewMember("");
+    setModeration(await getModeration(topic));
+  };
+  const uninvite = async (peer: PeerInfo) => {
+    if (!topic) return;
+    await removeMember(topic, peer.id);
+    setModeration(await getModeration(topic));
+  };
+
   return (
     <>
       <Modal
@@ -24,9 +74,73 @@ const PeerInfoModal: React.FC<{ peers: PeerInfo[] }> = ({ peers }) => {
               <PeerActivityStatus status={peer.status} />
               {peer.nickname} -{" "}
               {new Date(peer.lastSeen / 1000).toLocaleTimeString()}
+              {peer.role === "Creator" && " (creator)"}
+              {peer.role === "Admin" && " (admin)"}
+              {peer.muted && " (muted)"}
+              {peer.offlineReason === "Left" && " (left)"}
+              {peer.offlineReason === "Kicked" && " (kicked)"}
+              {peer.offlineReason === "Banned" && " (banned)"}
+              {peer.offlineReason === "Removed" && " (removed)"}
+              {canModerate(peer) && (
+                <span className="ml-auto flex flex-row space-x-1">
+                  {isCreator && (
+                    <ModerateButton
+                      label={peer.role === "Admin" ? "Revoke" : "Admin"}
+                      onClick={() =>
+                        act(peer, peer.role === "Admin" ? "revoke" : "grant")
+                      }
+                    />
+                  )}
+                  <ModerateButton
+                    label={peer.muted ? "Unmute" : "Mute"}
+                    onClick={() => act(peer, peer.muted ? "unmute" : "mute")}
+                  />
+                  <ModerateButton
+                    label="Kick"
+                    onClick={() => act(peer, "kick")}
+                  />
+                  {moderation?.banned.includes(peer.id) ? (
+                    <ModerateButton
+                      label="Unban"
+                      onClick={() => act(peer, "unban")}
+                    />
+                  ) : (
+                    <ModerateButton
+                      label="Ban"
+                      onClick={() => act(peer, "ban")}
+                    />
+                  )}
+                  {inviteOnly && (
+                    <ModerateButton
+                      label="Remove"
+                      onClick={() => uninvite(peer)}
+                    />
+                  )}
+                </span>
+              )}
             </li>
           ))}
         </ul>
+        {inviteOnly && isAdmin && (
+          <form
+            className="flex flex-row space-x-2 p-1"
+            onSubmit={async (e) => {
+              e.preventDefault();
+              await invite();
+            }}
+          >
+            <input
+              className="input input-sm input-primary grow"
+              type="text"
+              placeholder="Node ID to invite"
+              value={newMember}
+              onChange={(e) => setNewMember(e.target.value)}
+            />
+            <button type="submit" className="btn btn-sm" disabled={!newMember}>
+              Add member
+            </button>
+          </form>
+        )}
       </Modal>
       <button
         className="btn bg-blue-950 hover:bg-primary"
@@ -38,6 +152,15 @@ const PeerInfoModal: React.FC<{ peers: PeerInfo[] }> = ({ peers }) => {
   );
 };
 
+const ModerateButton: React.FC<{ label: string; onClick: () => void }> = ({
+  label,
+  onClick,
+}) => (
+  <button className="btn btn-xs" onClick={onClick}>
+    {label}
+  </button>
+);
+
 const PeerActivityStatus: React.FC<{ status: PeerStatus }> = ({ status }) => {
   switch (status) {
     case "Online":
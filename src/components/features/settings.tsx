@@ -0,0 +1,160 @@
+import Modal, { ModalProps } from "components/elements/modal";
+import { useEffect, useState } from "react";
+import {
+  getAcceptanceWindow,
+  getNetworkConfig,
+  restartNode,
+  setAcceptanceWindow,
+  setNetworkConfig,
+} from "services/ipc";
+import { notify } from "services/notifications";
+import { NetworkConfig } from "types";
+
+const SettingsModal: React.FC<ModalProps> = ({ isOpen, onClose }) => {
+  const [lanOnly, setLanOnly] = useState<boolean>(false);
+  const [relays, setRelays] = useState<string>("");
+  const [pkarrRelay, setPkarrRelay] = useState<string>("");
+  const [dnsOrigin, setDnsOrigin] = useState<string>("");
+  const [bindPort, setBindPort] = useState<string>("");
+  const [restarting, setRestarting] = useState<boolean>(false);
+  const [maxAgeSecs, setMaxAgeSecs] = useState<string>("");
+  const [maxSkewSecs, setMaxSkewSecs] = useState<string>("");
+
+  useEffect(() => {
+    if (!isOpen) return;
+    getNetworkConfig().then((config) => {
+      setLanOnly(config.mode === "lanOnly");
+      setRelays(config.relays.join("\n"));
+      setPkarrRelay(config.pkarrRelay ?? "");
+      setDnsOrigin(config.dnsOrigin ?? "");
+      setBindPort(config.bindPort?.toString() ?? "");
+    });
+    getAcceptanceWindow().then((window) => {
+      setMaxAgeSecs(window.maxAgeSecs.toString());
+      setMaxSkewSecs(window.maxSkewSecs.toString());
+    });
+  }, [isOpen]);
+
+  const saveWindow = async () => {
+    const maxAge = parseInt(maxAgeSecs);
+    const maxSkew = parseInt(maxSkewSecs);
+    if (!(maxAge >= 0 && maxSkew >= 0)) return;
+    await setAcceptanceWindow({ maxAgeSecs: maxAge, maxSkewSecs: maxSkew });
+    notify("Applies to rooms joined from now on.");
+  };
+
+  // Empty fields fall back to the defaults.
+  const saveAndRestart = async () => {
+    const port = parseInt(bindPort);
+    const config: NetworkConfig = {
+      mode: lanOnly ? "lanOnly" : "public",
+      relays: relays
+        .split("\n")
+        .map((relay) => relay.trim())
+        .filter((relay) => relay !== ""),
+      pkarrRelay: pkarrRelay.trim() || null,
+      dnsOrigin: dnsOrigin.trim() || null,
+      bindPort: port > 0 ? port : null,
+    };
+    if (!(await setNetworkConfig(config))) return;
+    setRestarting(true);
+    await restartNode();
+    setRestarting(false);
+    notify("Network settings applied.");
+  };
+
+  return (
+    <Modal isOpen={isOpen} onClose={onClose} title="Settings">
+      <form
+        className="flex flex-col space-y-2 p-1"
+        onSubmit={async (e) => {
+          e.preventDefault();
+          await saveAndRestart();
+        }}
+      >
+        <label className="label flex flex-row space-x-2">
+          <input
+            type="checkbox"
+            className="checkbox checkbox-sm"
+            checked={lanOnly}
+            onChange={(e) => setLanOnly(e.target.checked)}
+          />
+          <span>Local network only</span>
+        </label>
+        <p className="text-xs opacity-70">
+          Find peers on this network over mDNS, without public relays or
+          discovery. Peers elsewhere can't be reached.
+        </p>
+        <textarea
+          className="textarea textarea-sm textarea-primary"
+          placeholder="Relay URLs, one per line"
+          disabled={lanOnly}
+          value={relays}
+          onChange={(e) => setRelays(e.target.value)}
+        />
+        <input
+          className="input input-sm input-primary"
+          placeholder="Pkarr relay URL"
+          disabled={lanOnly}
+          value={pkarrRelay}
+          onChange={(e) => setPkarrRelay(e.target.value)}
+        />
+        <input
+          className="input input-sm input-primary"
+          placeholder="DNS discovery origin"
+          disabled={lanOnly}
+          value={dnsOrigin}
+          onChange={(e) => setDnsOrigin(e.target.value)}
+        />
+        <input
+          className="input input-sm input-primary"
+          type="number"
+          min="1"
+          max="65535"
+          placeholder="Bind port"
+          value={bindPort}
+          onChange={(e) => setBindPort(e.target.value)}
+        />
+        <p className="text-xs opacity-70">
+          Leave a field empty to use the default.
+        </p>
+        <button type="submit" className="btn btn-sm" disabled={restarting}>
+          {restarting ? "Restarting..." : "Save and restart"}
+        </button>
+      </form>
+      <form
+        className="flex flex-col space-y-2 p-1 mt-2"
+        onSubmit={async (e) => {
+          e.preventDefault();
+          await saveWindow();
+        }}
+      >
+        <label className="label flex flex-row space-x-2">
+          <span className="w-2/3">Drop messages older than (seconds)</span>
+          <input
+            className="input input-sm input-primary w-1/3"
+            type="number"
+            min="0"
+            value={maxAgeSecs}
+            onChange={(e) => setMaxAgeSecs(e.target.value)}
+          />
+        </label>
+        <label className="label flex flex-row space-x-2">
+          <span className="w-2/3">Flag clocks off by more than (seconds)</span>
+          <input
+            className="input input-sm input-primary w-1/3"
+            type="number"
+            min="0"
+            value={maxSkewSecs}
+            onChange={(e) => setMaxSkewSecs(e.target.value)}
+          />
+        </label>
+        <button type="submit" className="btn btn-sm">
+          Save
+        </button>
+      </form>
+    </Modal>
+  );
+};
+
+export default SettingsModal;
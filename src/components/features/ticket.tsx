@@ -1,23 +1,138 @@
 import { MdShare } from "react-icons/md";
 import { notify, notifyError } from "services/notifications";
-import { getLatestTicket } from "services/ipc";
+import {
+  createInvite,
+  getInvites,
+  getLatestTicket,
+  getModeration,
+  revokeInvite,
+} from "services/ipc";
 import { writeText } from "@tauri-apps/plugin-clipboard-manager";
+import Modal from "components/elements/modal";
+import { useEffect, useState } from "react";
+import { IssuedInvite } from "types";
+
+const TicketViewer: React.FC<{ topic?: string }> = ({ topic }) => {
+  const [openInvites, setOpenInvites] = useState<boolean>(false);
+  const [invites, setInvites] = useState<IssuedInvite[]>([]);
+  const [expiresInHours, setExpiresInHours] = useState<string>("");
+  const [maxUses, setMaxUses] = useState<string>("");
+  const [inviteOnly, setInviteOnly] = useState<boolean>(false);
+
+  useEffect(() => {
+    if (!topic || !openInvites) return;
+    getInvites(topic).then(setInvites);
+    getModeration(topic).then((moderation) =>
+      setInviteOnly(moderation.members !== null)
+    );
+  }, [topic, openInvites]);
+
+  const copyTicket = async () => {
+    const ticket = await getLatestTicket();
+    if (ticket === null) {
+      notifyError("No Room ID to copy.");
+      return;
+    }
+    await writeText(ticket.ticket);
+    notify(`🔗 Room ID copied to clipboard.`);
+  };
+
+  // Empty fields leave the invite unlimited, as are all invites to open rooms.
+  const copyInvite = async () => {
+    if (!topic) return;
+    const hours = parseFloat(expiresInHours);
+    const uses = parseInt(maxUses);
+    const ticket = await createInvite(
+      topic,
+      inviteOnly && hours > 0 ? Math.round(hours * 3600) : null,
+      inviteOnly && uses > 0 ? uses : null
+    );
+    if (ticket === null) return;
+    await writeText(ticket);
+    notify(`🔗 Invite copied to clipboard.`);
+    setInvites(await getInvites(topic));
+  };
+
+  const revoke = async (invite: IssuedInvite) => {
+    if (!topic) return;
+    await revokeInvite(topic, invite.id);
+    setInvites(await getInvites(topic));
+  };
 
-const TicketViewer: React.FC = () => {
   return (
     <div className="flex flex-row space-x-2 max-w-screen">
+      <Modal
+        isOpen={openInvites}
+        onClose={() => setOpenInvites(false)}
+        title="Invites"
+      >
+        <button className="btn btn-sm w-full mb-2" onClick={copyTicket}>
+          Copy room ticket
+        </button>
+        <form
+          className="flex flex-row space-x-2 p-1"
+          onSubmit={async (e) => {
+            e.preventDefault();
+            await copyInvite();
+          }}
+        >
+          <input
+            className="input input-sm input-primary w-1/3"
+            type="number"
+            min="0"
+            placeholder="Expires in hours"
+            disabled={!inviteOnly}
+            value={expiresInHours}
+            onChange={(e) => setExpiresInHours(e.target.value)}
+          />
+          <input
+            className="input input-sm input-primary w-1/3"
+            type="number"
+            min="1"
+            placeholder="Max uses"
+            disabled={!inviteOnly}
+            value={maxUses}
+            onChange={(e) => setMaxUses(e.target.value)}
+          />
+          <button type="submit" className="btn btn-sm grow">
+            Copy invite
+          </button>
+        </form>
+        {!inviteOnly && (
+          <p className="p-1 text-xs opacity-70">
+            Anyone with a ticket can join this room, so invites to it can't
+            expire or run out.
+          </p>
+        )}
+        <ul className="p-1 text-sm w-full">
+          {invites.map((invite) => (
+            <li
+              key={invite.id}
+              className="flex items-center flex-row border-b border-gray-400"
+            >
+              {invite.id.slice(0, 8)} - {invite.uses}
+              {invite.max_uses !== null && `/${invite.max_uses}`} used
+              {invite.expires_at !== null &&
+                `, expires ${new Date(
+                  invite.expires_at / 1000
+                ).toLocaleString()}`}
+              {invite.revoked ? (
+                <span className="ml-auto">(revoked)</span>
+              ) : (
+                <button
+                  className="btn btn-xs ml-auto"
+                  onClick={() => revoke(invite)}
+                >
+                  Revoke
+                </button>
+              )}
+            </li>
+          ))}
+        </ul>
+      </Modal>
       <button
         className="btn bg-blue-950 hover:bg-primary"
-        onClick={async () => {
-          const ticket = await getLatestTicket();
-          if (ticket === null) {
-            notifyError("No Room ID to copy.");
-            return;
-          }
-          const ticketString = ticket.ticket;
-          await writeText(ticketString);
-          notify(`🔗 Room ID copied to clipboard.`);
-        }}
+        onClick={() => (topic ? setOpenInvites(true) : copyTicket())}
       >
         Invite <MdShare />
       </button>
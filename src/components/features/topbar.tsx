@@ -1,4 +1,4 @@
-import { leaveRoom } from "services/ipc";
+import { getReceiptsEnabled, leaveRoom, setReceipts } from "services/ipc";
 import PeerInfoModal from "./peerList";
 import TicketViewer from "./ticket";
 import { PeerInfo } from "types";
@@ -6,27 +6,43 @@ import { CiLogout, CiMemoPad } from "react-icons/ci";
 import Button from "components/elements/button";
 import { ChatEvent } from "types/events";
 import EventLogModal from "./eventLog";
-import { useState } from "react";
+import { useEffect, useState } from "react";
+import { MdDoneAll, MdRemoveDone } from "react-icons/md";
 
 const TopBar: React.FC<{
+  topic?: string;
   eventLog: ChatEvent[];
   neighbours: PeerInfo[];
-}> = ({ eventLog, neighbours }) => {
+}> = ({ topic, eventLog, neighbours }) => {
   const [openLog, setOpenLog] = useState<boolean>(false);
+  const [receipts, setReceiptsEnabled] = useState<boolean>(false);
+
+  useEffect(() => {
+    if (topic) getReceiptsEnabled(topic).then(setReceiptsEnabled);
+  }, [topic]);
 
   return (
     <div className="w-screen flex justify-between bg-blue-950 py-1 px-1">
       <Button
         onClick={async () => {
-          await leaveRoom();
+          if (topic) await leaveRoom(topic);
           location.href = "/lobby";
         }}
       >
         <CiLogout />
       </Button>
       <div className="flex flex-row space-x-2">
-        <PeerInfoModal peers={neighbours} />
-        <TicketViewer />
+        <PeerInfoModal topic={topic} peers={neighbours} />
+        <TicketViewer topic={topic} />
+        <Button
+          onClick={async () => {
+            if (!topic) return;
+            await setReceipts(topic, !receipts);
+            setReceiptsEnabled(!receipts);
+          }}
+        >
+          {receipts ? <MdDoneAll /> : <MdRemoveDone />}
+        </Button>
       </div>
       <EventLogModal
         eventLog={eventLog}
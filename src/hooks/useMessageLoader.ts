@@ -1,18 +1,67 @@
 import { useState, useEffect, useCallback } from "react";
-import { MessageReceivedEvent } from "types/events";
-import { VisitedRoom } from "types";
-import { getMessages, messageToEvent } from "services/db";
+import {
+  MessageDeletedEvent,
+  MessageEditedEvent,
+  MessageReceivedEvent,
+  MessageReceipts,
+  ReactionCount,
+  ReactionSummaryEvent,
+  ReceiptsUpdatedEvent,
+} from "types/events";
+import { Message, VisitedRoom } from "types";
+import { messageToEvent } from "services/db";
+import { getMessages, getReactions, getReceipts } from "services/ipc";
 import { notifyError } from "services/notifications";
 
 const MESSAGES_PER_PAGE = 20;
 
+/** Group persisted reactions in a room by the message they are to. */
+async function loadReactions(
+  topic: string,
+  messageIds: string[]
+): Promise<Record<string, ReactionCount[]>> {
+  const reactions: Record<string, ReactionCount[]> = {};
+  for (const summary of await getReactions(topic, messageIds)) {
+    (reactions[summary.message_id] ??= []).push({
+      emoji: summary.emoji,
+      senders: summary.senders,
+    });
+  }
+  return reactions;
+}
+
+/** Persisted receipts in a room by the message they acknowledge. */
+async function loadReceipts(
+  topic: string,
+  messageIds: string[]
+): Promise<Record<string, MessageReceipts>> {
+  const receipts: Record<string, MessageReceipts> = {};
+  for (const summary of await getReceipts(topic, messageIds)) {
+    receipts[summary.message_id] = {
+      messageId: summary.message_id,
+      delivered: summary.delivered,
+      read: summary.read,
+    };
+  }
+  return receipts;
+}
+
 interface UseMessageLoaderProps {
   ticket: VisitedRoom | undefined;
 }
 
 export function useMessageLoader({ ticket }: UseMessageLoaderProps) {
   const [dbMessages, setDbMessages] = useState<MessageReceivedEvent[]>([]);
-  const [currentPage, setCurrentPage] = useState(1);
+  // Reactions to the loaded messages, by message id
+  const [reactions, setReactions] = useState<Record<string, ReactionCount[]>>(
+    {}
+  );
+  // Receipts for the loaded messages, by message id
+  const [receipts, setReceipts] = useState<Record<string, MessageReceipts>>(
+    {}
+  );
+  // The oldest persisted message loaded, the cursor for the next page
+  const [oldestLoaded, setOldestLoaded] = useState<Message | null>(null);
   const [isLoadingMore, setIsLoadingMore] = useState(false);
   const [hasMoreOldMessages, setHasMoreOldMessages] = useState(true);
 
@@ -25,21 +74,26 @@ export function useMessageLoader({ ticket }: UseMessageLoaderProps) {
 
     // Reset state for new ticket
     setDbMessages([]);
-    setCurrentPage(1);
+    setReactions({});
+    setReceipts({});
+    setOldestLoaded(null);
     setIsLoadingMore(false); // Will be set true by loadInitialMessages
     setHasMoreOldMessages(true);
 
     const loadInitialMessages = async () => {
       setIsLoadingMore(true);
       try {
-        const persisted = await getMessages(ticket.id, 1, MESSAGES_PER_PAGE);
+        const persisted = await getMessages(ticket.id, null, MESSAGES_PER_PAGE);
         if (persisted.length < MESSAGES_PER_PAGE) {
           setHasMoreOldMessages(false);
         }
         const historicalMessages: MessageReceivedEvent[] =
           persisted.map(messageToEvent);
         setDbMessages(historicalMessages);
-        // setCurrentPage is already 1
+        const ids = persisted.map((msg) => msg.id);
+        setReactions(await loadReactions(ticket.id, ids));
+        setReceipts(await loadReceipts(ticket.id, ids));
+        if (persisted.length > 0) setOldestLoaded(persisted[0]);
       } catch (error) {
         notifyError(
           `Error loading persisted messages: ${error}`,
@@ -59,10 +113,9 @@ export function useMessageLoader({ ticket }: UseMessageLoaderProps) {
 
     setIsLoadingMore(true);
     try {
-      const nextPageToFetch = currentPage + 1;
       const persisted = await getMessages(
         ticket.id,
-        nextPageToFetch,
+        oldestLoaded,
         MESSAGES_PER_PAGE
       );
 
@@ -73,7 +126,12 @@ export function useMessageLoader({ ticket }: UseMessageLoaderProps) {
           persisted.map(messageToEvent);
         // Prepend older messages
         setDbMessages((prevMsgs) => [...historicalMessages, ...prevMsgs]);
-        setCurrentPage(nextPageToFetch);
+        const ids = persisted.map((msg) => msg.id);
+        const olderReactions = await loadReactions(ticket.id, ids);
+        setReactions((prev) => ({ ...olderReactions, ...prev }));
+        const olderReceipts = await loadReceipts(ticket.id, ids);
+        setReceipts((prev) => ({ ...olderReceipts, ...prev }));
+        setOldestLoaded(persisted[0]);
         if (persisted.length < MESSAGES_PER_PAGE) setHasMoreOldMessages(false);
       }
     } catch (error) {
@@ -82,20 +140,74 @@ export function useMessageLoader({ ticket }: UseMessageLoaderProps) {
     } finally {
       setIsLoadingMore(false);
     }
-  }, [ticket, isLoadingMore, hasMoreOldMessages, currentPage]);
+  }, [ticket, isLoadingMore, hasMoreOldMessages, oldestLoaded]);
 
   const addLiveMessageToDisplay = useCallback(
     (liveMessage: MessageReceivedEvent) => {
-      setDbMessages((prevMsgs) => [...prevMsgs, liveMessage]);
+      setDbMessages((prevMsgs) =>
+        // Backfilled history may include messages we already have.
+        prevMsgs.some((msg) => msg.id === liveMessage.id)
+          ? prevMsgs
+          : [...prevMsgs, liveMessage]
+      );
+    },
+    []
+  );
+
+  // Edits and deletions only apply to messages sent by their author, the backend
+  // has already applied them to the persisted copy.
+  const applyAmendment = useCallback(
+    (amendment: MessageEditedEvent | MessageDeletedEvent) => {
+      setDbMessages((prevMsgs) =>
+        prevMsgs.map((msg) => {
+          if (msg.id !== amendment.targetId || msg.from !== amendment.from) {
+            return msg;
+          }
+          if (amendment.type === "messageDeleted") {
+            return { ...msg, text: "", attachment: undefined, deleted: true };
+          }
+          if (msg.deleted || (msg.editedAt ?? 0) >= amendment.sentTimestamp) {
+            return msg;
+          }
+          return {
+            ...msg,
+            text: amendment.newText,
+            editedAt: amendment.sentTimestamp,
+          };
+        })
+      );
     },
     []
   );
 
+  const applyReactionSummary = useCallback(
+    (summary: ReactionSummaryEvent) => {
+      setReactions((prev) => ({
+        ...prev,
+        [summary.targetId]: summary.reactions,
+      }));
+    },
+    []
+  );
+
+  const applyReceipts = useCallback((update: ReceiptsUpdatedEvent) => {
+    setReceipts((prev) => {
+      const next = { ...prev };
+      for (const receipt of update.receipts) next[receipt.messageId] = receipt;
+      return next;
+    });
+  }, []);
+
   return {
     dbMessages,
+    reactions,
+    receipts,
     loadMorePreviousMessages,
     isLoadingMore,
     hasMoreOldMessages,
     addLiveMessageToDisplay,
+    applyAmendment,
+    applyReactionSummary,
+    applyReceipts,
   };
 }
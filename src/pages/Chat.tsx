@@ -1,16 +1,12 @@
 import { useEffect, useState } from "react";
-import { ChatEvent } from "types/events";
+import { ChatEvent, RoomEvent } from "types/events";
 import { listen } from "@tauri-apps/api/event";
 import TopBar from "components/features/topbar";
 import Messages from "components/features/messages";
-import { notify, notifyError } from "services/notifications";
-import { PeerInfo, VisitedRoom } from "types";
-import { getLatestTicket } from "services/ipc";
-import {
-  addMessage,
-  eventToMessage,
-  ensureConversationExists,
-} from "services/db";
+import { notify } from "services/notifications";
+import { PeerInfo, RoomPeers, VisitedRoom } from "types";
+import { getLatestTicket, getNodeId } from "services/ipc";
+import { attachmentToEvent } from "services/db";
 import { useMessageLoader } from "hooks/useMessageLoader";
 
 export function ChatPage() {
@@ -23,6 +19,11 @@ export function ChatPage() {
     isLoadingMore,
     hasMoreOldMessages,
     addLiveMessageToDisplay,
+    applyAmendment,
+    reactions,
+    applyReactionSummary,
+    receipts,
+    applyReceipts,
   } = useMessageLoader({ ticket });
 
   useEffect(() => {
@@ -33,43 +34,80 @@ export function ChatPage() {
     });
   }, []);
 
-  useEffect(() => {
-    if (!ticket) return;
-    const setupConversation = async () => {
-      try {
-        await ensureConversationExists(ticket.id, ticket.name);
-      } catch (error) {
-        notifyError(`Failed to set up conversation: ${error}`, "convError");
-      }
-    };
-    setupConversation();
-  }, [ticket]);
-
   useEffect(() => {
     // If ticket is not yet available, don't set up listeners that depend on it.
     // The effect will re-run when ticket is set.
     if (!ticket) return;
-    const updatePeersRef = listen<PeerInfo[]>("peers-event", async (event) => {
+    const updatePeersRef = listen<RoomPeers>("peers-event", async (event) => {
+      if (event.payload.topic !== ticket.id) return;
       console.log(event.payload);
-      setNeighbours(event.payload);
-    });
-    const welcomePeersRef = listen<String>("peers-new", async (event) => {
-      notify(`👋 found ${event.payload}`, "newPeer", 1000);
+      setNeighbours(event.payload.peers);
     });
+    const welcomePeersRef = listen<RoomEvent<{ nickname: string }>>(
+      "peers-new",
+      async (event) => {
+        if (event.payload.topic !== ticket.id) return;
+        notify(`👋 found ${event.payload.nickname}`, "newPeer", 1000);
+      }
+    );
 
-    const eventsRef = listen<ChatEvent>("chat-event", async (event) => {
+    const eventsRef = listen<RoomEvent<ChatEvent>>("chat-event", async (event) => {
+      if (event.payload.topic !== ticket.id) return;
       console.log(event);
       setEventLog((prevLog) => [...prevLog, event.payload]);
+      // Messages are persisted by the backend before they are emitted.
       if (event.payload.type === "messageReceived") {
-        const liveMessage = event.payload;
-        addLiveMessageToDisplay(liveMessage); // Update messages via the hook
-        try {
-          // Add message to database for persistence
-          await addMessage(eventToMessage(liveMessage, ticket));
-        } catch (dbError) {
-          console.error("Failed to persist message:", dbError);
-          notifyError(`Error saving message: ${dbError}`, "saveMsgError");
+        addLiveMessageToDisplay(event.payload); // Update messages via the hook
+      } else if (event.payload.type === "attachmentReceived") {
+        addLiveMessageToDisplay(attachmentToEvent(event.payload));
+      } else if (
+        event.payload.type === "messageEdited" ||
+        event.payload.type === "messageDeleted"
+      ) {
+        applyAmendment(event.payload);
+      } else if (event.payload.type === "reactionSummary") {
+        applyReactionSummary(event.payload);
+      } else if (event.payload.type === "receiptsUpdated") {
+        applyReceipts(event.payload);
+      } else if (event.payload.type === "backfill") {
+        event.payload.messages.forEach((message) => {
+          if (message.type === "attachmentReceived") {
+            addLiveMessageToDisplay(attachmentToEvent(message));
+          } else if (message.type === "messageReceived") {
+            addLiveMessageToDisplay(message);
+          } else if (message.type !== "moderation") {
+            applyAmendment(message);
+          }
+        });
+      } else if (event.payload.type === "moderation") {
+        const { target, action } = event.payload;
+        // The backend has left the room already.
+        if (
+          (action === "kick" ||
+            action === "ban" ||
+            action === "removeMember") &&
+          target === (await getNodeId())
+        ) {
+          notify(
+            action === "kick"
+              ? "👢 you were kicked from the room"
+              : "🚫 you were removed from the room",
+            "removedFromRoom"
+          );
+          location.href = "/lobby";
         }
+      } else if (event.payload.type === "reconnecting") {
+        notify(
+          `🔌 connection lost, retrying in ${event.payload.delayMs / 1000}s`,
+          "reconnecting"
+        );
+      } else if (event.payload.type === "reconnected") {
+        notify("🔗 reconnected", "reconnecting", 1000);
+      } else if (event.payload.type === "unsupportedMessage") {
+        notify(
+          "⚠️ a peer sent a message this version can't show, update required",
+          "updateRequired"
+        );
       }
     });
     return () => {
@@ -82,7 +120,11 @@ export function ChatPage() {
   return (
     <div className="flex flex-col items-center h-screen w-screen space-y-2">
       <div className="w-full text-center pb-1 border-b border-blue-950 rounded-b-box">
-        <TopBar eventLog={eventLog} neighbours={neighbours} />
+        <TopBar
+          topic={ticket?.id}
+          eventLog={eventLog}
+          neighbours={neighbours}
+        />
         <h1 className="text-xl font-bold py-1">{ticket?.name}</h1>
       </div>
       <Messages
@@ -90,7 +132,13 @@ export function ChatPage() {
         onLoadMore={loadMorePreviousMessages}
         isLoadingMore={isLoadingMore}
         hasMoreOldMessages={hasMoreOldMessages}
+        onAmend={applyAmendment}
+        reactions={reactions}
+        receipts={receipts}
         peersOnline={neighbours.length > 0}
+        typingPeers={neighbours
+          .filter((peer) => peer.typing)
+          .map((peer) => peer.nickname)}
       />
     </div>
   );
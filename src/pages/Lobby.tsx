@@ -2,11 +2,12 @@ import Button from "components/elements/button";
 import AboutModal from "components/features/about";
 import Branding from "components/features/branding";
 import RoomsList from "components/features/roomsList";
+import SettingsModal from "components/features/settings";
 import UserEditModal from "components/features/userEdit";
 import Footer from "components/Layout/footer";
 import { useEffect, useState } from "react";
 import { FaUserEdit } from "react-icons/fa";
-import { FaCircleInfo } from "react-icons/fa6";
+import { FaCircleInfo, FaGear } from "react-icons/fa6";
 import { getNickname } from "services/ipc";
 
 export function LobbyPage() {
@@ -22,6 +23,7 @@ export function LobbyPage() {
 const TopBar: React.FC = () => {
   const [openAbout, setOpenAbout] = useState<boolean>(false);
   const [openUserEdit, setOpenUserEdit] = useState<boolean>(false);
+  const [openSettings, setOpenSettings] = useState<boolean>(false);
 
   useEffect(() => {
     getNickname()
@@ -44,14 +46,23 @@ const TopBar: React.FC = () => {
         isOpen={openUserEdit}
         onClose={() => setOpenUserEdit(false)}
       />
+      <SettingsModal
+        isOpen={openSettings}
+        onClose={() => setOpenSettings(false)}
+      />
       {/* Top bar buttons */}
       <Button onClick={() => setOpenUserEdit(true)}>
         <FaUserEdit />
       </Button>
       <Branding />
-      <Button onClick={() => setOpenAbout(true)}>
-        <FaCircleInfo />
-      </Button>
+      <div className="flex flex-row space-x-2">
+        <Button onClick={() => setOpenSettings(true)}>
+          <FaGear />
+        </Button>
+        <Button onClick={() => setOpenAbout(true)}>
+          <FaCircleInfo />
+        </Button>
+      </div>
     </div>
   );
 };
@@ -1,10 +1,11 @@
 // src/db.ts
 import Database from "@tauri-apps/plugin-sql";
-import { Conversation, Message, VisitedRoom } from "types";
-import { MessageReceivedEvent } from "types/events";
+import { Conversation, Message } from "types";
+import { AttachmentReceivedEvent, MessageReceivedEvent } from "types/events";
 
 // This will load the 'chat.db' database configured in the 'preload' section of tauri.conf.json
-// Migrations are handled by the Tauri backend.
+// Migrations are handled by the Tauri backend, which also writes every message:
+// load them with `getMessages` from services/ipc.
 let db: Database | null = null;
 
 export async function getDb() {
@@ -19,151 +20,36 @@ export async function getDb() {
   }
 }
 
-/** Function to ensure a conversation record exists in the database. */
-export async function ensureConversationExists(
-  conversationId: string,
-  conversationName?: string
-): Promise<void> {
-  if (!conversationId) {
-    console.error("ensureConversationExists called with no conversationId");
-    throw new Error("Conversation ID cannot be empty.");
-  }
-  const conn = await getDb();
-  try {
-    const result = await conn.select<Array<{ id: string }>>(
-      "SELECT id FROM conversations WHERE id = $1",
-      [conversationId]
-    );
-
-    if (result.length === 0) {
-      console.log(
-        `Conversation '${conversationId}' not found, creating with name: '${
-          conversationName || "Unnamed Chat"
-        }'...`
-      );
-      await conn.execute(
-        "INSERT INTO conversations (id, name) VALUES ($1, $2)", // last_message_at will be NULL by default
-        [
-          conversationId,
-          conversationName || `Chat ${conversationId.substring(0, 8)}`, // Default name
-        ]
-      );
-      console.log(`Conversation '${conversationId}' created.`);
-    } else {
-      console.log(`Conversation '${conversationId}' already exists.`);
-      // Potential future option: Update the name if it has changed (no mechanism for changing room names yet).
-      // const existing = await conn.select<Conversation[]>("SELECT * FROM conversations WHERE id = $1", [conversationId]);
-      // if (existing.length > 0 && conversationName && existing[0].name !== conversationName && existing[0].name !== `Chat ${conversationId.substring(0,8)}`) {
-      //   console.log(`Updating conversation '${conversationId}' name to '${conversationName}'`);
-      //   await conn.execute("UPDATE conversations SET name = $1 WHERE id = $2", [conversationName, conversationId]);
-      // }
-    }
-  } catch (error) {
-    console.error(
-      `Error in ensureConversationExists for ID ${conversationId}:`,
-      error
-    );
-    throw error; // Re-throw
-  }
-}
-
-export function eventToMessage(
-  eventPayload: MessageReceivedEvent,
-  ticket: VisitedRoom
-): Message {
-  if (!ticket || !ticket.id) {
-    console.error("buildMessage called with invalid roomTicket:", ticket);
-    throw new Error("Invalid room ticket provided to buildMessage.");
-  }
-  return {
-    id: globalThis.crypto.randomUUID(),
-    nickname: eventPayload.nickname,
-    conversation_id: ticket.id,
-    content: eventPayload.text,
-    created_at: eventPayload.sentTimestamp,
-    sender_id: eventPayload.from,
-  };
-}
-
 export function messageToEvent(message: Message): MessageReceivedEvent {
   return {
     type: "messageReceived",
+    id: message.id,
     from: message.sender_id,
     nickname: message.nickname,
-    text: message.content,
+    text: message.content ?? "",
     sentTimestamp: message.created_at,
+    clockSkewed: false,
+    attachment: message.attachment ?? undefined,
+    editedAt: message.edited_at ?? undefined,
+    deleted: message.deleted_at !== null,
+    replyTo: message.reply_to,
   };
 }
 
-/** Add message to the database for persistence. */
-export async function addMessage(message: Message) {
-  if (!message.conversation_id) {
-    console.error("addMessage called with no conversation_id:", message);
-    throw new Error("Message must have a conversation_id.");
-  }
-  console.log("addMessage: Attempting to begin transaction...");
-  const db = await getDb();
-  try {
-    await db.execute(
-      `
-        BEGIN;
-        INSERT INTO messages (id, conversation_id, sender_id, nickname, content, created_at) 
-        VALUES ($1, $2, $3, $4, $5, $6);
-        UPDATE conversations SET last_message_at = $7 WHERE id = $8;
-        COMMIT;
-      `,
-      [
-        // Parameters for the INSERT statement
-        message.id,
-        message.conversation_id,
-        message.sender_id,
-        message.nickname,
-        message.content,
-        message.created_at,
-        // Parameters for the UPDATE statement
-        message.created_at,
-        message.conversation_id,
-      ]
-    );
-    console.log("Message added and conversation updated successfully.");
-  } catch (error) {
-    await db.execute("ROLLBACK;");
-    console.error("Failed to add message:", error);
-    throw error; // Re-throw
-  }
-}
-
-/** Retrieves messages for a given conversation ID, sorted by creation time. */
-export async function getMessages(
-  conversationId: string,
-  page: number = 1,
-  limit: number = 50
-): Promise<Message[]> {
-  if (!conversationId) {
-    console.warn(
-      "getMessages called with no conversationId. Returning empty array."
-    );
-    return [];
-  }
-  const db = await getDb();
-  const offset = (page - 1) * limit;
-  try {
-    const messages = await db.select<Message[]>(
-      "SELECT * FROM messages WHERE conversation_id = $1 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
-      [conversationId, limit, offset]
-    );
-    console.log(
-      `Fetched ${messages.length} messages for conversation '${conversationId}'`
-    );
-    // Messages are fetched in reverse chronological order for pagination (latest first)
-    return messages.reverse();
-  } catch (error) {
-    console.error(
-      `Error fetching messages for conversation ${conversationId}:`,
-      error
-    );
-    throw error; // Re-throw
-  }
+/** Show a shared file in the message list alongside text messages. */
+export function attachmentToEvent(
+  event: AttachmentReceivedEvent
+): MessageReceivedEvent {
+  return {
+    type: "messageReceived",
+    id: event.id,
+    from: event.from,
+    nickname: event.nickname,
+    text: "",
+    sentTimestamp: event.sentTimestamp,
+    clockSkewed: event.clockSkewed,
+    attachment: event.attachment,
+  };
 }
 
 /** Retrieve all conversations as a map of id to last_message_at. */
@@ -1,15 +1,37 @@
 import { invoke } from "@tauri-apps/api/core";
 import { notifyError } from "./notifications";
-import { VisitedRoom } from "types";
+import {
+  AcceptanceWindow,
+  ApiConfig,
+  DirectConversation,
+  DirectMessage,
+  DownloadProgress,
+  IssuedInvite,
+  Message,
+  NetworkConfig,
+  ReactionSummary,
+  ReceiptSummary,
+  RoomModeration,
+  VisitedRoom,
+} from "types";
+import { ModerationAction } from "types/events";
 
 /** Create a new room and return the information required to send
- an out-of-band Join Code to others to connect. */
+ an out-of-band Join Code to others to connect. Invite-only rooms only
+ let in the members their admins add. */
 export async function createRoom(
   nickname: string,
-  name: string
+  name: string,
+  encrypted: boolean = true,
+  inviteOnly: boolean = false
 ): Promise<string> {
   try {
-    let ticket = await invoke<string>("create_room", { nickname, name });
+    let ticket = await invoke<string>("create_room", {
+      nickname,
+      name,
+      encrypted,
+      inviteOnly,
+    });
     return ticket;
   } catch (e) {
     notifyError(`Failed to create room: ${e}`, "RoomCreateError");
@@ -41,12 +63,316 @@ export async function getLatestTicket(): Promise<VisitedRoom | null> {
   }
 }
 
-/** Send a message to a room. */
-export async function sendMessage(message: string): Promise<void> {
+/** Send a message to a room, optionally as a reply to one of its messages, returning its message id. */
+export async function sendMessage(
+  topic: string,
+  message: string,
+  replyTo?: string
+): Promise<string | null> {
   try {
-    await invoke("send_message", { message });
+    return await invoke<string>("send_message", { topic, message, replyTo });
   } catch (e) {
     notifyError(`Failed to send message: ${e}`, "MessageSendError");
+    return null;
+  }
+}
+
+/** Replace the text of one of our own messages, returning the id of the edit. */
+export async function editMessage(
+  topic: string,
+  messageId: string,
+  text: string
+): Promise<string | null> {
+  try {
+    return await invoke<string>("edit_message", { topic, messageId, text });
+  } catch (e) {
+    notifyError(`Failed to edit message: ${e}`, "MessageEditError");
+    return null;
+  }
+}
+
+/** Delete one of our own messages, returning the id of the deletion. */
+export async function deleteMessage(
+  topic: string,
+  messageId: string
+): Promise<string | null> {
+  try {
+    return await invoke<string>("delete_message", { topic, messageId });
+  } catch (e) {
+    notifyError(`Failed to delete message: ${e}`, "MessageDeleteError");
+    return null;
+  }
+}
+
+/** Add or take back our emoji reaction to a message. */
+export async function react(
+  topic: string,
+  messageId: string,
+  emoji: string,
+  add: boolean
+): Promise<string | null> {
+  try {
+    return await invoke<string>("react", { topic, messageId, emoji, add });
+  } catch (e) {
+    notifyError(`Failed to react: ${e}`, "ReactionError");
+    return null;
+  }
+}
+
+/** Act on a member of a room we moderate. */
+export async function moderate(
+  topic: string,
+  nodeId: string,
+  action: ModerationAction
+): Promise<string | null> {
+  try {
+    return await invoke<string>("moderate", { topic, nodeId, action });
+  } catch (e) {
+    notifyError(`Failed to ${action}: ${e}`, "ModerationError");
+    return null;
+  }
+}
+
+/** Let a node into an invite-only room we moderate. */
+export async function addMember(
+  topic: string,
+  nodeId: string
+): Promise<string | null> {
+  try {
+    return await invoke<string>("add_member", { topic, nodeId });
+  } catch (e) {
+    notifyError(`Failed to add member: ${e}`, "ModerationError");
+    return null;
+  }
+}
+
+/** Remove a node from an invite-only room we moderate. */
+export async function removeMember(
+  topic: string,
+  nodeId: string
+): Promise<string | null> {
+  try {
+    return await invoke<string>("remove_member", { topic, nodeId });
+  } catch (e) {
+    notifyError(`Failed to remove member: ${e}`, "ModerationError");
+    return null;
+  }
+}
+
+/** Issue an invite to a room, returning the ticket that carries it. Only invites
+ to invite-only rooms can expire or run out. */
+export async function createInvite(
+  topic: string,
+  expiresInSecs: number | null,
+  maxUses: number | null
+): Promise<string | null> {
+  try {
+    return await invoke<string>("create_invite", {
+      topic,
+      expiresInSecs,
+      maxUses,
+    });
+  } catch (e) {
+    notifyError(`Failed to create invite: ${e}`, "InviteError");
+    return null;
+  }
+}
+
+/** Revoke an invite to a room, so members refuse anyone else using it. */
+export async function revokeInvite(
+  topic: string,
+  inviteId: string
+): Promise<string | null> {
+  try {
+    return await invoke<string>("revoke_invite", { topic, inviteId });
+  } catch (e) {
+    notifyError(`Failed to revoke invite: ${e}`, "InviteError");
+    return null;
+  }
+}
+
+/** Get the invites we issued to a room, newest first. */
+export async function getInvites(topic: string): Promise<IssuedInvite[]> {
+  return await invoke<IssuedInvite[]>("get_invites", { topic });
+}
+
+/** Get who moderates a room and who they banned or muted. */
+export async function getModeration(topic: string): Promise<RoomModeration> {
+  return await invoke<RoomModeration>("get_moderation", { topic });
+}
+
+/** Get the current reactions in a room to the given messages, grouped by emoji. */
+export async function getReactions(
+  topic: string,
+  messageIds: string[]
+): Promise<ReactionSummary[]> {
+  return await invoke<ReactionSummary[]>("get_reactions", {
+    topic,
+    messageIds,
+  });
+}
+
+/** Get up to `limit` persisted messages of a room that come before the `before`
+ message, or the latest if omitted. Oldest first. */
+export async function getMessages(
+  topic: string,
+  before: Pick<Message, "created_at" | "id"> | null,
+  limit: number
+): Promise<Message[]> {
+  return await invoke<Message[]>("get_messages", {
+    topic,
+    before: before?.created_at ?? null,
+    beforeId: before?.id ?? null,
+    limit,
+  });
+}
+
+/** Tell a room whether we are typing, throttled by the backend. */
+export async function setTyping(topic: string, active: boolean) {
+  try {
+    await invoke("set_typing", { topic, active });
+  } catch (e) {
+    console.error("Failed to send typing state:", e);
+  }
+}
+
+/** Turn sending delivery and read receipts in a room on or off. */
+export async function setReceipts(topic: string, enabled: boolean) {
+  try {
+    await invoke("set_receipts", { topic, enabled });
+  } catch (e) {
+    notifyError(`Failed to change receipts: ${e}`, "ReceiptsError");
+  }
+}
+
+/** Whether we send delivery and read receipts in a room. */
+export async function getReceiptsEnabled(topic: string): Promise<boolean> {
+  return await invoke<boolean>("get_receipts_enabled", { topic });
+}
+
+/** Tell a room we have read some of its messages, if receipts are enabled for it. */
+export async function markRead(topic: string, messageIds: string[]) {
+  try {
+    await invoke("mark_read", { topic, messageIds });
+  } catch (e) {
+    console.error("Failed to send read receipts:", e);
+  }
+}
+
+/** Get who acknowledged the given messages of a room. */
+export async function getReceipts(
+  topic: string,
+  messageIds: string[]
+): Promise<ReceiptSummary[]> {
+  return await invoke<ReceiptSummary[]>("get_receipts", { topic, messageIds });
+}
+
+/** Get a message of a room and all replies below it, oldest first. */
+export async function getThread(
+  topic: string,
+  messageId: string
+): Promise<Message[]> {
+  return await invoke<Message[]>("get_thread", { topic, messageId });
+}
+
+/** Share a file (e.g. one dropped onto the window) with a room, returning its message id. */
+export async function sendAttachment(
+  topic: string,
+  path: string
+): Promise<string | null> {
+  try {
+    return await invoke<string>("send_attachment", { topic, path });
+  } catch (e) {
+    notifyError(`Failed to share file: ${e}`, "AttachmentSendError");
+    return null;
+  }
+}
+
+/** Start fetching a message's attachment, progress arrives as `attachment-event`s. */
+export async function downloadAttachment(
+  messageId: string
+): Promise<string | null> {
+  try {
+    return await invoke<string>("download_attachment", { messageId });
+  } catch (e) {
+    notifyError(`Failed to download file: ${e}`, "AttachmentDownloadError");
+    return null;
+  }
+}
+
+/** Stop fetching an attachment. */
+export async function cancelDownload(hash: string): Promise<boolean> {
+  try {
+    return await invoke<boolean>("cancel_download", { hash });
+  } catch (e) {
+    notifyError(`Failed to cancel download: ${e}`, "AttachmentCancelError");
+    return false;
+  }
+}
+
+/** Return how much of a message's attachment has been downloaded. */
+export async function getDownloadProgress(
+  messageId: string
+): Promise<DownloadProgress | null> {
+  try {
+    return await invoke<DownloadProgress>("get_download_progress", {
+      messageId,
+    });
+  } catch (e) {
+    console.error("Failed to get download progress:", e);
+    return null;
+  }
+}
+
+/** Save a downloaded attachment, to the Downloads folder unless `dir` is given.
+ Returns the path it was saved to. */
+export async function saveAttachment(
+  messageId: string,
+  dir?: string
+): Promise<string | null> {
+  try {
+    return await invoke<string>("save_attachment", { messageId, dir });
+  } catch (e) {
+    notifyError(`Failed to save file: ${e}`, "AttachmentSaveError");
+    return null;
+  }
+}
+
+/** Send a private message straight to a peer, returning its message id. */
+export async function sendDirectMessage(
+  peer: string,
+  message: string
+): Promise<string | null> {
+  try {
+    return await invoke<string>("send_direct_message", { peer, message });
+  } catch (e) {
+    notifyError(`Failed to send direct message: ${e}`, "DirectSendError");
+    return null;
+  }
+}
+
+/** Get up to `limit` persisted direct messages exchanged with a peer that come
+ before the `before` message, or the latest if omitted. Oldest first. */
+export async function getDirectMessages(
+  peer: string,
+  before: Pick<DirectMessage, "created_at" | "id"> | null,
+  limit: number
+): Promise<DirectMessage[]> {
+  return await invoke<DirectMessage[]>("get_direct_messages", {
+    peer,
+    before: before?.created_at ?? null,
+    beforeId: before?.id ?? null,
+    limit,
+  });
+}
+
+/** Return the peers we have direct conversations with, most recently active first. */
+export async function listDirectConversations(): Promise<DirectConversation[]> {
+  try {
+    return await invoke<DirectConversation[]>("list_direct_conversations");
+  } catch (e) {
+    notifyError(`Failed to list direct conversations: ${e}`, "DirectListError");
+    return [];
   }
 }
 
@@ -69,10 +395,38 @@ export async function getNickname(): Promise<string | null> {
   }
 }
 
-/** Leave the currently joined room. */
-export async function leaveRoom(): Promise<void> {
+/** Choose how far message timestamps may drift from local time, in rooms
+ * joined from now on. */
+export async function setAcceptanceWindow(window: AcceptanceWindow) {
   try {
-    await invoke("leave_room");
+    await invoke("set_acceptance_window", { window });
+  } catch (e) {
+    notifyError(
+      `Failed to set acceptance window: ${e}`,
+      "AcceptanceWindowError"
+    );
+  }
+}
+
+/** Get how far message timestamps may drift from local time. */
+export async function getAcceptanceWindow(): Promise<AcceptanceWindow> {
+  return await invoke<AcceptanceWindow>("get_acceptance_window");
+}
+
+/** Return the rooms we are currently joined to. */
+export async function getActiveRooms(): Promise<VisitedRoom[]> {
+  try {
+    return await invoke<VisitedRoom[]>("get_active_rooms");
+  } catch (e) {
+    notifyError(`Failed to get active rooms: ${e}`, "RoomsGetError");
+    return [];
+  }
+}
+
+/** Leave a joined room. */
+export async function leaveRoom(topic: string): Promise<void> {
+  try {
+    await invoke("leave_room", { topic });
   } catch (e) {
     notifyError(`Failed to leave room: ${e}`, "RoomLeaveError");
   }
@@ -108,3 +462,63 @@ export async function deleteVisitedRoom(topic: string) {
     notifyError(`Failed to delete: ${e}`, "RoomsDeleteError");
   }
 }
+
+/** Choose how the node finds and reaches peers, from its next start on.
+ * Returns false if the config was rejected. */
+export async function setNetworkConfig(
+  config: NetworkConfig
+): Promise<boolean> {
+  try {
+    await invoke("set_network_config", { config });
+    return true;
+  } catch (e) {
+    notifyError(`Failed to set network config: ${e}`, "NetworkConfigError");
+    return false;
+  }
+}
+
+/** Get how the node finds and reaches peers. */
+export async function getNetworkConfig(): Promise<NetworkConfig> {
+  return await invoke<NetworkConfig>("get_network_config");
+}
+
+/** Restart the node with the stored network config, rejoining the current rooms. */
+export async function restartNode() {
+  try {
+    await invoke("restart_node");
+  } catch (e) {
+    notifyError(`Failed to restart node: ${e}`, "RestartNodeError");
+  }
+}
+
+/** Serve the local bot API, on `port` or the one it last used, returning
+ * where it listens and the token to authenticate with. */
+export async function enableLocalApi(
+  port?: number
+): Promise<ApiConfig | null> {
+  try {
+    return await invoke<ApiConfig>("enable_local_api", { port });
+  } catch (e) {
+    notifyError(`Failed to enable local API: ${e}`, "ApiEnableError");
+    return null;
+  }
+}
+
+/** Stop serving the local bot API. */
+export async function disableLocalApi() {
+  try {
+    await invoke("disable_local_api");
+  } catch (e) {
+    notifyError(`Failed to disable local API: ${e}`, "ApiDisableError");
+  }
+}
+
+/** Return where the local bot API is served, if it is running. */
+export async function getLocalApi(): Promise<ApiConfig | null> {
+  try {
+    return await invoke<ApiConfig | null>("get_local_api");
+  } catch (e) {
+    notifyError(`Failed to get local API: ${e}`, "ApiGetError");
+    return null;
+  }
+}
@@ -1,13 +1,27 @@
+import { Attachment } from "types";
+
 interface BaseEvent {
   type:
     | "joined"
     | "messageReceived"
+    | "attachmentReceived"
+    | "messageEdited"
+    | "messageDeleted"
+    | "reactionSummary"
+    | "typing"
+    | "receiptsUpdated"
+    | "left"
+    | "moderation"
     | "neighborUp"
     | "neighborDown"
     | "presence"
+    | "unsupportedMessage"
     | "lagged"
     | "errored"
-    | "disconnected";
+    | "disconnected"
+    | "reconnecting"
+    | "reconnected"
+    | "backfill";
 }
 
 /** We joined the topic with at least one peer.
@@ -21,10 +35,144 @@ export interface JoinedEvent extends BaseEvent {
 /** We received a gossip message for this topic. */
 export interface MessageReceivedEvent extends BaseEvent {
   type: "messageReceived";
+  /** Content-derived id of the signed message. */
+  id: string;
   from: string;
   text: string;
   nickname: string;
   sentTimestamp: number;
+  /** The sender's clock is skewed, so sentTimestamp may be wrong. */
+  clockSkewed: boolean;
+  /** Set when the message shares a file rather than text. */
+  attachment?: Attachment;
+  /** Send time of the author's latest edit, if they edited it. */
+  editedAt?: number;
+  /** The author deleted the message, text and attachment are then empty. */
+  deleted?: boolean;
+  /** The message this one answers, making it part of that message's thread. */
+  replyTo?: string | null;
+}
+
+/** A file shared in the room, fetched on demand with `downloadAttachment`. */
+export interface AttachmentReceivedEvent extends BaseEvent {
+  type: "attachmentReceived";
+  id: string;
+  from: string;
+  nickname: string;
+  attachment: Attachment;
+  sentTimestamp: number;
+  clockSkewed: boolean;
+}
+
+/** The author of `targetId` replaced its text. */
+export interface MessageEditedEvent extends BaseEvent {
+  type: "messageEdited";
+  id: string;
+  from: string;
+  targetId: string;
+  newText: string;
+  sentTimestamp: number;
+  clockSkewed: boolean;
+}
+
+/** The author of `targetId` deleted it. */
+export interface MessageDeletedEvent extends BaseEvent {
+  type: "messageDeleted";
+  id: string;
+  from: string;
+  targetId: string;
+  sentTimestamp: number;
+  clockSkewed: boolean;
+}
+
+/** Everyone currently reacting to a message with one emoji. */
+export interface ReactionCount {
+  emoji: string;
+  senders: string[];
+}
+
+/** The reactions to `targetId` changed, sent in place of individual reactions. */
+export interface ReactionSummaryEvent extends BaseEvent {
+  type: "reactionSummary";
+  targetId: string;
+  reactions: ReactionCount[];
+}
+
+/** A peer started or stopped typing, also reflected in `PeerInfo.typing`. */
+export interface TypingEvent extends BaseEvent {
+  type: "typing";
+  from: string;
+  active: boolean;
+  sentTimestamp: number;
+}
+
+/** The peers that acknowledged one message. Reading implies delivery. */
+export interface MessageReceipts {
+  messageId: string;
+  delivered: string[];
+  read: string[];
+}
+
+/** Who has acknowledged some messages now, sent in place of individual receipts. */
+export interface ReceiptsUpdatedEvent extends BaseEvent {
+  type: "receiptsUpdated";
+  receipts: MessageReceipts[];
+}
+
+/** A peer announced it is leaving, also reflected in `PeerInfo.offlineReason`. */
+export interface LeftEvent extends BaseEvent {
+  type: "left";
+  from: string;
+  sentTimestamp: number;
+}
+
+/** What a room admin did to a member. */
+export type ModerationAction =
+  | "grant"
+  | "revoke"
+  | "kick"
+  | "ban"
+  | "unban"
+  | "mute"
+  | "unmute"
+  | "addMember"
+  | "removeMember";
+
+/** A room admin acted on `target`, also reflected in the room's `PeerInfo`s. */
+export interface ModerationEvent extends BaseEvent {
+  type: "moderation";
+  id: string;
+  from: string;
+  target: string;
+  action: ModerationAction;
+  sentTimestamp: number;
+}
+
+/** What an invite lets its holders do, signed by the member that issued it. */
+export interface InviteTerms {
+  issuer: string;
+  /** When the invite stops letting newcomers in, in micros. */
+  expiresAt: number | null;
+  maxUses: number | null;
+}
+
+/** A newcomer joined the room with an invite that holds. */
+export interface InviteRedeemedEvent extends BaseEvent {
+  type: "inviteRedeemed";
+  id: string;
+  from: string;
+  inviteId: string;
+  invite: { terms: InviteTerms };
+  sentTimestamp: number;
+}
+
+/** A peer revoked an invite, so nobody else can join with it. */
+export interface InviteRevokedEvent extends BaseEvent {
+  type: "inviteRevoked";
+  id: string;
+  from: string;
+  inviteId: string;
+  sentTimestamp: number;
 }
 
 export interface PresenceEvent extends BaseEvent {
@@ -32,6 +180,16 @@ export interface PresenceEvent extends BaseEvent {
   from: string;
   nickname: string;
   sentTimestamp: number;
+  /** Wire protocol version the peer is running. */
+  version: number;
+}
+
+/** A peer running a newer build sent a message we can't decode, so we need to update. */
+export interface UnsupportedMessageEvent extends BaseEvent {
+  type: "unsupportedMessage";
+  from: string;
+  version: number;
+  sentTimestamp: number;
 }
 
 /** We have a new, direct neighbor in the swarm membership layer for this topic. */
@@ -56,19 +214,94 @@ export interface DisconnectedEvent extends BaseEvent {
   type: "disconnected";
 }
 
+/** Backend retrying to join the room after it disconnected.  Not part of the Gossip Events protocol. */
+export interface ReconnectingEvent extends BaseEvent {
+  type: "reconnecting";
+  attempt: number;
+  /** How long until this attempt is made. */
+  delayMs: number;
+}
+
+/** Backend rejoined the room after it disconnected.  Not part of the Gossip Events protocol. */
+export interface ReconnectedEvent extends BaseEvent {
+  type: "reconnected";
+}
+
 /** Backend reporting an error event.  Not part of the Gossip Events protocol. */
 export interface ErrorEvent extends BaseEvent {
   type: "errored";
   message: string;
 }
 
+/** Past messages served by a neighbor after we joined.  Not part of the Gossip Events protocol. */
+export interface BackfillEvent extends BaseEvent {
+  type: "backfill";
+  from: string;
+  messages: (
+    | MessageReceivedEvent
+    | AttachmentReceivedEvent
+    | MessageEditedEvent
+    | MessageDeletedEvent
+    | ModerationEvent
+    | InviteRedeemedEvent
+    | InviteRevokedEvent
+  )[];
+}
+
+/** Payload emitted by the backend, tagged with the topic id of its room. */
+export type RoomEvent<T> = T & { topic: string };
+
 /** Gossip Events */
 export type ChatEvent =
   | JoinedEvent
   | MessageReceivedEvent
+  | AttachmentReceivedEvent
+  | MessageEditedEvent
+  | MessageDeletedEvent
+  | ReactionSummaryEvent
+  | TypingEvent
+  | ReceiptsUpdatedEvent
+  | LeftEvent
+  | ModerationEvent
+  | InviteRedeemedEvent
+  | InviteRevokedEvent
   | NeighborUpEvent
   | NeighborDownEvent
   | PresenceEvent
+  | UnsupportedMessageEvent
   | LaggedEvent
   | DisconnectedEvent
-  | ErrorEvent;
+  | ReconnectingEvent
+  | ReconnectedEvent
+  | ErrorEvent
+  | BackfillEvent;
+
+/** A private message sent straight to us by a peer, emitted as `direct-event`. */
+export interface DirectMessageReceivedEvent {
+  type: "messageReceived";
+  id: string;
+  from: string;
+  text: string;
+  nickname: string;
+  sentTimestamp: number;
+}
+
+/** A peer running a newer build sent a direct message we can't decode. */
+export interface DirectUnsupportedMessageEvent {
+  type: "unsupportedMessage";
+  from: string;
+  version: number;
+  sentTimestamp: number;
+}
+
+/** Direct conversation events */
+export type DirectEvent =
+  | DirectMessageReceivedEvent
+  | DirectUnsupportedMessageEvent;
+
+/** Status of an attachment download, emitted as `attachment-event`. */
+export type DownloadEvent =
+  | { type: "progress"; hash: string; received: number; size: number }
+  | { type: "completed"; hash: string }
+  | { type: "failed"; hash: string; error: string }
+  | { type: "cancelled"; hash: string };
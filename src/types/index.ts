@@ -7,6 +7,7 @@ export type TicketOpts = {
   includeMyself: boolean;
   includeBootstrap: boolean;
   includeNeighbors: boolean;
+  includeAddrs: boolean;
 };
 
 export interface Conversation {
@@ -15,13 +16,69 @@ export interface Conversation {
   last_message_at?: number | null;
 }
 
+/** A file shared in a room, fetched on demand from peers that have it. */
+export interface Attachment {
+  /** Content hash, also used to track its download */
+  hash: string;
+  name: string;
+  size: number;
+  mime: string;
+}
+
 export interface Message {
   id: string;
   conversation_id: string;
-  content: string;
+  /** Null for attachments */
+  content: string | null;
   created_at: number;
   sender_id: string;
   nickname: string;
+  attachment: Attachment | null;
+  /** Send time of the author's latest edit */
+  edited_at: number | null;
+  /** Set when the author deleted the message, content and attachment are then null */
+  deleted_at: number | null;
+  /** The message this one answers, null unless it's part of a thread */
+  reply_to: string | null;
+}
+
+/** Everyone currently reacting to a message with one emoji. */
+export interface ReactionSummary {
+  message_id: string;
+  emoji: string;
+  senders: string[];
+}
+
+/** The peers that acknowledged a message. */
+export interface ReceiptSummary {
+  message_id: string;
+  delivered: string[];
+  read: string[];
+}
+
+/** How much of an attachment has been downloaded. */
+export interface DownloadProgress {
+  hash: string;
+  received: number;
+  size: number;
+  complete: boolean;
+}
+
+export interface DirectMessage {
+  id: string;
+  /** The other side of the conversation, whoever sent the message */
+  peer_id: string;
+  sender_id: string;
+  nickname: string | null;
+  content: string | null;
+  created_at: number;
+}
+
+export interface DirectConversation {
+  peer_id: string;
+  /** The nickname the peer last messaged us with, if they ever have */
+  nickname: string | null;
+  last_message_at: number;
 }
 
 export interface PeerInfo {
@@ -29,15 +86,93 @@ export interface PeerInfo {
   nickname: string;
   status: PeerStatus;
   lastSeen: number;
-  role: "Myself" | "RemoteNode";
+  role: "Myself" | "RemoteNode" | "Admin" | "Creator";
+  /** Wire protocol version from the peer's last presence, if known. */
+  version: number | null;
+  /** The peer told us it's typing, within the last few seconds. */
+  typing: boolean;
+  /** Why the peer went offline, if it did. */
+  offlineReason: OfflineReason | null;
+  /** A room admin muted the peer, so its chat messages are dropped. */
+  muted: boolean;
+}
+
+/** An invite we issued to a room, as stored in the database. */
+export interface IssuedInvite {
+  id: string;
+  conversation_id: string;
+  /** In micros, null if it never expires. */
+  expires_at: number | null;
+  max_uses: number | null;
+  created_at: number;
+  uses: number;
+  revoked: boolean;
+}
+
+/** Who moderates a room and who they banned or muted. */
+export interface RoomModeration {
+  /** Unset for rooms created before moderation, which nobody can moderate. */
+  creator: string | null;
+  admins: string[];
+  banned: string[];
+  muted: string[];
+  /** Who besides the creator is let in, null for rooms anyone with a ticket can join. */
+  members: string[] | null;
+}
+
+/** Payload of the `peers-event` emitted whenever a room's peer list changes. */
+export interface RoomPeers {
+  topic: string;
+  peers: PeerInfo[];
 }
 
 export type PeerStatus = "Online" | "Away" | "Offline";
 
+/** How far a signed message's timestamp may be from local time. */
+export interface AcceptanceWindow {
+  /** Messages older or newer than this are dropped as replays. */
+  maxAgeSecs: number;
+  /** Messages off by more than this are shown but flagged as skewed. */
+  maxSkewSecs: number;
+}
+
+/** `Left` if the peer said goodbye, `Disconnected` if the swarm lost it,
+ * `Kicked`, `Banned` or `Removed` (from an invite-only room) if a room admin did. */
+export type OfflineReason =
+  | "Left"
+  | "Disconnected"
+  | "Kicked"
+  | "Banned"
+  | "Removed";
+
 /** Helper function from backend */
 export type VisitedRoom = {
   id: string;
   name: string;
   ticket: string;
+  /** True if the ticket carries a secret for an end-to-end encrypted room. */
+  encrypted: boolean;
   last_message_at?: number | null; // updated from sql db
 };
+
+/** How the node finds and reaches peers. */
+export type NetworkMode = "public" | "lanOnly";
+
+/** Which relays and discovery services the node uses, n0's when unset. */
+export interface NetworkConfig {
+  mode: NetworkMode;
+  /** Relay URLs, ignored on the local network. */
+  relays: string[];
+  /** Pkarr relay URL to publish and resolve addresses with. */
+  pkarrRelay: string | null;
+  /** DNS origin to resolve addresses under. */
+  dnsOrigin: string | null;
+  /** Port to bind on, random if null. */
+  bindPort: number | null;
+}
+
+/** Where the local bot API is served on localhost, and the token clients authenticate with. */
+export interface ApiConfig {
+  port: number;
+  token: string;
+}